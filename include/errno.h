@@ -0,0 +1,49 @@
+// Error numbers of the system calls,
+// the same as those of the kernel in src/errno.rs.
+// A failed system call returns -1 and sets errno to one of them.
+
+#define EPERM         1   // operation not permitted
+#define ENOENT        2   // no such file or directory
+#define ESRCH         3   // no such process
+#define EINTR         4   // interrupted system call
+#define EIO           5   // input/output error
+#define ENXIO         6   // no such device or address
+#define E2BIG         7   // argument list too long
+#define ENOEXEC       8   // exec format error
+#define EBADF         9   // bad file descriptor
+#define ECHILD        10  // no child processes
+#define EAGAIN        11  // resource temporarily unavailable
+#define ENOMEM        12  // out of memory
+#define EACCES        13  // permission denied
+#define EFAULT        14  // bad address
+#define EBUSY         16  // device or resource busy
+#define EEXIST        17  // file exists
+#define EXDEV         18  // cross-device link
+#define ENODEV        19  // no such device
+#define ENOTDIR       20  // not a directory
+#define EISDIR        21  // is a directory
+#define EINVAL        22  // invalid argument
+#define ENFILE        23  // too many open files in system
+#define EMFILE        24  // too many open files
+#define ENOTTY        25  // inappropriate ioctl for device
+#define EFBIG         27  // file too large
+#define ENOSPC        28  // no space left on device
+#define EPIPE         32  // broken pipe
+#define ENAMETOOLONG  36  // file name too long
+#define ENOSYS        38  // function not implemented
+#define ENOTEMPTY     39  // directory not empty
+#define ELOOP         40  // too many levels of symbolic links
+#define ENOTSOCK      88  // socket operation on non-socket
+#define EAFNOSUPPORT  97  // address family not supported by protocol
+#define EADDRINUSE    98  // address already in use
+#define EISCONN      106  // transport endpoint is already connected
+#define ENOTCONN     107  // transport endpoint is not connected
+#define ETIMEDOUT    110  // connection timed out
+#define ECONNREFUSED 111  // connection refused
+
+// largest error number the kernel may return
+#define MAXERRNO      4095
+
+#ifndef __ASSEMBLER__
+extern int errno;
+#endif
@@ -25,7 +25,11 @@ struct inode {
   short major;
   short minor;
   short nlink;
+  ushort mode;
+  ushort uid;
+  ushort gid;
   uint size;
+  uint mtime;
   uint addrs[NDIRECT+1];
 };
 
@@ -37,4 +41,6 @@ struct devsw {
 
 extern struct devsw devsw[];
 
+#define FIFO    0  // no device, mknod makes a FIFO
 #define CONSOLE 1
+#define RTC     2
@@ -24,7 +24,7 @@ struct superblock {
 
 #define FSMAGIC 0x10203040
 
-#define NDIRECT 12
+#define NDIRECT 9
 #define NINDIRECT (BSIZE / sizeof(uint))
 #define MAXFILE (NDIRECT + NINDIRECT)
 
@@ -34,7 +34,12 @@ struct dinode {
   short major;          // Major device number (T_DEVICE only)
   short minor;          // Minor device number (T_DEVICE only)
   short nlink;          // Number of links to inode in file system
+  ushort mode;          // Permission bits, rwx for owner, group and others
+  ushort uid;           // Owner user id
+  ushort gid;           // Owner group id
+  ushort pad;
   uint size;            // Size of file (bytes)
+  uint mtime;           // Last modification time, seconds since the epoch
   uint addrs[NDIRECT+1];   // Data block addresses
 };
 
@@ -0,0 +1,4 @@
+// Operations of futex
+
+#define FUTEX_WAIT  0   // sleep if the word is still the value
+#define FUTEX_WAKE  1   // wake up at most the value of waiters
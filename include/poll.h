@@ -0,0 +1,13 @@
+// Events of poll
+
+#define POLLIN    0x01  // ready to read without blocking
+#define POLLOUT   0x04  // ready to write without blocking
+#define POLLERR   0x08  // the reading end of a pipe is closed
+#define POLLHUP   0x10  // the writing end of a pipe is closed
+#define POLLNVAL  0x20  // fd not opened
+
+struct pollfd {
+  int fd;         // ignored if negative
+  short events;   // events wanted
+  short revents;  // events ready
+};
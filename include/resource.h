@@ -0,0 +1,15 @@
+// Resource limits of getrlimit and setrlimit
+
+#define RLIMIT_CPU     0   // cpu time in ticks
+#define RLIMIT_FSIZE   1   // size of a file written
+#define RLIMIT_STACK   3   // size of the user stack, at most MAXSTACK
+#define RLIMIT_NPROC   6   // number of child processes
+#define RLIMIT_NOFILE  7   // number of open files
+#define RLIMIT_AS      9   // size of the address space
+
+#define RLIM_INFINITY  (~0UL)
+
+struct rlimit {
+  unsigned long rlim_cur;  // soft limit
+  unsigned long rlim_max;  // hard limit, can only be lowered
+};
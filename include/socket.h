@@ -0,0 +1,10 @@
+// Unix domain stream sockets
+
+#define AF_UNIX      1
+#define SOCK_STREAM  1
+#define SOMAXCONN    8   // maximum pending connections of a listening socket
+
+struct sockaddr_un {
+  ushort sun_family;    // AF_UNIX
+  char sun_path[128];   // nul-terminated path, at most MAXPATH
+};
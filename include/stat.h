@@ -1,6 +1,8 @@
 #define T_DIR     1   // Directory
 #define T_FILE    2   // File
 #define T_DEVICE  3   // Device
+#define T_FIFO    4   // Named pipe
+#define T_SOCKET  5   // Socket bound to the path
 
 struct stat {
   int dev;     // File system's disk device
@@ -8,4 +10,8 @@ struct stat {
   short type;  // Type of file
   short nlink; // Number of links to file
   uint64 size; // Size of file in bytes
+  ushort mode; // Permission bits
+  ushort uid;  // Owner user id
+  ushort gid;  // Owner group id
+  uint mtime;  // Last modification time, seconds since the epoch
 };
@@ -20,3 +20,42 @@
 #define SYS_link   19
 #define SYS_mkdir  20
 #define SYS_close  21
+#define SYS_mmap   22
+#define SYS_munmap 23
+#define SYS_clone  24
+#define SYS_join   25
+#define SYS_sigaction   26
+#define SYS_sigprocmask 27
+#define SYS_sigreturn   28
+#define SYS_setpriority 29
+#define SYS_sched_setaffinity 30
+#define SYS_trace  31
+#define SYS_waitpid 32
+#define SYS_setpgid 33
+#define SYS_getpgid 34
+#define SYS_setsid  35
+#define SYS_tcsetpgrp 36
+#define SYS_tcgetpgrp 37
+#define SYS_execve  38
+#define SYS_getrlimit 39
+#define SYS_setrlimit 40
+#define SYS_chmod   41
+#define SYS_chown   42
+#define SYS_setuid  43
+#define SYS_getuid  44
+#define SYS_sigalarm 45
+#define SYS_clock_gettime 46
+#define SYS_nanosleep 47
+#define SYS_gettimeofday 48
+#define SYS_futex   49
+#define SYS_fcntl   50
+#define SYS_poll    51
+#define SYS_mkfifo  52
+#define SYS_socket  53
+#define SYS_bind    54
+#define SYS_listen  55
+#define SYS_accept  56
+#define SYS_connect 57
+#define SYS_socketpair 58
+#define SYS_setgid  59
+#define SYS_getgid  60
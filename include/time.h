@@ -0,0 +1,16 @@
+// Clocks of clock_gettime, and time in seconds and nanoseconds or microseconds
+
+#define CLOCK_REALTIME  0   // wall-clock time since the epoch
+#define CLOCK_MONOTONIC 1   // time since boot
+
+#define NSEC_PER_SEC 1000000000L
+
+struct timespec {
+  long tv_sec;   // seconds
+  long tv_nsec;  // nanoseconds, less than NSEC_PER_SEC
+};
+
+struct timeval {
+  long tv_sec;   // seconds
+  long tv_usec;  // microseconds
+};
@@ -0,0 +1,11 @@
+// Options and status word of waitpid
+
+#define WNOHANG   0x1   // return at once if no child has changed
+#define WUNTRACED 0x2   // also report the stopped children
+
+#define WEXITSTATUS(s)  (((s) >> 8) & 0xff)
+#define WTERMSIG(s)     ((s) & 0x7f)
+#define WSTOPSIG(s)     WEXITSTATUS(s)
+#define WIFEXITED(s)    (WTERMSIG(s) == 0)
+#define WIFSTOPPED(s)   (((s) & 0xff) == 0x7f)
+#define WIFSIGNALED(s)  (WTERMSIG(s) != 0 && !WIFSTOPPED(s))
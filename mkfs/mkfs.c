@@ -4,6 +4,7 @@
 #include <string.h>
 #include <fcntl.h>
 #include <assert.h>
+#include <time.h>
 
 #define stat xv6_stat  // avoid clash with host struct stat
 #include "include/types.h"
@@ -38,7 +39,7 @@ void wsect(uint, void*);
 void winode(uint, struct dinode*);
 void rinode(uint inum, struct dinode *ip);
 void rsect(uint sec, void *buf);
-uint ialloc(ushort type);
+uint ialloc(ushort type, ushort mode);
 void iappend(uint inum, void *p, int n);
 
 // convert to intel byte order
@@ -69,6 +70,7 @@ main(int argc, char *argv[])
 {
   int i, cc, fd;
   uint rootino, inum, off;
+  ushort mode;
   struct dirent de;
   char buf[BSIZE];
   struct dinode din;
@@ -115,7 +117,7 @@ main(int argc, char *argv[])
   memmove(buf, &sb, sizeof(sb));
   wsect(1, buf);
 
-  rootino = ialloc(T_DIR);
+  rootino = ialloc(T_DIR, 0755);
   assert(rootino == ROOTINO);
 
   bzero(&de, sizeof(de));
@@ -147,10 +149,14 @@ main(int argc, char *argv[])
     // The binaries are named _rm, _cat, etc. to keep the
     // build operating system from trying to execute them
     // in place of system binaries like rm and cat.
-    if(shortname[0] == '_')
+    // Only they are executable.
+    mode = 0644;
+    if(shortname[0] == '_'){
       shortname += 1;
+      mode = 0755;
+    }
 
-    inum = ialloc(T_FILE);
+    inum = ialloc(T_FILE, mode);
 
     bzero(&de, sizeof(de));
     de.inum = xshort(inum);
@@ -229,7 +235,7 @@ rsect(uint sec, void *buf)
 }
 
 uint
-ialloc(ushort type)
+ialloc(ushort type, ushort mode)
 {
   uint inum = freeinode++;
   struct dinode din;
@@ -237,7 +243,11 @@ ialloc(ushort type)
   bzero(&din, sizeof(din));
   din.type = xshort(type);
   din.nlink = xshort(1);
+  din.mode = xshort(mode);
+  din.uid = xshort(0);
+  din.gid = xshort(0);
   din.size = xint(0);
+  din.mtime = xint(time(0));
   winode(inum, &din);
   return inum;
 }
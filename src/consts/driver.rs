@@ -7,15 +7,24 @@ pub const CONSOLE_BUF: usize = 128;
 /// buffer size for uart
 pub const UART_BUF: usize = 32;
 
+/// major of no device, with which mknod creates a FIFO instead
+pub const DEV_FIFO: usize = 0;
+
 /// constant device index of console
 pub const DEV_CONSOLE: usize = 1;
 
+/// constant device index of rtc
+pub const DEV_RTC: usize = 2;
+
 ////////////////////////////////////////////////
 ///////////    Control Characters   ////////////
 ////////////////////////////////////////////////
 
 // reference manual: https://man7.org/linux/man-pages/man4/console_codes.4.html
 
+/// interrupt, i.e., Ctrl-C, sends SIGINT to the foreground process group
+pub const CTRL_INTR: u8 = 0x03;
+
 /// end of transmit/file.line
 pub const CTRL_EOT: u8 = 0x04;
 
@@ -28,6 +37,9 @@ pub const CTRL_LF: u8 = 0x0A;
 /// carriage return
 pub const CTRL_CR: u8 = 0x0D;
 
+/// suspend, i.e., Ctrl-Z, sends SIGTSTP to the foreground process group
+pub const CTRL_SUSP: u8 = 0x1A;
+
 /// DEL
 pub const CTRL_DEL: u8 = 0x7f;
 
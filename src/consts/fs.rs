@@ -8,7 +8,7 @@ pub const BPB: u32 = (BSIZE * 8) as u32;
 
 /// number of inodes in inode cache
 pub const NINODE: usize = 50;
-pub const NDIRECT: usize = 12;
+pub const NDIRECT: usize = 9;
 /// number of indirect blocks in a single block
 /// note: the blockno should be u32
 pub const NINDIRECT: usize = BSIZE / core::mem::size_of::<u32>();
@@ -45,7 +45,46 @@ pub const O_WRONLY: i32 = 0x1;
 pub const O_RDWR: i32 = 0x2;
 pub const O_CREATE: i32 = 0x200;
 pub const O_TRUNC: i32 = 0x400;
+pub const O_NONBLOCK: i32 = 0x800;
+
+/// commands of fcntl
+pub const F_DUPFD: i32 = 0;
+pub const F_GETFD: i32 = 1;
+pub const F_SETFD: i32 = 2;
+pub const F_GETFL: i32 = 3;
+pub const F_SETFL: i32 = 4;
+/// file descriptor flag, closed when exec
+pub const FD_CLOEXEC: i32 = 1;
+
+/// events of poll
+pub const POLLIN: i16 = 0x1;
+pub const POLLOUT: i16 = 0x4;
+pub const POLLERR: i16 = 0x8;
+pub const POLLHUP: i16 = 0x10;
+pub const POLLNVAL: i16 = 0x20;
+
+/// for permission checks, the same as the bits for others in the mode
+pub const MAY_EXEC: u16 = 0o1;
+pub const MAY_WRITE: u16 = 0o2;
+pub const MAY_READ: u16 = 0o4;
+/// permission bits in the mode
+pub const MODE_MASK: u16 = 0o777;
+/// modes of the newly created inodes
+pub const FILE_MODE: u16 = 0o644;
+pub const DIR_MODE: u16 = 0o755;
+pub const DEVICE_MODE: u16 = 0o666;
+pub const SOCKET_MODE: u16 = 0o666;
 
 /// maximum data size of a pipe
 pub const PIPESIZE: usize = 454;
-pub const PIPESIZE_U32: u32 = 454;
\ No newline at end of file
+pub const PIPESIZE_U32: u32 = 454;
+/// maximum number of FIFOs opened at the same time
+pub const NFIFO: usize = 16;
+
+/// the only supported domain and type of sockets
+pub const AF_UNIX: i32 = 1;
+pub const SOCK_STREAM: i32 = 1;
+/// maximum number of sockets bound at the same time
+pub const NSOCKET: usize = 16;
+/// maximum number of pending connections of a listening socket
+pub const SOMAXCONN: usize = 8;
\ No newline at end of file
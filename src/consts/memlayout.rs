@@ -4,6 +4,7 @@
 //! based on qemu's hw/riscv/virt.c:
 //!
 //! 00001000 -- boot ROM, provided by qemu
+//! 00101000 -- goldfish rtc
 //! 02000000 -- CLINT
 //! 0C000000 -- PLIC
 //! 10000000 -- uart0
@@ -22,9 +23,14 @@ use super::*;
 /// local interrupt controller, which contains the timer.
 pub const CLINT: ConstAddr = ConstAddr(0x2000000);
 pub const CLINT_MAP_SIZE: usize = 0x10000;
+pub const CLINT_MSIP: ConstAddr = CLINT;
 pub const CLINT_MTIMECMP: ConstAddr = CLINT.const_add(0x4000);
 pub const CLINT_MTIME: ConstAddr = CLINT.const_add(0xbff8);
 
+/// goldfish real-time clock
+pub const RTC0: ConstAddr = ConstAddr(0x101000);
+pub const RTC0_MAP_SIZE: usize = PGSIZE;
+
 /// qemu puts UART registers here in physical memory.
 pub const UART0: ConstAddr = ConstAddr(0x10000000);
 pub const UART0_MAP_SIZE: usize = PGSIZE;
@@ -44,6 +50,8 @@ pub const PLIC_MAP_SIZE: usize = 0x400000;
 /// from physical address 0x80000000 to PHYSTOP.
 pub const KERNBASE: ConstAddr = ConstAddr(0x80000000);
 pub const PHYSTOP: ConstAddr = KERNBASE.const_add(128 * 1024 * 1024);
+/// number of physical pages between KERNBASE and PHYSTOP
+pub const NPHYSPAGE: usize = (PHYSTOP.0 - KERNBASE.0) / PGSIZE;
 
 /// map the trampoline page to the highest address,
 /// in both user and kernel space.
@@ -54,5 +62,10 @@ pub const TRAMPOLINE: ConstAddr = MAXVA.const_sub(PGSIZE);
 /// 0x3FFFFFE000
 pub const TRAPFRAME: ConstAddr = TRAMPOLINE.const_sub(PGSIZE);
 
+/// trapframes of threads are below the trapframe,
+/// one page for each slot in the process table,
+/// and memory mapped areas are allocated downwards from their bottom
+pub const MMAPTOP: ConstAddr = TRAPFRAME.const_sub(NPROC * PGSIZE);
+
 /// user text/code start address
 pub const USERTEXT: ConstAddr = ConstAddr(0);
@@ -7,6 +7,7 @@ pub use riscv::*;
 
 pub mod fs;
 pub mod driver;
+pub mod signal;
 
 mod memlayout;
 mod param;
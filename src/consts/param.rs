@@ -17,10 +17,72 @@ pub const PGMASKLEN: usize = 9;
 /// for syscall
 /// maximum length of a file system path
 pub const MAXPATH: usize = 128;
-/// maximum number of command line arguments
-pub const MAXARG: usize = 16;
-/// maximum length of a single command line argument
-pub const MAXARGLEN: usize = 64;
+/// maximum total bytes of the exec arguments and environment,
+/// including the pointers to them
+pub const ARG_MAX: usize = PGSIZE;
+/// maximum nesting of scripts run by their `#!` interpreters
+pub const MAXINTERP: usize = 4;
+/// maximum size of the user stack, which grows on demand up to RLIMIT_STACK
+pub const MAXSTACK: usize = 64 * PGSIZE;
+/// size of the unmapped gap below the user stack, to catch stack overflow
+pub const STACKGAP: usize = PGSIZE;
+
+/// for mmap
+/// maximum number of memory mapped areas per process
+pub const NVMA: usize = 16;
+pub const PROT_NONE: i32 = 0x0;
+pub const PROT_READ: i32 = 0x1;
+pub const PROT_WRITE: i32 = 0x2;
+pub const PROT_EXEC: i32 = 0x4;
+pub const MAP_SHARED: i32 = 0x01;
+pub const MAP_PRIVATE: i32 = 0x02;
+pub const MAP_ANONYMOUS: i32 = 0x20;
+
+/// for waitpid
+/// return at once if no child has changed
+pub const WNOHANG: i32 = 0x1;
+/// also report the stopped children
+pub const WUNTRACED: i32 = 0x2;
+
+/// for rlimits, numbered as in Linux
+/// cpu time in ticks
+pub const RLIMIT_CPU: usize = 0;
+/// size of a file written by the process
+pub const RLIMIT_FSIZE: usize = 1;
+/// size of the user stack, no more than [`MAXSTACK`]
+pub const RLIMIT_STACK: usize = 3;
+/// number of child processes
+pub const RLIMIT_NPROC: usize = 6;
+/// number of open files
+pub const RLIMIT_NOFILE: usize = 7;
+/// size of the user address space
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_NLIMITS: usize = 10;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// for scheduler
+/// number of the priority queue levels, level 0 is the highest
+pub const NQUEUE: usize = 4;
+/// ticks a process can run at each level before it is demoted
+pub const TIME_SLICE: [usize; NQUEUE] = [1, 2, 4, 8];
+/// ticks between two priority boosts
+pub const BOOST_INTERVAL: usize = 100;
+/// range of the nice value, lower value for higher priority
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
+
+/// operations of futex
+pub const FUTEX_WAIT: i32 = 0;
+pub const FUTEX_WAKE: i32 = 1;
+
+/// for timer
+/// frequency of the CLINT mtime, 10MHz in qemu
+pub const MTIME_FREQ: u64 = 10_000_000;
+/// cycles of mtime between two ticks, about 1/10th second in qemu
+pub const TIMER_INTERVAL: u64 = 1_000_000;
+/// clocks of clock_gettime
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
 
 /// The smallest block size of the buddy system
 pub const LEAF_SIZE: usize = 16;
\ No newline at end of file
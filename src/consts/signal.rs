@@ -0,0 +1,41 @@
+/// number of signals, signal numbers range from 1 to NSIG-1
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGXCPU: usize = 24;
+pub const SIGXFSZ: usize = 25;
+
+/// default action of the signal
+pub const SIG_DFL: usize = 0;
+/// ignore the signal
+pub const SIG_IGN: usize = 1;
+
+/// do not block the signal itself in its handler
+pub const SA_NODEFER: u32 = 0x40000000;
+/// reset to the default action once the handler is called
+pub const SA_RESETHAND: u32 = 0x80000000;
+
+/// how to change the blocked mask in sigprocmask
+pub const SIG_BLOCK: i32 = 0;
+pub const SIG_UNBLOCK: i32 = 1;
+pub const SIG_SETMASK: i32 = 2;
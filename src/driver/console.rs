@@ -1,9 +1,12 @@
 //! Console driver for user input and output.
 
 use core::num::Wrapping;
-use core::sync::atomic::Ordering;
 
 use crate::consts::driver::*;
+use crate::consts::signal::{SIGINT, SIGTSTP};
+use crate::consts::fs::{POLLIN, POLLOUT};
+use crate::errno::Errno;
+use crate::fs::poll_notify;
 use crate::spinlock::SpinLock;
 use crate::mm::Address;
 use crate::process::{CPU_MANAGER, PROC_MANAGER};
@@ -17,7 +20,9 @@ pub unsafe fn init() {
 
 /// Read from console `tot` bytes to `dst`,
 /// which might be a virtual or kernel [`Address`].
-pub(super) fn read(mut dst: Address, tot: u32) -> Result<u32, ()> {
+/// If `nonblock` or interrupted by a signal, return what has been read
+/// instead of waiting for more input, or EAGAIN/EINTR if nothing.
+pub(super) fn read(mut dst: Address, tot: u32, nonblock: bool) -> Result<u32, Errno> {
     let mut console = CONSOLE.lock();
 
     let mut left = tot;
@@ -25,11 +30,14 @@ pub(super) fn read(mut dst: Address, tot: u32) -> Result<u32, ()> {
         // if no available data in console buf
         // wait until the console device write some data
         while console.ri == console.wi {
+            if nonblock {
+                return if left < tot { Ok(tot - left) } else { Err(Errno::EAGAIN) }
+            }
             let p = unsafe { CPU_MANAGER.my_proc() };
-            if p.killed.load(Ordering::Relaxed) {
-                return Err(())
+            if p.interrupted() {
+                return if left < tot { Ok(tot - left) } else { Err(Errno::EINTR) }
             }
-            p.sleep(&console.ri as *const Wrapping<_> as usize, console);
+            p.sleep_interruptible(&console.ri as *const Wrapping<_> as usize, console);
             console = CONSOLE.lock();
         }
 
@@ -66,7 +74,7 @@ pub(super) fn read(mut dst: Address, tot: u32) -> Result<u32, ()> {
 
 /// Write to console `tot` bytes from `src`,
 /// which might be a virtual or kernel [`Address`].
-pub(super) fn write(mut src: Address, tot: u32) -> Result<u32, ()> {
+pub(super) fn write(mut src: Address, tot: u32) -> Result<u32, Errno> {
     for i in 0..tot {
         let mut c = 0u8;
         if src.copy_in(&mut c as *mut u8, 1).is_err() {
@@ -78,6 +86,48 @@ pub(super) fn write(mut src: Address, tot: u32) -> Result<u32, ()> {
     Ok(tot)
 }
 
+/// Readable if a line is available, and always writable.
+pub(super) fn poll() -> i16 {
+    let console = CONSOLE.lock();
+    if console.ri != console.wi {
+        POLLIN | POLLOUT
+    } else {
+        POLLOUT
+    }
+}
+
+/// The foreground process group of the console, zero if none.
+pub fn fg_pgrp() -> usize {
+    CONSOLE.lock().fg_pgrp
+}
+
+/// Set the foreground process group of the console on behalf of the session `sid`,
+/// which receives the signals from the control characters.
+/// If no session controls the console, it becomes the controlling terminal
+/// of the session, but only for the session `leader`.
+/// Return [`Errno::ENOTTY`] if the console is controlled by another session.
+pub fn set_fg_pgrp(sid: usize, leader: bool, pgid: usize) -> Result<(), Errno> {
+    let mut console = CONSOLE.lock();
+    if console.sid == 0 && leader {
+        console.sid = sid;
+    }
+    if console.sid != sid {
+        return Err(Errno::ENOTTY)
+    }
+    console.fg_pgrp = pgid;
+    Ok(())
+}
+
+/// Detach the console from the session `sid` if it controls the console,
+/// typically when the session leader exits.
+pub fn release_session(sid: usize) {
+    let mut console = CONSOLE.lock();
+    if console.sid == sid {
+        console.sid = 0;
+        console.fg_pgrp = 0;
+    }
+}
+
 /// Put a single character to console.
 pub(crate) fn putc(c: u8) {
     if c == CTRL_BS {
@@ -116,6 +166,17 @@ pub(super) fn intr(c: u8) {
                 putc(CTRL_BS);
             }
         }
+        CTRL_INTR | CTRL_SUSP => {
+            // discard the line being edited
+            console.ei = console.wi;
+            putc(b'^');
+            putc(c + b'@');
+            putc(CTRL_LF);
+            let sig = if c == CTRL_INTR { SIGINT } else { SIGTSTP };
+            if console.fg_pgrp != 0 {
+                let _ = unsafe { PROC_MANAGER.kill_pgrp(console.fg_pgrp, sig) };
+            }
+        },
         _ => {
             // echo back
             if c != 0 && (console.ei - console.ri).0 < CONSOLE_BUF {
@@ -127,6 +188,7 @@ pub(super) fn intr(c: u8) {
                 if c == CTRL_LF || c == CTRL_EOT || (console.ei - console.ri).0 == CONSOLE_BUF {
                     console.wi = console.ei;
                     unsafe { PROC_MANAGER.wakeup(&console.ri as *const Wrapping<_> as usize); }
+                    poll_notify(super::poll_key(DEV_CONSOLE));
                 }
             }
         },
@@ -139,6 +201,8 @@ static CONSOLE: SpinLock<Console> = SpinLock::new(
         ri: Wrapping(0),
         wi: Wrapping(0),
         ei: Wrapping(0),
+        sid: 0,
+        fg_pgrp: 0,
     },
     "console",
 );
@@ -151,4 +215,8 @@ struct Console {
     wi: Wrapping<usize>,
     // edit index
     ei: Wrapping<usize>,
+    // controlling session, zero if none
+    sid: usize,
+    // foreground process group
+    fg_pgrp: usize,
 }
@@ -1,18 +1,19 @@
 use core::sync::atomic::AtomicBool;
 
-use crate::{consts::driver::NDEV, mm::Address};
+use crate::{consts::driver::NDEV, errno::Errno, mm::Address};
 
 pub mod virtio_disk;
 pub mod console;
 pub mod uart;
+pub mod rtc;
 
 /// Used to signal whether any of the harts panic.
 pub(crate) static PANICKED: AtomicBool = AtomicBool::new(false);
 
 pub static DEVICES: [Option<Device>; NDEV] = [
     /* 0 */   None,
-    /* 1 */   Some(Device { read: console::read, write: console::write }),
-    /* 2 */   None,
+    /* 1 */   Some(Device { read: console::read, write: console::write, poll: console::poll }),
+    /* 2 */   Some(Device { read: rtc::read, write: rtc::write, poll: rtc::poll }),
     /* 3 */   None,
     /* 4 */   None,
     /* 5 */   None,
@@ -23,8 +24,16 @@ pub static DEVICES: [Option<Device>; NDEV] = [
 ];
 
 pub struct Device {
-    /// function: read from [`Address`] count bytes.
-    pub read: fn(Address, u32) -> Result<u32, ()>,
+    /// function: read from [`Address`] count bytes,
+    /// return EAGAIN instead of blocking if non-blocking.
+    pub read: fn(Address, u32, bool) -> Result<u32, Errno>,
     /// function: write to [`Address`] count bytes.
-    pub write: fn(Address, u32) -> Result<u32, ()>,
+    pub write: fn(Address, u32) -> Result<u32, Errno>,
+    /// function: return the events ready, e.g., POLLIN and POLLOUT.
+    pub poll: fn() -> i16,
+}
+
+/// Key to poll the device, notified by its driver when it may become ready.
+pub fn poll_key(major: usize) -> usize {
+    &DEVICES[major] as *const Option<Device> as usize
 }
@@ -0,0 +1,56 @@
+//! Goldfish RTC driver for the wall-clock time,
+//! refer to goldfish-rtc.c in qemu's hw/rtc for detail.
+
+use core::convert::Into;
+use core::mem;
+use core::ptr;
+
+use crate::consts::{RTC0, fs::{POLLIN, POLLOUT}};
+use crate::errno::Errno;
+use crate::mm::Address;
+use crate::timer::NSEC_PER_SEC;
+
+/// nanoseconds since the epoch, reading the low half latches the high half
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+#[inline]
+unsafe fn read_reg(reg: usize) -> u32 {
+    ptr::read_volatile((Into::<usize>::into(RTC0) + reg) as *const u32)
+}
+
+/// Read the nanoseconds since the epoch.
+pub fn read_ns() -> u64 {
+    unsafe {
+        let low = read_reg(TIME_LOW);
+        let high = read_reg(TIME_HIGH);
+        ((high as u64) << 32) | low as u64
+    }
+}
+
+/// Read the seconds since the epoch, as kept in the inodes.
+pub fn read_secs() -> u32 {
+    (read_ns() / NSEC_PER_SEC) as u32
+}
+
+/// Read the current time as a u64 of nanoseconds since the epoch,
+/// `tot` should be large enough to hold it. It never blocks.
+pub(super) fn read(dst: Address, tot: u32, _nonblock: bool) -> Result<u32, Errno> {
+    let ns = read_ns();
+    let size = mem::size_of::<u64>();
+    if (tot as usize) < size {
+        return Err(Errno::EINVAL)
+    }
+    dst.copy_out(&ns as *const u64 as *const u8, size)?;
+    Ok(size as u32)
+}
+
+/// The time is kept by the host and cannot be set.
+pub(super) fn write(_src: Address, _tot: u32) -> Result<u32, Errno> {
+    Err(Errno::EPERM)
+}
+
+/// Always ready, since neither read nor write blocks.
+pub(super) fn poll() -> i16 {
+    POLLIN | POLLOUT
+}
@@ -0,0 +1,145 @@
+//! Error numbers of the system calls
+
+use core::fmt;
+
+/// Reason of a failed system call,
+/// returned to the user space as the negated number.
+/// The numbers should be the same as those in include/errno.h.
+#[repr(isize)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Errno {
+    /// operation not permitted
+    EPERM = 1,
+    /// no such file or directory
+    ENOENT = 2,
+    /// no such process
+    ESRCH = 3,
+    /// interrupted system call
+    EINTR = 4,
+    /// input/output error
+    EIO = 5,
+    /// no such device or address
+    ENXIO = 6,
+    /// argument list too long
+    E2BIG = 7,
+    /// exec format error
+    ENOEXEC = 8,
+    /// bad file descriptor
+    EBADF = 9,
+    /// no child processes
+    ECHILD = 10,
+    /// resource temporarily unavailable
+    EAGAIN = 11,
+    /// out of memory
+    ENOMEM = 12,
+    /// permission denied
+    EACCES = 13,
+    /// bad address
+    EFAULT = 14,
+    /// device or resource busy
+    EBUSY = 16,
+    /// file exists
+    EEXIST = 17,
+    /// cross-device link
+    EXDEV = 18,
+    /// no such device
+    ENODEV = 19,
+    /// not a directory
+    ENOTDIR = 20,
+    /// is a directory
+    EISDIR = 21,
+    /// invalid argument
+    EINVAL = 22,
+    /// too many open files in system
+    ENFILE = 23,
+    /// too many open files
+    EMFILE = 24,
+    /// inappropriate ioctl for device
+    ENOTTY = 25,
+    /// file too large
+    EFBIG = 27,
+    /// no space left on device
+    ENOSPC = 28,
+    /// broken pipe
+    EPIPE = 32,
+    /// file name too long
+    ENAMETOOLONG = 36,
+    /// function not implemented
+    ENOSYS = 38,
+    /// directory not empty
+    ENOTEMPTY = 39,
+    /// too many levels of symbolic links, or nested interpreters
+    ELOOP = 40,
+    /// socket operation on non-socket
+    ENOTSOCK = 88,
+    /// address family not supported by protocol
+    EAFNOSUPPORT = 97,
+    /// address already in use
+    EADDRINUSE = 98,
+    /// transport endpoint is already connected
+    EISCONN = 106,
+    /// transport endpoint is not connected
+    ENOTCONN = 107,
+    /// connection timed out
+    ETIMEDOUT = 110,
+    /// connection refused
+    ECONNREFUSED = 111,
+}
+
+impl Errno {
+    /// The value returned to the user space in a0.
+    #[inline]
+    pub fn as_ret(self) -> usize {
+        -(self as isize) as usize
+    }
+
+    /// Human-readable description of the error.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::EPERM => "operation not permitted",
+            Self::ENOENT => "no such file or directory",
+            Self::ESRCH => "no such process",
+            Self::EINTR => "interrupted system call",
+            Self::EIO => "input/output error",
+            Self::ENXIO => "no such device or address",
+            Self::E2BIG => "argument list too long",
+            Self::ENOEXEC => "exec format error",
+            Self::EBADF => "bad file descriptor",
+            Self::ECHILD => "no child processes",
+            Self::EAGAIN => "resource temporarily unavailable",
+            Self::ENOMEM => "out of memory",
+            Self::EACCES => "permission denied",
+            Self::EFAULT => "bad address",
+            Self::EBUSY => "device or resource busy",
+            Self::EEXIST => "file exists",
+            Self::EXDEV => "cross-device link",
+            Self::ENODEV => "no such device",
+            Self::ENOTDIR => "not a directory",
+            Self::EISDIR => "is a directory",
+            Self::EINVAL => "invalid argument",
+            Self::ENFILE => "too many open files in system",
+            Self::EMFILE => "too many open files",
+            Self::ENOTTY => "inappropriate ioctl for device",
+            Self::EFBIG => "file too large",
+            Self::ENOSPC => "no space left on device",
+            Self::EPIPE => "broken pipe",
+            Self::ENAMETOOLONG => "file name too long",
+            Self::ENOSYS => "function not implemented",
+            Self::ENOTEMPTY => "directory not empty",
+            Self::ELOOP => "too many levels of symbolic links",
+            Self::ENOTSOCK => "socket operation on non-socket",
+            Self::EAFNOSUPPORT => "address family not supported by protocol",
+            Self::EADDRINUSE => "address already in use",
+            Self::EISCONN => "transport endpoint is already connected",
+            Self::ENOTCONN => "transport endpoint is not connected",
+            Self::ETIMEDOUT => "connection timed out",
+            Self::ECONNREFUSED => "connection refused",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}({})", self, self.as_str())
+    }
+}
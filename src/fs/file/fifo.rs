@@ -0,0 +1,127 @@
+//! Named pipes, i.e., FIFOs in the file system
+//!
+//! The opened FIFOs are kept in a table keyed by the device and inode number,
+//! so that the files opening the same FIFO share one [`Pipe`].
+
+use array_macro::array;
+
+use alloc::sync::Arc;
+use core::num::Wrapping;
+
+use crate::consts::fs::NFIFO;
+use crate::errno::Errno;
+use crate::process::{CPU_MANAGER, PROC_MANAGER};
+use crate::spinlock::SpinLock;
+
+use super::Pipe;
+
+struct Fifo {
+    dev: u32,
+    inum: u32,
+    pipe: Arc<Pipe>,
+    /// number of the files opened for reading and writing
+    readers: usize,
+    writers: usize,
+    /// times it is opened for reading and writing,
+    /// so that an opener waiting for its peer notices one that has come and gone
+    read_opens: Wrapping<usize>,
+    write_opens: Wrapping<usize>,
+}
+
+/// The opened FIFOs, the address of each entry is the channel its openers sleep on.
+static FIFOS: SpinLock<[Option<Fifo>; NFIFO]> = SpinLock::new(array![_ => None; NFIFO], "fifos");
+
+/// Open the FIFO at inode `inum` of device `dev`, return the shared [`Pipe`].
+/// Opening only for reading or only for writing waits until the other end is opened.
+/// If `nonblock`, it does not wait, but opening only for writing fails with ENXIO if there is no reader.
+pub(super) fn fifo_open(dev: u32, inum: u32, readable: bool, writable: bool, nonblock: bool)
+    -> Result<Arc<Pipe>, Errno>
+{
+    let mut fifos = FIFOS.lock();
+    let i = match fifos.iter().position(|f| matches!(f, Some(f) if f.dev == dev && f.inum == inum)) {
+        Some(i) => i,
+        None => {
+            let i = fifos.iter().position(|f| f.is_none()).ok_or(Errno::ENFILE)?;
+            fifos[i] = Some(Fifo {
+                dev,
+                inum,
+                pipe: Pipe::new()?,
+                readers: 0,
+                writers: 0,
+                read_opens: Wrapping(0),
+                write_opens: Wrapping(0),
+            });
+            i
+        },
+    };
+
+    let fifo = fifos[i].as_mut().unwrap();
+    if nonblock && writable && !readable && fifo.readers == 0 {
+        if fifo.writers == 0 {
+            fifos[i] = None;
+        }
+        return Err(Errno::ENXIO)
+    }
+    if readable {
+        fifo.readers += 1;
+        fifo.read_opens += Wrapping(1);
+    }
+    if writable {
+        fifo.writers += 1;
+        fifo.write_opens += Wrapping(1);
+    }
+    fifo.pipe.set_open(fifo.readers > 0, fifo.writers > 0);
+    let pipe = Arc::clone(&fifo.pipe);
+    let seen = if readable { fifo.write_opens } else { fifo.read_opens };
+    let channel = &fifos[i] as *const Option<Fifo> as usize;
+    unsafe { PROC_MANAGER.wakeup(channel); }
+
+    // wait for the other end
+    if !nonblock && readable != writable {
+        let p = unsafe { CPU_MANAGER.my_proc() };
+        loop {
+            let fifo = fifos[i].as_ref().unwrap();
+            let (peers, opens) = if readable {
+                (fifo.writers, fifo.write_opens)
+            } else {
+                (fifo.readers, fifo.read_opens)
+            };
+            if peers > 0 || opens != seen {
+                break
+            }
+            if p.interrupted() {
+                release(&mut fifos, i, readable, writable);
+                return Err(Errno::EINTR)
+            }
+            p.sleep_interruptible(channel, fifos);
+            fifos = FIFOS.lock();
+        }
+    }
+
+    Ok(pipe)
+}
+
+/// Close a file opening the FIFO of `pipe`.
+pub(super) fn fifo_close(pipe: &Arc<Pipe>, readable: bool, writable: bool) {
+    let mut fifos = FIFOS.lock();
+    let i = fifos.iter()
+        .position(|f| matches!(f, Some(f) if Arc::ptr_eq(&f.pipe, pipe)))
+        .expect("fifo not opened");
+    release(&mut fifos, i, readable, writable);
+}
+
+/// Release the opened ends of the FIFO at entry `i`,
+/// and remove the entry if neither end is opened any more.
+fn release(fifos: &mut [Option<Fifo>; NFIFO], i: usize, readable: bool, writable: bool) {
+    let fifo = fifos[i].as_mut().unwrap();
+    if readable {
+        fifo.readers -= 1;
+    }
+    if writable {
+        fifo.writers -= 1;
+    }
+    fifo.pipe.set_open(fifo.readers > 0, fifo.writers > 0);
+    if fifo.readers == 0 && fifo.writers == 0 {
+        fifos[i] = None;
+    }
+}
@@ -2,27 +2,37 @@ use alloc::sync::Arc;
 use core::cell::UnsafeCell;
 use core::cmp::min;
 use core::convert::TryInto;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::consts::driver::NDEV;
 use crate::consts::fs::{MAXOPBLOCKS, BSIZE};
-use crate::consts::fs::{O_RDONLY, O_WRONLY, O_RDWR, O_CREATE, O_TRUNC};
-use crate::driver::DEVICES;
+use crate::consts::fs::{O_RDONLY, O_WRONLY, O_RDWR, O_CREATE, O_TRUNC, O_NONBLOCK};
+use crate::consts::fs::{MAY_READ, MAY_WRITE, POLLIN, POLLOUT, POLLERR, POLLHUP};
+use crate::driver::{DEVICES, poll_key};
+use crate::errno::Errno;
 use crate::mm::Address;
 
 use super::{ICACHE, LOG, inode::FileStat};
 use super::{Inode, InodeType};
 
 mod pipe;
+mod fifo;
+mod socket;
 
 pub use pipe::Pipe;
+pub use socket::Socket;
+use fifo::{fifo_open, fifo_close};
 
 /// File abstraction above inode.
-/// It can represent regular file, device and pipe.
+/// It can represent regular file, device, pipe, FIFO and socket.
 #[derive(Debug)]
 pub struct File {
     inner: FileInner,
     readable: bool,
     writable: bool,
+    /// whether to return EAGAIN instead of blocking,
+    /// shared by the duplicated file descriptors
+    nonblock: AtomicBool,
 }
 
 unsafe impl Send for File {}
@@ -31,24 +41,24 @@ unsafe impl Sync for File {}
 impl File {
     /// Open a file and optionally create a regular file.
     /// LTODO - avoid stack allocation by Arc::new - consider box syntax?
-    pub fn open(path: &[u8], flags: i32) -> Option<Arc<Self>> {
+    pub fn open(path: &[u8], flags: i32) -> Result<Arc<Self>, Errno> {
         LOG.begin_op();
 
         let inode: Inode;
         if flags & O_CREATE > 0 {
             match ICACHE.create(&path, InodeType::File, 0, 0, true) {
-                Some(i) => inode = i,
-                None => {
+                Ok(i) => inode = i,
+                Err(e) => {
                     LOG.end_op();
-                    return None
+                    return Err(e)
                 }
             }
         } else {
             match ICACHE.namei(&path) {
-                Some(i) => inode = i,
-                None => {
+                Ok(i) => inode = i,
+                Err(e) => {
                     LOG.end_op();
-                    return None
+                    return Err(e)
                 }
             }
         }
@@ -57,12 +67,23 @@ impl File {
         let inner;
         let readable = (flags & O_WRONLY) == 0;
         let writable = ((flags & O_WRONLY) | (flags & O_RDWR)) > 0;
+        let mut want = 0;
+        if readable {
+            want |= MAY_READ;
+        }
+        if writable || flags & O_TRUNC > 0 {
+            want |= MAY_WRITE;
+        }
+        if let Err(e) = idata.permit(want) {
+            drop(idata); drop(inode); LOG.end_op();
+            return Err(e)
+        }
         match idata.get_itype() {
             InodeType::Empty => panic!("empty inode"),
             InodeType::Directory => {
-                if flags != O_RDONLY {
+                if flags & !O_NONBLOCK != O_RDONLY {
                     drop(idata); drop(inode); LOG.end_op();
-                    return None
+                    return Err(Errno::EISDIR)
                 }
                 drop(idata);
                 inner = FileInner::Regular(FileRegular { offset: UnsafeCell::new(0), inode: Some(inode) });
@@ -78,30 +99,77 @@ impl File {
                 let (major, _) = idata.get_devnum();
                 if major as usize >= NDEV {
                     drop(idata); drop(inode); LOG.end_op();
-                    return None
+                    return Err(Errno::ENXIO)
                 }
                 drop(idata);
                 inner = FileInner::Device(FileDevice { major, inode: Some(inode) });
-            }
+            },
+            InodeType::Fifo => {
+                let (dev, inum) = idata.get_dev_inum();
+                drop(idata);
+                LOG.end_op();
+
+                // it might wait for the other end, out of the fs transaction
+                let nonblock = flags & O_NONBLOCK > 0;
+                let pipe = match fifo_open(dev, inum, readable, writable, nonblock) {
+                    Ok(pipe) => pipe,
+                    Err(e) => {
+                        LOG.begin_op(); drop(inode); LOG.end_op();
+                        return Err(e)
+                    },
+                };
+                return Ok(Arc::new(File {
+                    inner: FileInner::Fifo(FileFifo { pipe, inode: Some(inode) }),
+                    readable,
+                    writable,
+                    nonblock: AtomicBool::new(nonblock),
+                }))
+            },
+            InodeType::Socket => {
+                drop(idata); drop(inode); LOG.end_op();
+                return Err(Errno::ENXIO)
+            },
         }
 
         LOG.end_op();
-        Some(Arc::new(File {
+        Ok(Arc::new(File {
             inner,
             readable,
-            writable
+            writable,
+            nonblock: AtomicBool::new(flags & O_NONBLOCK > 0),
         }))
     }
 
+    /// Get the access mode and status flags, as passed to open.
+    pub fn get_flags(&self) -> i32 {
+        let mode = match (self.readable, self.writable) {
+            (true, true) => O_RDWR,
+            (false, true) => O_WRONLY,
+            _ => O_RDONLY,
+        };
+        if self.nonblock.load(Ordering::Relaxed) {
+            mode | O_NONBLOCK
+        } else {
+            mode
+        }
+    }
+
+    /// Set the status flags, only O_NONBLOCK could be changed.
+    pub fn set_flags(&self, flags: i32) {
+        self.nonblock.store(flags & O_NONBLOCK > 0, Ordering::Relaxed);
+    }
+
     /// Read from file to user buffer at `addr` in total `count` bytes.
     /// Return the acutal conut of bytes read.
-    pub fn fread(&self, addr: usize, count: u32) -> Result<u32, ()> {
+    pub fn fread(&self, addr: usize, count: u32) -> Result<u32, Errno> {
         if !self.readable {
-            return Err(())
+            return Err(Errno::EBADF)
         }
 
         match self.inner {
-            FileInner::Pipe(ref pipe) => pipe.read(addr, count),
+            FileInner::Pipe(ref pipe) => pipe.read(addr, count, self.nonblock.load(Ordering::Relaxed)),
+            FileInner::Fifo(ref fifo) => fifo.pipe.read(addr, count, self.nonblock.load(Ordering::Relaxed)),
+            FileInner::Socket(ref socket) => socket.read(addr, count, self.nonblock.load(Ordering::Relaxed)),
             FileInner::Regular(ref file) => {
                 let mut idata = file.inode.as_ref().unwrap().lock();
                 let offset = unsafe { &mut *file.offset.get() };
@@ -111,25 +179,27 @@ impl File {
                         drop(idata);
                         Ok(read_count)
                     },
-                    Err(()) => Err(())
+                    Err(e) => Err(e)
                 }
             },
             FileInner::Device(ref dev) => {
-                let dev_read = DEVICES[dev.major as usize].as_ref().ok_or(())?.read;
-                dev_read(Address::Virtual(addr), count)
+                let dev_read = DEVICES[dev.major as usize].as_ref().ok_or(Errno::ENXIO)?.read;
+                dev_read(Address::Virtual(addr), count, self.nonblock.load(Ordering::Relaxed))
             },
         }
     }
 
     /// Write user data from `addr` to file in total `count` bytes.
     /// Return the acutal conut of bytes written.
-    pub fn fwrite(&self, addr: usize, count: u32) -> Result<u32, ()> {
+    pub fn fwrite(&self, addr: usize, count: u32) -> Result<u32, Errno> {
         if !self.writable {
-            return Err(())
+            return Err(Errno::EBADF)
         }
 
         match self.inner {
-            FileInner::Pipe(ref pipe) => pipe.write(addr, count),
+            FileInner::Pipe(ref pipe) => pipe.write(addr, count, self.nonblock.load(Ordering::Relaxed)),
+            FileInner::Fifo(ref fifo) => fifo.pipe.write(addr, count, self.nonblock.load(Ordering::Relaxed)),
+            FileInner::Socket(ref socket) => socket.write(addr, count, self.nonblock.load(Ordering::Relaxed)),
             FileInner::Regular(ref file) => {
                 let batch = ((MAXOPBLOCKS-4)/2*BSIZE) as u32;
                 let mut addr = Address::Virtual(addr);
@@ -151,26 +221,139 @@ impl File {
                                 return Ok(i+actual_count)
                             }
                         },
-                        Err(()) => return Err(()),
+                        Err(e) => return Err(e),
                     }
                     addr = addr.offset(write_count as usize);
                 }
                 Ok(count)
             },
             FileInner::Device(ref dev) => {
-                let dev_write = DEVICES[dev.major as usize].as_ref().ok_or(())?.write;
+                let dev_write = DEVICES[dev.major as usize].as_ref().ok_or(Errno::ENXIO)?.write;
                 dev_write(Address::Virtual(addr), count)
             },
         }
     }
 
+    /// Check if the file could be memory mapped.
+    /// It should be a readable regular file,
+    /// and should also be writable if the mapping writes back to it.
+    pub fn mmapable(&self, write_back: bool) -> bool {
+        match self.inner {
+            FileInner::Regular(_) => self.readable && (!write_back || self.writable),
+            _ => false,
+        }
+    }
+
+    /// Read from a regular file at `offset` to `dst` in total `count` bytes,
+    /// without touching the file offset.
+    /// Return the acutal conut of bytes read.
+    pub fn read_at(&self, dst: Address, offset: u32, count: u32) -> Result<u32, Errno> {
+        match self.inner {
+            FileInner::Regular(ref file) => {
+                let mut idata = file.inode.as_ref().unwrap().lock();
+                let ret = idata.try_iread(dst, offset, count);
+                drop(idata);
+                ret
+            },
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// Write to a regular file at `offset` from `src` in total `count` bytes,
+    /// without touching the file offset.
+    /// The file size is not changed, so content beyond the end of file is discarded.
+    /// Return the acutal conut of bytes written.
+    pub fn write_at(&self, src: Address, offset: u32, count: u32) -> Result<u32, Errno> {
+        let file = match self.inner {
+            FileInner::Regular(ref file) => file,
+            _ => return Err(Errno::EINVAL),
+        };
+
+        let batch = ((MAXOPBLOCKS-4)/2*BSIZE) as u32;
+        let mut src = src;
+        let mut written = 0;
+        while written < count {
+            LOG.begin_op();
+            let mut idata = file.inode.as_ref().unwrap().lock();
+            let size = idata.get_size();
+            let cur_offset = offset + written;
+            if cur_offset >= size {
+                drop(idata);
+                LOG.end_op();
+                break
+            }
+            let write_count = min(min(batch, count - written), size - cur_offset);
+            let ret = idata.iwrite(src, cur_offset, write_count);
+            drop(idata);
+            LOG.end_op();
+
+            ret?;
+            written += write_count;
+            src = src.offset(write_count as usize);
+        }
+        Ok(written)
+    }
+
+    /// Events ready on the file, only POLLIN and POLLOUT it is opened for.
+    /// A regular file is always ready.
+    pub fn poll(&self) -> i16 {
+        let events = match self.inner {
+            FileInner::Pipe(ref pipe) => return pipe.poll(self.writable),
+            FileInner::Fifo(ref fifo) => {
+                let mut events = 0;
+                if self.readable {
+                    events |= fifo.pipe.poll(false);
+                }
+                if self.writable {
+                    events |= fifo.pipe.poll(true);
+                }
+                return events
+            },
+            FileInner::Socket(ref socket) => return socket.poll(),
+            FileInner::Regular(_) => POLLIN | POLLOUT,
+            FileInner::Device(ref dev) => match DEVICES[dev.major as usize] {
+                Some(ref device) => (device.poll)(),
+                None => POLLERR,
+            },
+        };
+        let mut mask = POLLERR | POLLHUP;
+        if self.readable {
+            mask |= POLLIN;
+        }
+        if self.writable {
+            mask |= POLLOUT;
+        }
+        events & mask
+    }
+
+    /// Keys notified when the file may become ready, None if it never blocks.
+    /// A socket has one for each direction.
+    pub fn poll_keys(&self) -> [Option<usize>; 2] {
+        match self.inner {
+            FileInner::Pipe(ref pipe) => [Some(pipe.key()), None],
+            FileInner::Fifo(ref fifo) => [Some(fifo.pipe.key()), None],
+            FileInner::Socket(ref socket) => socket.poll_keys(),
+            FileInner::Regular(_) => [None, None],
+            FileInner::Device(ref dev) => [Some(poll_key(dev.major as usize)), None],
+        }
+    }
+
+    /// Major device number if it is a device file.
+    pub fn major(&self) -> Option<u16> {
+        match self.inner {
+            FileInner::Device(ref dev) => Some(dev.major),
+            _ => None,
+        }
+    }
+
     /// Copy the file status to user memory.
-    pub fn fstat(&self, stat: &mut FileStat) -> Result<(), ()> {
+    pub fn fstat(&self, stat: &mut FileStat) -> Result<(), Errno> {
         let inode: &Inode;
         match self.inner {
-            FileInner::Pipe(_) => return Err(()),
+            FileInner::Pipe(_) | FileInner::Socket(_) => return Err(Errno::EINVAL),
             FileInner::Regular(ref file) => inode = file.inode.as_ref().unwrap(),
             FileInner::Device(ref dev) => inode = dev.inode.as_ref().unwrap(),
+            FileInner::Fifo(ref fifo) => inode = fifo.inode.as_ref().unwrap(),
         }
         let idata = inode.lock();
         idata.istat(stat);
@@ -193,6 +376,13 @@ impl Drop for File {
                 drop(dev.inode.take());
                 LOG.end_op();
             },
+            FileInner::Fifo(ref mut fifo) => {
+                fifo_close(&fifo.pipe, self.readable, self.writable);
+                LOG.begin_op();
+                drop(fifo.inode.take());
+                LOG.end_op();
+            },
+            FileInner::Socket(ref socket) => socket.close(),
         }
     }
 }
@@ -202,6 +392,8 @@ enum FileInner {
     Pipe(Arc<Pipe>),
     Regular(FileRegular),
     Device(FileDevice),
+    Fifo(FileFifo),
+    Socket(Socket),
 }
 
 #[derive(Debug)]
@@ -216,3 +408,10 @@ struct FileDevice {
     major: u16,
     inode: Option<Inode>,
 }
+
+#[derive(Debug)]
+struct FileFifo {
+    /// shared by the files opening the same FIFO
+    pipe: Arc<Pipe>,
+    inode: Option<Inode>,
+}
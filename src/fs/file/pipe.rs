@@ -1,11 +1,13 @@
 use alloc::sync::Arc;
 use core::mem;
 use core::num::Wrapping;
-use core::sync::atomic::{Ordering, AtomicUsize};
+use core::sync::atomic::{AtomicBool, AtomicUsize};
 use core::cmp::min;
 use core::ptr::addr_of_mut;
 
-use crate::consts::fs::{PIPESIZE, PIPESIZE_U32};
+use crate::consts::fs::{PIPESIZE, PIPESIZE_U32, POLLIN, POLLOUT, POLLERR, POLLHUP};
+use crate::errno::Errno;
+use crate::fs::poll_notify;
 use crate::process::{CPU_MANAGER, PROC_MANAGER};
 use crate::spinlock::SpinLock;
 
@@ -15,18 +17,24 @@ use super::{File, FileInner};
 pub struct Pipe(SpinLock<PipeInner>);
 
 impl Pipe {
-    /// Create a [`Pipe`].
-    /// Return two files respectively reading from and writing to this [`Pipe`].
-    pub fn create() -> Option<(Arc<File>, Arc<File>)> {
+    /// Allocate a [`Pipe`] with both ends closed.
+    pub(super) fn new() -> Result<Arc<Self>, Errno> {
         debug_assert!(mem::size_of::<Pipe>() <= 512-2*mem::size_of::<AtomicUsize>());
 
-        // create a pipe
-        let mut pipe = Arc::<Self>::try_new_zeroed().ok()?;
+        let mut pipe = Arc::<Self>::try_new_zeroed().map_err(|_| Errno::ENOMEM)?;
         let pipe = unsafe {
             let ptr = Arc::get_mut_unchecked(&mut pipe).as_mut_ptr();
             SpinLock::init_name(addr_of_mut!((*ptr).0), "pipe");
             pipe.assume_init()
         };
+        Ok(pipe)
+    }
+
+    /// Create a [`Pipe`].
+    /// Return two files respectively reading from and writing to this [`Pipe`].
+    pub fn create() -> Result<(Arc<File>, Arc<File>), Errno> {
+        // create a pipe
+        let pipe = Self::new()?;
         let mut guard = pipe.0.lock();
         guard.read_open = true;
         guard.write_open = true;
@@ -37,29 +45,34 @@ impl Pipe {
             inner: FileInner::Pipe(Arc::clone(&pipe)),
             readable: true,
             writable: false,
-        }).ok()?;
+            nonblock: AtomicBool::new(false),
+        }).map_err(|_| Errno::ENOMEM)?;
         let write_file = Arc::try_new(File {
             inner: FileInner::Pipe(Arc::clone(&pipe)),
             readable: false,
             writable: true,
-        }).ok()?;
+            nonblock: AtomicBool::new(false),
+        }).map_err(|_| Errno::ENOMEM)?;
 
-        Some((read_file, write_file))
+        Ok((read_file, write_file))
     }
 
     /// Read from the pipe.
-    /// Return the bytes actually read.
-    pub(super) fn read(&self, addr: usize, count: u32) -> Result<u32, ()> {
+    /// Return the bytes actually read, or EAGAIN if `nonblock` and it is empty.
+    pub(super) fn read(&self, addr: usize, count: u32, nonblock: bool) -> Result<u32, Errno> {
         let p = unsafe { CPU_MANAGER.my_proc() };
 
         let mut pipe = self.0.lock();
 
         // wait for data to be written
         while pipe.read_cnt == pipe.write_cnt && pipe.write_open {
-            if p.killed.load(Ordering::Relaxed) {
-                return Err(())
+            if nonblock {
+                return Err(Errno::EAGAIN)
+            }
+            if p.interrupted() {
+                return Err(Errno::EINTR)
             }
-            p.sleep(&pipe.read_cnt as *const Wrapping<_> as usize, pipe);
+            p.sleep_interruptible(&pipe.read_cnt as *const Wrapping<_> as usize, pipe);
             pipe = self.0.lock();
         }
 
@@ -76,27 +89,43 @@ impl Pipe {
             }
         }
         unsafe { PROC_MANAGER.wakeup(&pipe.write_cnt as *const Wrapping<_> as usize); }
+        poll_notify(self.key());
         drop(pipe);
         Ok(read_count)
     }
 
     /// Write to the pipe.
     /// Return the bytes actually written.
-    pub(super) fn write(&self, addr: usize, count: u32) -> Result<u32, ()> {
+    /// If `nonblock`, only write what fits in the pipe, and return EAGAIN if nothing fits.
+    pub(super) fn write(&self, addr: usize, count: u32, nonblock: bool) -> Result<u32, Errno> {
         let p = unsafe { CPU_MANAGER.my_proc() };
 
         let mut pipe = self.0.lock();
 
         let mut write_count = 0;
         while write_count < count {
-            if !pipe.read_open || p.killed.load(Ordering::Relaxed) {
-                return Err(())
+            if !pipe.read_open {
+                return Err(Errno::EPIPE)
             }
 
             if pipe.write_cnt == pipe.read_cnt + Wrapping(PIPESIZE_U32) {
+                if nonblock {
+                    if write_count == 0 {
+                        return Err(Errno::EAGAIN)
+                    }
+                    break
+                }
+                // a signal stops the writing, but what is written is still reported
+                if p.interrupted() {
+                    if write_count == 0 {
+                        return Err(Errno::EINTR)
+                    }
+                    break
+                }
                 // wait for data to be read
                 unsafe { PROC_MANAGER.wakeup(&pipe.read_cnt as *const Wrapping<_> as usize); }
-                p.sleep(&pipe.write_cnt as *const Wrapping<_> as usize, pipe);
+                poll_notify(self.key());
+                p.sleep_interruptible(&pipe.write_cnt as *const Wrapping<_> as usize, pipe);
                 pipe = self.0.lock();
             } else {
                 let mut byte: u8 = 0;
@@ -110,19 +139,61 @@ impl Pipe {
             }
         }
         unsafe { PROC_MANAGER.wakeup(&pipe.read_cnt as *const Wrapping<_> as usize); }
+        poll_notify(self.key());
         drop(pipe);
         Ok(write_count)
     }
 
+    /// Events ready on the read end, or on the write end if `is_write`.
+    pub(super) fn poll(&self, is_write: bool) -> i16 {
+        let pipe = self.0.lock();
+        let mut events = 0;
+        if is_write {
+            if !pipe.read_open {
+                events |= POLLERR;
+            } else if pipe.write_cnt != pipe.read_cnt + Wrapping(PIPESIZE_U32) {
+                events |= POLLOUT;
+            }
+        } else {
+            if pipe.read_cnt != pipe.write_cnt {
+                events |= POLLIN;
+            }
+            if !pipe.write_open {
+                events |= POLLHUP;
+            }
+        }
+        events
+    }
+
+    /// Key to poll the pipe, notified whenever either end may become ready.
+    pub(super) fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Set whether each end is opened by any file, used by a FIFO.
+    /// Wake up the readers and writers to notice the change.
+    pub(super) fn set_open(&self, read_open: bool, write_open: bool) {
+        let mut pipe = self.0.lock();
+        pipe.read_open = read_open;
+        pipe.write_open = write_open;
+        unsafe {
+            PROC_MANAGER.wakeup(&pipe.read_cnt as *const Wrapping<_> as usize);
+            PROC_MANAGER.wakeup(&pipe.write_cnt as *const Wrapping<_> as usize);
+        }
+        poll_notify(self.key());
+    }
+
     /// Close one end of the pipe.
     pub(super) fn close(&self, is_write: bool) {
         let mut pipe = self.0.lock();
         if is_write {
             pipe.write_open = false;
             unsafe { PROC_MANAGER.wakeup(&pipe.read_cnt as *const Wrapping<_> as usize); }
+            poll_notify(self.key());
         } else {
             pipe.read_open = false;
             unsafe { PROC_MANAGER.wakeup(&pipe.write_cnt as *const Wrapping<_> as usize); }
+            poll_notify(self.key());
         }
     }
 }
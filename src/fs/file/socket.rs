@@ -0,0 +1,391 @@
+//! Unix domain stream sockets
+//!
+//! A connection is a pair of [`Pipe`]s, one for each direction.
+//! The bound sockets are kept in a table keyed by the device and inode number
+//! of the socket inode created at the path, so that a client connects to it through the path.
+
+use array_macro::array;
+
+use alloc::sync::Arc;
+use core::cmp::{min, max};
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::consts::fs::{NSOCKET, SOMAXCONN, MAY_WRITE, POLLIN};
+use crate::errno::Errno;
+use crate::fs::{ICACHE, Inode, InodeType, LOG, poll_notify};
+use crate::process::{CPU_MANAGER, PROC_MANAGER};
+use crate::spinlock::SpinLock;
+
+use super::{File, FileInner, Pipe};
+
+/// One end of a connection.
+#[derive(Debug)]
+struct Endpoint {
+    /// read from, written by the peer
+    rx: Arc<Pipe>,
+    /// written to, read by the peer
+    tx: Arc<Pipe>,
+}
+
+impl Endpoint {
+    /// Create the two ends of a new connection.
+    fn pair() -> Result<(Self, Self), Errno> {
+        let a = Pipe::new()?;
+        let b = Pipe::new()?;
+        a.set_open(true, true);
+        b.set_open(true, true);
+        Ok((Self { rx: Arc::clone(&a), tx: Arc::clone(&b) }, Self { rx: b, tx: a }))
+    }
+}
+
+impl Drop for Endpoint {
+    /// Close both directions,
+    /// so that the peer reads the end of file, and gets EPIPE when writing.
+    fn drop(&mut self) {
+        self.rx.close(false);
+        self.tx.close(true);
+    }
+}
+
+/// A socket bound to an inode.
+struct Bound {
+    dev: u32,
+    inum: u32,
+    /// maximum number of pending connections, 0 if not listening
+    backlog: usize,
+    /// ring of the connections waiting to be accepted
+    pending: [Option<Endpoint>; SOMAXCONN],
+    head: usize,
+    len: usize,
+}
+
+impl Bound {
+    fn new(dev: u32, inum: u32) -> Self {
+        Self {
+            dev,
+            inum,
+            backlog: 0,
+            pending: array![_ => None; SOMAXCONN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, endpoint: Endpoint) {
+        debug_assert!(self.len < SOMAXCONN);
+        self.pending[(self.head + self.len) % SOMAXCONN] = Some(endpoint);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Endpoint> {
+        if self.len == 0 {
+            return None
+        }
+        let endpoint = self.pending[self.head].take();
+        self.head = (self.head + 1) % SOMAXCONN;
+        self.len -= 1;
+        endpoint
+    }
+}
+
+/// The bound sockets, the address of each entry is the channel
+/// its acceptors and connectors sleep on, and the key to poll it.
+static BOUND: SpinLock<[Option<Bound>; NSOCKET]> = SpinLock::new(array![_ => None; NSOCKET], "sockets");
+
+#[derive(Debug)]
+enum SocketState {
+    Unbound,
+    /// being connected by another thread
+    Connecting,
+    /// bound at the entry of the table, and listening if the backlog is set there
+    Bound(usize, Inode),
+    Connected(Endpoint),
+}
+
+/// Unix domain stream socket.
+#[derive(Debug)]
+pub struct Socket(SpinLock<SocketState>);
+
+impl Socket {
+    /// Create an unbound socket.
+    pub fn create() -> Result<Arc<File>, Errno> {
+        Self::new_file(SocketState::Unbound)
+    }
+
+    /// Create a pair of sockets connected to each other.
+    pub fn pair() -> Result<(Arc<File>, Arc<File>), Errno> {
+        let (a, b) = Endpoint::pair()?;
+        let file_a = Self::new_file(SocketState::Connected(a))?;
+        let file_b = Self::new_file(SocketState::Connected(b))?;
+        Ok((file_a, file_b))
+    }
+
+    fn new_file(state: SocketState) -> Result<Arc<File>, Errno> {
+        Arc::try_new(File {
+            inner: FileInner::Socket(Socket(SpinLock::new(state, "socket"))),
+            readable: true,
+            writable: true,
+            nonblock: AtomicBool::new(false),
+        }).map_err(|_| Errno::ENOMEM)
+    }
+
+    /// Bind to a newly created socket inode at `path`.
+    fn bind(&self, path: &[u8]) -> Result<(), Errno> {
+        if !matches!(*self.0.lock(), SocketState::Unbound) {
+            return Err(Errno::EINVAL)
+        }
+
+        LOG.begin_op();
+        let inode = match ICACHE.create(path, InodeType::Socket, 0, 0, false) {
+            Ok(inode) => inode,
+            Err(Errno::EEXIST) => {
+                LOG.end_op();
+                return Err(Errno::EADDRINUSE)
+            },
+            Err(e) => {
+                LOG.end_op();
+                return Err(e)
+            },
+        };
+        let (dev, inum) = inode.lock().get_dev_inum();
+        LOG.end_op();
+
+        let mut inode = Some(inode);
+        let mut bound = BOUND.lock();
+        let mut state = self.0.lock();
+        let ret = match *state {
+            SocketState::Unbound => match bound.iter().position(|b| b.is_none()) {
+                Some(i) => {
+                    bound[i] = Some(Bound::new(dev, inum));
+                    *state = SocketState::Bound(i, inode.take().unwrap());
+                    Ok(())
+                },
+                None => Err(Errno::ENFILE),
+            },
+            _ => Err(Errno::EINVAL),
+        };
+        drop(state);
+        drop(bound);
+
+        // failed after creating the inode, which is left at the path as a stale address
+        if inode.is_some() {
+            LOG.begin_op();
+            drop(inode);
+            LOG.end_op();
+        }
+        ret
+    }
+
+    /// Start to accept connections, at most `backlog` pending ones.
+    fn listen(&self, backlog: usize) -> Result<(), Errno> {
+        let i = match *self.0.lock() {
+            SocketState::Bound(i, _) => i,
+            _ => return Err(Errno::EINVAL),
+        };
+        BOUND.lock()[i].as_mut().unwrap().backlog = min(max(backlog, 1), SOMAXCONN);
+        Ok(())
+    }
+
+    /// Accept a pending connection, or wait for one if not `nonblock`.
+    /// Return the socket of the server end.
+    fn accept(&self, nonblock: bool) -> Result<Arc<File>, Errno> {
+        let i = match *self.0.lock() {
+            SocketState::Bound(i, _) => i,
+            _ => return Err(Errno::EINVAL),
+        };
+
+        let p = unsafe { CPU_MANAGER.my_proc() };
+        let mut bound = BOUND.lock();
+        let channel = &bound[i] as *const Option<Bound> as usize;
+        let ret = loop {
+            let b = bound[i].as_mut().unwrap();
+            if b.backlog == 0 {
+                break Err(Errno::EINVAL)
+            }
+            if let Some(endpoint) = b.pop() {
+                // wake up the connectors waiting for room
+                unsafe { PROC_MANAGER.wakeup(channel); }
+                break Ok(endpoint)
+            }
+            if nonblock {
+                break Err(Errno::EAGAIN)
+            }
+            if p.interrupted() {
+                break Err(Errno::EINTR)
+            }
+            p.sleep_interruptible(channel, bound);
+            bound = BOUND.lock();
+        };
+        drop(bound);
+
+        Self::new_file(SocketState::Connected(ret?))
+    }
+
+    /// Connect to the listening socket bound at `path`.
+    /// Wait for room if its pending connections are full, unless `nonblock`.
+    fn connect(&self, path: &[u8], nonblock: bool) -> Result<(), Errno> {
+        LOG.begin_op();
+        let inode = match ICACHE.namei(path) {
+            Ok(inode) => inode,
+            Err(e) => {
+                LOG.end_op();
+                return Err(e)
+            },
+        };
+        let idata = inode.lock();
+        let key = if idata.get_itype() != InodeType::Socket {
+            Err(Errno::ECONNREFUSED)
+        } else {
+            idata.permit(MAY_WRITE).map(|()| idata.get_dev_inum())
+        };
+        drop(idata); drop(inode); LOG.end_op();
+        let (dev, inum) = key?;
+
+        {
+            let mut state = self.0.lock();
+            match *state {
+                SocketState::Unbound => *state = SocketState::Connecting,
+                SocketState::Connected(_) => return Err(Errno::EISCONN),
+                _ => return Err(Errno::EINVAL),
+            }
+        }
+
+        let p = unsafe { CPU_MANAGER.my_proc() };
+        let mut bound = BOUND.lock();
+        let ret = loop {
+            let i = match bound.iter().position(|b| {
+                matches!(b, Some(b) if b.dev == dev && b.inum == inum && b.backlog > 0)
+            }) {
+                Some(i) => i,
+                None => break Err(Errno::ECONNREFUSED),
+            };
+            let channel = &bound[i] as *const Option<Bound> as usize;
+            let b = bound[i].as_mut().unwrap();
+            if b.len < b.backlog {
+                match Endpoint::pair() {
+                    Ok((client, server)) => {
+                        b.push(server);
+                        unsafe { PROC_MANAGER.wakeup(channel); }
+                        poll_notify(channel);
+                        break Ok(client)
+                    },
+                    Err(e) => break Err(e),
+                }
+            }
+            if nonblock {
+                break Err(Errno::EAGAIN)
+            }
+            if p.interrupted() {
+                break Err(Errno::EINTR)
+            }
+            p.sleep_interruptible(channel, bound);
+            bound = BOUND.lock();
+        };
+        drop(bound);
+
+        let mut state = self.0.lock();
+        match ret {
+            Ok(endpoint) => {
+                *state = SocketState::Connected(endpoint);
+                Ok(())
+            },
+            Err(e) => {
+                *state = SocketState::Unbound;
+                Err(e)
+            },
+        }
+    }
+
+    /// Read from the connection.
+    pub(super) fn read(&self, addr: usize, count: u32, nonblock: bool) -> Result<u32, Errno> {
+        let rx = match *self.0.lock() {
+            SocketState::Connected(ref endpoint) => Arc::clone(&endpoint.rx),
+            _ => return Err(Errno::ENOTCONN),
+        };
+        rx.read(addr, count, nonblock)
+    }
+
+    /// Write to the connection.
+    pub(super) fn write(&self, addr: usize, count: u32, nonblock: bool) -> Result<u32, Errno> {
+        let tx = match *self.0.lock() {
+            SocketState::Connected(ref endpoint) => Arc::clone(&endpoint.tx),
+            _ => return Err(Errno::ENOTCONN),
+        };
+        tx.write(addr, count, nonblock)
+    }
+
+    /// Events ready on the connection,
+    /// or POLLIN if it is listening and a connection is pending.
+    pub(super) fn poll(&self) -> i16 {
+        let i = match *self.0.lock() {
+            SocketState::Connected(ref endpoint) => return endpoint.rx.poll(false) | endpoint.tx.poll(true),
+            SocketState::Bound(i, _) => i,
+            _ => return 0,
+        };
+        match BOUND.lock()[i] {
+            Some(ref b) if b.len > 0 => POLLIN,
+            _ => 0,
+        }
+    }
+
+    /// Keys notified when it may become ready.
+    pub(super) fn poll_keys(&self) -> [Option<usize>; 2] {
+        let i = match *self.0.lock() {
+            SocketState::Connected(ref endpoint) => return [Some(endpoint.rx.key()), Some(endpoint.tx.key())],
+            SocketState::Bound(i, _) => i,
+            _ => return [None, None],
+        };
+        [Some(&BOUND.lock()[i] as *const Option<Bound> as usize), None]
+    }
+
+    /// Close the socket, the pending connections are closed if it is listening.
+    pub(super) fn close(&self) {
+        let state = mem::replace(&mut *self.0.lock(), SocketState::Unbound);
+        match state {
+            SocketState::Bound(i, inode) => {
+                let mut bound = BOUND.lock();
+                let entry = bound[i].take();
+                // wake up the connectors waiting for room, to be refused
+                unsafe { PROC_MANAGER.wakeup(&bound[i] as *const Option<Bound> as usize); }
+                drop(bound);
+                drop(entry);
+                LOG.begin_op();
+                drop(inode);
+                LOG.end_op();
+            },
+            SocketState::Connected(endpoint) => drop(endpoint),
+            SocketState::Unbound | SocketState::Connecting => {},
+        }
+    }
+}
+
+impl File {
+    /// The socket if the file is one.
+    fn socket(&self) -> Result<&Socket, Errno> {
+        match self.inner {
+            FileInner::Socket(ref socket) => Ok(socket),
+            _ => Err(Errno::ENOTSOCK),
+        }
+    }
+
+    /// Bind the socket to a newly created socket inode at `path`.
+    pub fn bind(&self, path: &[u8]) -> Result<(), Errno> {
+        self.socket()?.bind(path)
+    }
+
+    /// Let the bound socket accept connections, at most `backlog` pending ones.
+    pub fn listen(&self, backlog: usize) -> Result<(), Errno> {
+        self.socket()?.listen(backlog)
+    }
+
+    /// Accept a connection on the listening socket.
+    pub fn accept(&self) -> Result<Arc<File>, Errno> {
+        self.socket()?.accept(self.nonblock.load(Ordering::Relaxed))
+    }
+
+    /// Connect the socket to the listening one bound at `path`.
+    pub fn connect(&self, path: &[u8]) -> Result<(), Errno> {
+        self.socket()?.connect(path, self.nonblock.load(Ordering::Relaxed))
+    }
+}
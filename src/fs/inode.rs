@@ -4,11 +4,14 @@ use array_macro::array;
 
 use core::{cmp::min, mem, panic, ptr};
 
+use crate::errno::Errno;
 use crate::mm::Address;
 use crate::spinlock::SpinLock;
 use crate::sleeplock::{SleepLock, SleepLockGuard};
 use crate::process::CPU_MANAGER;
+use crate::driver::rtc;
 use crate::consts::fs::{NINODE, BSIZE, NDIRECT, NINDIRECT, MAX_DIR_SIZE, MAX_FILE_SIZE, ROOTDEV, ROOTINUM};
+use crate::consts::fs::{MAY_EXEC, MAY_WRITE, MODE_MASK, FILE_MODE, DIR_MODE, DEVICE_MODE, SOCKET_MODE};
 use super::{BCACHE, BufData, superblock::SUPER_BLOCK, LOG};
 use super::block::{bm_alloc, bm_free, inode_alloc};
 
@@ -116,13 +119,13 @@ impl InodeCache {
     }
 
     /// Helper function for `namei` and `namei_parent`.
-    fn namex(&self, path: &[u8], name: &mut [u8; MAX_DIR_SIZE], is_parent: bool) -> Option<Inode> {
+    fn namex(&self, path: &[u8], name: &mut [u8; MAX_DIR_SIZE], is_parent: bool) -> Result<Inode, Errno> {
         let mut inode: Inode;
         if path[0] == b'/' {
             inode = self.get(ROOTDEV, ROOTINUM);
         } else {
             let p = unsafe { CPU_MANAGER.my_proc() };
-            inode = self.dup(p.data.get_mut().cwd.as_ref().unwrap());
+            inode = p.data.get_mut().cwd();
         }
 
         let mut cur: usize = 0;
@@ -134,16 +137,18 @@ impl InodeCache {
             let mut data_guard = inode.lock();
             if data_guard.dinode.itype != InodeType::Directory {
                 drop(data_guard);
-                return None
+                return Err(Errno::ENOTDIR)
             }
+            // need search permission on every directory walked through
+            data_guard.permit(MAY_EXEC)?;
             if is_parent && path[cur] == 0 {
                 drop(data_guard);
-                return Some(inode)
+                return Ok(inode)
             }
             match data_guard.dir_lookup(name, false) {
                 None => {
                     drop(data_guard);
-                    return None
+                    return Err(Errno::ENOENT)
                 },
                 Some((last_inode, _)) => {
                     drop(data_guard);
@@ -155,32 +160,34 @@ impl InodeCache {
         if is_parent {
             // only when querying root inode's parent
             println!("kernel warning: namex querying root inode's parent");
-            None
+            Err(Errno::ENOENT)
         } else {
-            Some(inode)
+            Ok(inode)
         }
     }
 
     /// namei interprets the path argument as an pathname to Unix file.
-    /// It will return an [`Inode`] if succeed, Err(()) if fail.
+    /// It will return an [`Inode`] if succeed, the reason if fail.
     /// It must be called inside a transaction(i.e., `begin_op` and `end_op`) since it calls `put`.
     /// Note: the path should end with 0u8, otherwise it might panic due to out-of-bound.
-    pub fn namei(&self, path: &[u8]) -> Option<Inode> {
+    pub fn namei(&self, path: &[u8]) -> Result<Inode, Errno> {
         let mut name: [u8; MAX_DIR_SIZE] = [0; MAX_DIR_SIZE];
         self.namex(path, &mut name, false)
     }
 
     /// Same behavior as `namei`, but return the parent of the inode,
     /// and copy the end path into name.
-    pub fn namei_parent(&self, path: &[u8], name: &mut [u8; MAX_DIR_SIZE]) -> Option<Inode> {
+    pub fn namei_parent(&self, path: &[u8], name: &mut [u8; MAX_DIR_SIZE]) -> Result<Inode, Errno> {
         self.namex(path, name, true)
     }
 
     /// Given the inode path, lookup and create it.
     /// When the inode on the specificed path is already created,
     /// i.e., successfully looked up,
-    /// return it or [`None`] according to the reuse flag.
-    pub fn create(&self, path: &[u8], itype: InodeType, major: u16, minor: u16, reuse: bool) -> Option<Inode> {
+    /// return it or [`Errno::EEXIST`] according to the reuse flag.
+    /// The new inode is owned by the current process,
+    /// which needs write and search permission on the parent directory.
+    pub fn create(&self, path: &[u8], itype: InodeType, major: u16, minor: u16, reuse: bool) -> Result<Inode, Errno> {
         let mut name: [u8; MAX_DIR_SIZE] = [0; MAX_DIR_SIZE];
         let dir_inode = self.namei_parent(path, &mut name)?;
         let mut dir_idata = dir_inode.lock();
@@ -188,13 +195,15 @@ impl InodeCache {
         // lookup first
         if let Some((inode, _)) = dir_idata.dir_lookup(&name, false) {
             if reuse {
-                return Some(inode)
+                return Ok(inode)
             } else {
-                return None
+                return Err(Errno::EEXIST)
             }
         }
 
         // not found, create it
+        dir_idata.permit(MAY_WRITE | MAY_EXEC)?;
+        let cred = unsafe { CPU_MANAGER.my_proc().cred() };
         let (dev, _) = *dir_idata.valid.as_ref().unwrap();
         let inum = inode_alloc(dev, itype);
         let inode = self.get(dev, inum);
@@ -202,6 +211,15 @@ impl InodeCache {
         idata.dinode.major = major;
         idata.dinode.minor = minor;
         idata.dinode.nlink = 1;
+        idata.dinode.mode = match itype {
+            InodeType::Directory => DIR_MODE,
+            InodeType::Device => DEVICE_MODE,
+            InodeType::Socket => SOCKET_MODE,
+            _ => FILE_MODE,
+        };
+        idata.dinode.uid = cred.uid;
+        idata.dinode.gid = cred.gid;
+        idata.dinode.mtime = rtc::read_secs();
         idata.update();
         debug_assert_eq!(idata.dinode.itype, itype);
 
@@ -229,7 +247,7 @@ impl InodeCache {
         drop(dir_idata);
         drop(dir_inode);
         drop(idata);
-        Some(inode)
+        Ok(inode)
     }
 }
 
@@ -363,6 +381,79 @@ impl InodeData {
         (self.dinode.major, self.dinode.minor)
     }
 
+    /// Get the data size of inode.
+    #[inline]
+    pub fn get_size(&self) -> u32 {
+        self.dinode.size
+    }
+
+    /// Get the owner, i.e., uid and gid.
+    #[inline]
+    pub fn get_owner(&self) -> (u16, u16) {
+        (self.dinode.uid, self.dinode.gid)
+    }
+
+    /// Check if the current process is allowed to access the inode as wanted,
+    /// which is some of `MAY_READ`, `MAY_WRITE` and `MAY_EXEC`.
+    /// The owner bits are used for the owner, the group bits for the group,
+    /// and the other bits for the rest.
+    /// Root is allowed everything, except executing a non-directory without any execute bit.
+    pub fn permit(&self, want: u16) -> Result<(), Errno> {
+        let cred = unsafe { CPU_MANAGER.my_proc().cred() };
+        let mode = self.dinode.mode;
+        let allowed = if cred.is_root() {
+            if want & MAY_EXEC == 0
+                || self.dinode.itype == InodeType::Directory
+                || mode & 0o111 != 0
+            {
+                return Ok(())
+            }
+            !MAY_EXEC
+        } else if cred.uid == self.dinode.uid {
+            mode >> 6
+        } else if cred.gid == self.dinode.gid {
+            mode >> 3
+        } else {
+            mode
+        };
+
+        if allowed & want == want {
+            Ok(())
+        } else {
+            Err(Errno::EACCES)
+        }
+    }
+
+    /// Change the permission bits of the inode and write it back.
+    /// Only the owner or root can do it.
+    pub fn chmod(&mut self, mode: u16) -> Result<(), Errno> {
+        let cred = unsafe { CPU_MANAGER.my_proc().cred() };
+        if !cred.is_root() && cred.uid != self.dinode.uid {
+            return Err(Errno::EPERM)
+        }
+        self.dinode.mode = mode & MODE_MASK;
+        self.update();
+        Ok(())
+    }
+
+    /// Change the owner of the inode and write it back.
+    /// None keeps the current one.
+    /// Only root can do it.
+    pub fn chown(&mut self, uid: Option<u16>, gid: Option<u16>) -> Result<(), Errno> {
+        let cred = unsafe { CPU_MANAGER.my_proc().cred() };
+        if !cred.is_root() {
+            return Err(Errno::EPERM)
+        }
+        if let Some(uid) = uid {
+            self.dinode.uid = uid;
+        }
+        if let Some(gid) = gid {
+            self.dinode.gid = gid;
+        }
+        self.update();
+        Ok(())
+    }
+
     /// Increase the hard link by 1.
     #[inline]
     pub fn link(&mut self) {
@@ -402,6 +493,7 @@ impl InodeData {
         }
 
         self.dinode.size = 0;
+        self.dinode.mtime = rtc::read_secs();
         self.update();
     }
 
@@ -420,11 +512,11 @@ impl InodeData {
     /// Read inode data from disk.
     /// According to the kind of dst, it will copy to virtual address or kernel address.
     /// Note: `offset` + `count` should not be larger than the data size of inode.
-    pub fn iread(&mut self, mut dst: Address, offset: u32, count: u32) -> Result<(), ()> {
+    pub fn iread(&mut self, mut dst: Address, offset: u32, count: u32) -> Result<(), Errno> {
         // check the reading content is in range
-        let end = offset.checked_add(count).ok_or(())?;
+        let end = offset.checked_add(count).ok_or(Errno::EINVAL)?;
         if end > self.dinode.size {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
 
         let (dev, _) = *self.valid.as_ref().unwrap();
@@ -451,12 +543,12 @@ impl InodeData {
 
     /// Similar to [`iread`].
     /// Try to read as much as possible, return the bytes read.
-    pub fn try_iread(&mut self, dst: Address, offset: u32, count: u32) -> Result<u32, ()> {
+    pub fn try_iread(&mut self, dst: Address, offset: u32, count: u32) -> Result<u32, Errno> {
         // check the reading content is in range
         if offset > self.dinode.size {
             return Ok(0)
         }
-        let end = offset.checked_add(count).ok_or(())?;
+        let end = offset.checked_add(count).ok_or(Errno::EINVAL)?;
         let actual_count = if end > self.dinode.size {
             self.dinode.size - offset
         } else {
@@ -468,11 +560,9 @@ impl InodeData {
 
     /// Wrapper of [`try_iwrite`].
     /// Succeed only when all the requested count of btyes are written.
-    pub fn iwrite(&mut self, src: Address, offset: u32, count: u32) -> Result<(), ()> {
-        match self.try_iwrite(src, offset, count) {
-            Ok(ret) => if ret == count { Ok(()) } else { Err(()) },
-            Err(()) => Err(()),
-        }
+    pub fn iwrite(&mut self, src: Address, offset: u32, count: u32) -> Result<(), Errno> {
+        let ret = self.try_iwrite(src, offset, count)?;
+        if ret == count { Ok(()) } else { Err(Errno::EFAULT) }
     }
 
     /// Try to write inode data to disk as much as possible.
@@ -480,14 +570,22 @@ impl InodeData {
     /// Return the actual bytes written.
     /// Note1: It will automatically increment the size of this inode, i.e.,
     ///     allocate new blocks in the disk/fs, but the offset must be in range.
-    pub fn try_iwrite(&mut self, mut src: Address, offset: u32, count: u32) -> Result<u32, ()> {
+    /// Note2: The bytes from the user are cut at RLIMIT_FSIZE of the current process.
+    pub fn try_iwrite(&mut self, mut src: Address, offset: u32, count: u32) -> Result<u32, Errno> {
         // check the writing content is in range
         if offset > self.dinode.size {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
-        let end = offset.checked_add(count).ok_or(())? as usize;
+        // user data written by the process is also limited by its RLIMIT_FSIZE
+        let count = match src {
+            Address::Virtual(_) => unsafe {
+                CPU_MANAGER.my_proc().fsize_limit(offset as usize, count as usize)? as u32
+            },
+            _ => count,
+        };
+        let end = offset.checked_add(count).ok_or(Errno::EFBIG)? as usize;
         if end > MAX_FILE_SIZE {
-            return Err(())
+            return Err(Errno::EFBIG)
         }
 
         let (dev, _) = *self.valid.as_ref().unwrap();
@@ -516,6 +614,7 @@ impl InodeData {
         if size > self.dinode.size {
             self.dinode.size = size;
         }
+        self.dinode.mtime = rtc::read_secs();
         self.update();
         Ok(size-offset)
     }
@@ -528,6 +627,10 @@ impl InodeData {
         stat.itype = self.dinode.itype;
         stat.nlink = self.dinode.nlink;
         stat.size = self.dinode.size as u64;
+        stat.mode = self.dinode.mode;
+        stat.uid = self.dinode.uid;
+        stat.gid = self.dinode.gid;
+        stat.mtime = self.dinode.mtime;
     }
 
     /// Given the relevant nth data block of this inode.
@@ -607,7 +710,7 @@ impl InodeData {
 
     /// Write a new [`DirEntry`] into this inode, whose type must be directory.
     /// LTODO - Panics if `inum` is larger than u16::MAX.
-    pub fn dir_link(&mut self, name: &[u8; MAX_DIR_SIZE], inum: u32) -> Result<(), ()> {
+    pub fn dir_link(&mut self, name: &[u8; MAX_DIR_SIZE], inum: u32) -> Result<(), Errno> {
         if inum > u16::MAX as u32 {
             panic!("inum {} too large", inum);
         }
@@ -616,7 +719,7 @@ impl InodeData {
         // the entry should not be present
         if self.dir_lookup(name, false).is_some() {
             // auto drop the returned inode
-            return Err(())
+            return Err(Errno::EEXIST)
         }
 
         // allocate a dir entry
@@ -647,10 +750,10 @@ impl InodeData {
     /// Also remove its entry in the directory.
     /// Panics if the inode data is not directory.
     /// It must be called within a log transaction.
-    pub fn dir_unlink(&mut self, name: &[u8; MAX_DIR_SIZE]) -> Result<(), ()> {
+    pub fn dir_unlink(&mut self, name: &[u8; MAX_DIR_SIZE]) -> Result<(), Errno> {
         // the name should not be . and ..
         if name[0] == b'.' && (name[1] == 0 || (name[1] == b'.' && name[2] == 0)) {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
 
         // lookup the entry correspond to the name
@@ -661,7 +764,7 @@ impl InodeData {
                 inode = i;
                 offset = off;
             },
-            _ => return Err(()),
+            _ => return Err(Errno::ENOENT),
         }
 
         // check the entry
@@ -670,7 +773,7 @@ impl InodeData {
             panic!("entry inode's link is zero");
         }
         if idata.dinode.itype == InodeType::Directory && !idata.dir_is_empty() {
-            return Err(())
+            return Err(Errno::ENOTEMPTY)
         }
 
         // empty the entry
@@ -732,6 +835,8 @@ pub fn icheck() {
 
     debug_assert_eq!(mem::align_of::<BufData>() % mem::align_of::<DirEntry>(), 0);
 
+    debug_assert_eq!(BSIZE % mem::size_of::<DiskInode>(), 0);
+
     debug_assert!(MAX_FILE_SIZE <= u32::MAX as usize);
 }
 
@@ -745,6 +850,10 @@ pub struct FileStat {
     itype: InodeType,
     nlink: u16,
     size: u64,
+    mode: u16,
+    uid: u16,
+    gid: u16,
+    mtime: u32,
 }
 
 impl FileStat {
@@ -755,6 +864,10 @@ impl FileStat {
             itype: InodeType::Empty,
             nlink: 0,
             size: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
         }
     }
 }
@@ -772,8 +885,18 @@ pub struct DiskInode {
     minor: u16,
     /// Hard links to this inode.
     nlink: u16,
+    /// Permission bits, rwx for owner, group and others.
+    mode: u16,
+    /// Owner user id.
+    uid: u16,
+    /// Owner group id.
+    gid: u16,
+    /// Padding to keep the size a divisor of [`BSIZE`].
+    pad: u16,
     /// Size of actual data/content of this inode.
     size: u32,
+    /// Last modification time, in seconds since the epoch.
+    mtime: u32,
     /// Data address.
     addrs: [u32; NDIRECT + 1],
 }
@@ -785,7 +908,12 @@ impl DiskInode {
             major: 0,
             minor: 0,
             nlink: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            pad: 0,
             size: 0,
+            mtime: 0,
             addrs: [0; NDIRECT + 1],
         }
     }
@@ -811,6 +939,8 @@ pub enum InodeType {
     Directory = 1,
     File = 2,
     Device = 3,
+    Fifo = 4,
+    Socket = 5,
 }
 
 /// Directory entry in the disk.
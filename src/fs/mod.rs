@@ -6,6 +6,7 @@ mod log;
 mod bio;
 mod block;
 mod superblock;
+mod poll;
 
 // TODO - Buf also could?
 pub use bio::Buf;
@@ -13,7 +14,8 @@ pub use bio::Buf;
 pub use bio::BCACHE;
 pub use inode::{ICACHE, Inode, InodeData, InodeType, FileStat};
 pub use log::LOG;
-pub use file::{File, Pipe};
+pub use file::{File, Pipe, Socket};
+pub use poll::{PollFd, poll, poll_notify, poll_tick};
 
 use superblock::SUPER_BLOCK;
 use log::Log;
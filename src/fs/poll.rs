@@ -0,0 +1,165 @@
+//! Waiting for several files at once, used by poll
+//!
+//! A file that may block is identified by a key,
+//! which its pipe or device notifies with [`poll_notify`] whenever it may become ready.
+//! A poller registers the keys before checking the files,
+//! so that a change between the check and its sleep is not missed.
+
+use alloc::sync::Arc;
+
+use crate::consts::{NPROC, fs::{NFILE, POLLERR, POLLHUP, POLLNVAL}};
+use crate::errno::Errno;
+use crate::process::{PROC_MANAGER, Proc};
+use crate::spinlock::SpinLock;
+use crate::trap::clock_read;
+
+use super::File;
+
+/// A file descriptor to poll.
+/// Its layout should be the same as `struct pollfd` in the user space.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PollFd {
+    pub fd: i32,
+    /// events wanted
+    pub events: i16,
+    /// events ready, filled by poll
+    pub revents: i16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PollState {
+    Waiting,
+    Notified,
+    TimedOut,
+}
+
+#[derive(Clone, Copy)]
+struct Poller {
+    keys: [usize; 2*NFILE],
+    nkeys: usize,
+    /// tick when the polling times out
+    deadline: Option<usize>,
+    state: PollState,
+}
+
+impl Poller {
+    /// Check if the deadline has come at tick `now`, which may have wrapped around.
+    fn expired(&self, now: usize) -> bool {
+        match self.deadline {
+            Some(deadline) => now.wrapping_sub(deadline) as isize >= 0,
+            None => false,
+        }
+    }
+}
+
+/// Pollers, indexed by the process index.
+/// The address of each entry is the channel the process sleeps on.
+static POLLERS: SpinLock<[Option<Poller>; NPROC]> = SpinLock::new([None; NPROC], "poll");
+
+/// Wait until any of the `files` is ready for the events wanted in `fds`,
+/// or for at most `timeout` ticks if it is not negative.
+/// `files` is in accordance with `fds`, None for an fd not opened.
+/// Fill the ready events and return the number of the ready fds, 0 if timed out.
+pub fn poll(p: &Proc, fds: &mut [PollFd], files: &[Option<Arc<File>>], timeout: i32) -> Result<usize, Errno> {
+    let mut poller = Poller {
+        keys: [0; 2*NFILE],
+        nkeys: 0,
+        deadline: None,
+        state: PollState::Waiting,
+    };
+    for file in files.iter().flatten() {
+        for key in file.poll_keys().iter().flatten() {
+            poller.keys[poller.nkeys] = *key;
+            poller.nkeys += 1;
+        }
+    }
+
+    let i = p.index();
+    let channel;
+    {
+        let mut pollers = POLLERS.lock();
+        // read the ticks with the lock held, so that no tick is missed by poll_tick
+        if timeout > 0 {
+            poller.deadline = Some(clock_read().wrapping_add(timeout as usize));
+        }
+        channel = &pollers[i] as *const Option<Poller> as usize;
+        pollers[i] = Some(poller);
+    }
+
+    let ret = loop {
+        let count = check(fds, files);
+        if count > 0 || timeout == 0 {
+            break Ok(count)
+        }
+
+        let mut pollers = POLLERS.lock();
+        let state = loop {
+            match pollers[i].unwrap().state {
+                PollState::Waiting => {},
+                state => break Ok(state),
+            }
+            if p.interrupted() {
+                break Err(Errno::EINTR)
+            }
+            p.sleep_interruptible(channel, pollers);
+            pollers = POLLERS.lock();
+        };
+        match state {
+            Ok(PollState::TimedOut) => break Ok(0),
+            Ok(_) => pollers[i].as_mut().unwrap().state = PollState::Waiting,
+            Err(e) => break Err(e),
+        }
+    };
+    POLLERS.lock()[i] = None;
+    ret
+}
+
+/// Fill the ready events of each fd, and return the number of the ready ones.
+fn check(fds: &mut [PollFd], files: &[Option<Arc<File>>]) -> usize {
+    let mut count = 0;
+    for (fd, file) in fds.iter_mut().zip(files.iter()) {
+        fd.revents = if fd.fd < 0 {
+            0
+        } else {
+            match file {
+                Some(file) => file.poll() & (fd.events | POLLERR | POLLHUP),
+                None => POLLNVAL,
+            }
+        };
+        if fd.revents != 0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Wake up the pollers waiting on `key`,
+/// called by a pipe or device when it may become ready.
+pub fn poll_notify(key: usize) {
+    let mut pollers = POLLERS.lock();
+    for poller in pollers.iter_mut() {
+        match poller {
+            Some(pl) if pl.state == PollState::Waiting && pl.keys[..pl.nkeys].contains(&key) => {
+                pl.state = PollState::Notified;
+                unsafe { PROC_MANAGER.wakeup(poller as *const Option<Poller> as usize); }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Time out the pollers whose deadlines have come, called on every tick
+/// without holding the ticks lock.
+pub fn poll_tick(now: usize) {
+    let mut pollers = POLLERS.lock();
+    for poller in pollers.iter_mut() {
+        match poller {
+            Some(pl) if pl.state == PollState::Waiting && pl.expired(now) => {
+                pl.state = PollState::TimedOut;
+                unsafe { PROC_MANAGER.wakeup(poller as *const Option<Poller> as usize); }
+            },
+            _ => {},
+        }
+    }
+}
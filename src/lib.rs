@@ -27,6 +27,7 @@ global_asm!(include_str!("asm/trampoline.S"));
 mod printf;
 
 mod consts;
+mod errno;
 mod fs;
 mod mm;
 mod process;
@@ -38,6 +39,7 @@ mod start;
 mod trap;
 mod driver;
 mod plic;
+mod timer;
 
 #[cfg(feature = "unit_test")]
 fn test_main_entry() {
@@ -2,8 +2,8 @@ use core::convert::{TryFrom, Into};
 use core::mem;
 
 use crate::consts::{
-    CLINT, CLINT_MAP_SIZE, KERNBASE, PHYSTOP, PLIC, PLIC_MAP_SIZE, UART0, UART0_MAP_SIZE, VIRTIO0,
-    VIRTIO0_MAP_SIZE, TRAMPOLINE, PGSIZE
+    CLINT, CLINT_MAP_SIZE, KERNBASE, PHYSTOP, PLIC, PLIC_MAP_SIZE, RTC0, RTC0_MAP_SIZE, UART0, UART0_MAP_SIZE,
+    VIRTIO0, VIRTIO0_MAP_SIZE, TRAMPOLINE, PGSIZE
 };
 use crate::register::satp;
 use super::{Addr, PageTable, PhysAddr, PteFlag, VirtAddr, RawSinglePage, RawDoublePage, RawQuadPage};
@@ -42,6 +42,14 @@ pub unsafe fn kvm_init() {
         PteFlag::R | PteFlag::W,
     );
 
+    // goldfish rtc
+    kvm_map(
+        VirtAddr::from(RTC0),
+        PhysAddr::from(RTC0),
+        RTC0_MAP_SIZE,
+        PteFlag::R | PteFlag::W,
+    );
+
     // CLINT
     kvm_map(
         VirtAddr::from(CLINT),
@@ -2,17 +2,19 @@ use alloc::boxed::Box;
 use core::{alloc::AllocError, ptr};
 
 use crate::consts::PGSIZE;
+use crate::errno::Errno;
 use crate::process::CPU_MANAGER;
 
 pub use addr::{Addr, PhysAddr, VirtAddr};
 pub use kvm::{kvm_init, kvm_init_hart, kvm_map, kvm_pa};
-pub use pagetable::{PageTable, PteFlag};
+pub use pagetable::{PageTable, PteFlag, put_user_page};
 pub use kalloc::{KernelHeap, KERNEL_HEAP};
 
 mod addr;
 pub mod kalloc;
 mod kvm;
 mod pagetable;
+mod pageref;
 mod list;
 
 /// Used to alloc pages-sized and page-aligned memory.
@@ -95,7 +97,7 @@ impl Address {
 
     /// Copy content from src to this Virtual/Kernel address.
     /// Copy `count` bytes in total.
-    pub fn copy_out(self, src: *const u8, count: usize) -> Result<(), ()> {
+    pub fn copy_out(self, src: *const u8, count: usize) -> Result<(), Errno> {
         match self {
             Self::Virtual(dst) => {
                 let p = unsafe { CPU_MANAGER.my_proc() };
@@ -113,7 +115,7 @@ impl Address {
 
     /// Copy content from this Virtual/Kernel address to dst.
     /// Copy `count` bytes in total.
-    pub fn copy_in(self, dst: *mut u8, count: usize) -> Result<(), ()> {
+    pub fn copy_in(self, dst: *mut u8, count: usize) -> Result<(), Errno> {
         match self {
             Self::Virtual(src) => {
                 let p = unsafe { CPU_MANAGER.my_proc() };
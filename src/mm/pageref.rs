@@ -0,0 +1,60 @@
+//! Reference counts of physical pages mapped into user space
+//!
+//! A page freshly handed out by the kernel heap has no record here,
+//! which means it is exclusively owned by a single mapping.
+//! Pages shared by copy-on-write fork record how many mappings refer to them.
+
+use core::convert::Into;
+
+use crate::consts::{KERNBASE, PHYSTOP, PGSIZE, NPHYSPAGE};
+use crate::spinlock::SpinLock;
+use super::{Addr, PhysAddr};
+
+pub static PAGE_REF: PageRef = PageRef::new();
+
+pub struct PageRef(SpinLock<[u16; NPHYSPAGE]>);
+
+impl PageRef {
+    const fn new() -> Self {
+        Self(SpinLock::new([0; NPHYSPAGE], "page ref"))
+    }
+
+    /// Record one more mapping of the physical page.
+    pub fn share(&self, pa: PhysAddr) {
+        let i = index(pa);
+        let mut refs = self.0.lock();
+        if refs[i] == u16::MAX {
+            panic!("page ref: too many mappings of pa={:#x}", pa.as_usize());
+        }
+        refs[i] = if refs[i] == 0 { 2 } else { refs[i] + 1 };
+        drop(refs);
+    }
+
+    /// Drop one mapping of the physical page.
+    /// Return true if it is the last mapping, so the caller should free the page.
+    pub fn put(&self, pa: PhysAddr) -> bool {
+        let i = index(pa);
+        let mut refs = self.0.lock();
+        let last = refs[i] <= 1;
+        refs[i] = match refs[i] {
+            0 | 1 | 2 => 0,
+            n => n - 1,
+        };
+        drop(refs);
+        last
+    }
+
+    /// Check if the physical page is mapped more than once.
+    pub fn is_shared(&self, pa: PhysAddr) -> bool {
+        self.0.lock()[index(pa)] > 1
+    }
+}
+
+#[inline]
+fn index(pa: PhysAddr) -> usize {
+    let pa = pa.as_usize();
+    if pa < Into::<usize>::into(KERNBASE) || pa >= Into::<usize>::into(PHYSTOP) {
+        panic!("page ref: pa={:#x} out of range", pa);
+    }
+    (pa - Into::<usize>::into(KERNBASE)) / PGSIZE
+}
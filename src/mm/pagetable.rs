@@ -5,7 +5,9 @@ use core::{cmp::min, convert::TryFrom};
 use core::ptr;
 
 use crate::consts::{PGSHIFT, PGSIZE, SATP_SV39, SV39FLAGLEN, USERTEXT, TRAMPOLINE, TRAPFRAME};
+use crate::errno::Errno;
 use super::{Addr, PhysAddr, RawPage, RawSinglePage, VirtAddr, pg_round_up};
+use super::pageref::PAGE_REF;
 
 bitflags! {
     pub struct PteFlag: usize {
@@ -18,6 +20,8 @@ bitflags! {
         const A = 1 << 6;
         const D = 1 << 7;
         const RSW = 0b11 << 8;
+        /// copy-on-write page, using the first bit reserved for software
+        const COW = 1 << 8;
     }
 }
 
@@ -49,6 +53,39 @@ impl PageTableEntry {
         (self.data & (PteFlag::U.bits())) > 0
     }
 
+    #[inline]
+    fn is_writable(&self) -> bool {
+        (self.data & (PteFlag::W.bits())) > 0
+    }
+
+    #[inline]
+    fn is_executable(&self) -> bool {
+        (self.data & (PteFlag::X.bits())) > 0
+    }
+
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        (self.data & (PteFlag::D.bits())) > 0
+    }
+
+    /// Mark the page accessed and dirty, as the hardware does on a user store,
+    /// when the kernel writes into it on behalf of the user.
+    #[inline]
+    fn set_dirty(&mut self) {
+        self.data |= (PteFlag::A | PteFlag::D).bits();
+    }
+
+    #[inline]
+    fn is_cow(&self) -> bool {
+        (self.data & (PteFlag::COW.bits())) > 0
+    }
+
+    /// Turn a writable page into a read-only copy-on-write page.
+    #[inline]
+    fn set_cow(&mut self) {
+        self.data = (self.data & !PteFlag::W.bits()) | PteFlag::COW.bits();
+    }
+
     #[inline]
     fn clear_user(&mut self) {
         self.data &= !PteFlag::U.bits()
@@ -221,6 +258,7 @@ impl PageTable {
 
     /// Same as [`walk_addr`], except that it gives out a physical address
     /// that the data it points to can be mutated.
+    /// If the page is copy-on-write, it is resolved before handing out.
     pub fn walk_addr_mut(&mut self, va: VirtAddr)
         -> Result<PhysAddr, &'static str>
     {
@@ -230,7 +268,13 @@ impl PageTable {
                     Err("pte not valid")
                 } else if !pte.is_user() {
                     Err("pte not mapped for user")
+                } else if pte.is_cow() {
+                    self.uvm_cow(va)
+                } else if !pte.is_writable() {
+                    Err("pte not writable")
                 } else {
+                    // a shared mapping is written back only if dirty
+                    pte.set_dirty();
                     Ok(pte.as_phys_addr())
                 }
             }
@@ -321,7 +365,8 @@ impl PageTable {
     /// Grow the user's usable memory size from old size to new size by
     /// allocating new physical memory and PTEs in the pagetable.
     /// Old size is typically zero or kept by the process.
-    pub fn uvm_alloc(&mut self, old_size: usize, new_size: usize) -> Result<usize, ()> {
+    /// The new pages are mapped with `perm`, which should include [`PteFlag::U`].
+    pub fn uvm_alloc(&mut self, old_size: usize, new_size: usize, perm: PteFlag) -> Result<usize, Errno> {
         if new_size <= old_size {
             return Ok(old_size)
         }
@@ -331,21 +376,21 @@ impl PageTable {
             match unsafe { RawSinglePage::try_new_zeroed() } {
                 Err(_) => {
                     self.uvm_dealloc(cur_size, old_size);
-                    return Err(())
+                    return Err(Errno::ENOMEM)
                 },
                 Ok(mem) => {
                     match self.map_pages(
                         unsafe { VirtAddr::from_raw(cur_size) },
                         PGSIZE, 
                         unsafe { PhysAddr::from_raw(mem as usize) }, 
-                        PteFlag::R | PteFlag::W | PteFlag::X | PteFlag::U
+                        perm
                     ) {
                         Err(s) => {
                             #[cfg(feature = "kernel_warning")]
                             println!("kernel warning: uvm_alloc occurs {}", s);
                             unsafe { RawSinglePage::from_raw_and_drop(mem); }
                             self.uvm_dealloc(cur_size, old_size);
-                            return Err(())
+                            return Err(Errno::ENOMEM)
                         },
                         Ok(_) => {
                             // the mem raw pointer is leaked
@@ -384,22 +429,40 @@ impl PageTable {
         }
 
         for ca in (va..(va+PGSIZE*count)).step_by(PGSIZE) {
-            let pte = self.walk_mut(unsafe {VirtAddr::from_raw(ca)})
-                                        .expect("unable to find va available");
-            if !pte.is_valid() {
-                panic!("this pte is not valid");
-            }
+            // lazily allocated pages may have not been mapped yet
+            let pte = match self.walk_mut(unsafe {VirtAddr::from_raw(ca)}) {
+                Some(pte) if pte.is_valid() => pte,
+                _ => continue,
+            };
             if !pte.is_leaf() {
                 panic!("this pte is not a leaf");
             }
             if freeing {
-                let pa = pte.as_phys_addr();
-                unsafe { RawSinglePage::from_raw_and_drop(pa.into_raw() as *mut u8); }
+                put_user_page(pte.as_phys_addr());
             }
             pte.write_zero();
         }
     }
 
+    /// Add permissions to the user page mapped at `va`,
+    /// typically when two program segments share the page.
+    /// Note: `va` must be page aligned.
+    pub fn uvm_add_perm(&mut self, va: usize, perm: PteFlag) {
+        let pte = self.walk_mut(VirtAddr::try_from(va).unwrap())
+                                                .expect("cannot find available pte");
+        let pa = pte.as_phys_addr();
+        let perm = pte.read_perm() | perm;
+        pte.write_perm(pa, perm);
+    }
+
+    /// Check if the user page at `va` is mapped executable.
+    pub fn uvm_executable(&self, va: VirtAddr) -> bool {
+        match self.walk(va) {
+            Some(pte) => pte.is_valid() && pte.is_user() && pte.is_executable(),
+            None => false,
+        }
+    }
+
     /// Explicitly mark a pte invalid for user.
     /// Typically used for the guard page.
     pub fn uvm_clear(&mut self, va: usize) {
@@ -408,35 +471,131 @@ impl PageTable {
         pte.clear_user();
     }
 
-    /// Copy the user page table to another process,
+    /// Copy the user page table within [start, end) to another process,
     /// typically its child process.
-    pub fn uvm_copy(&mut self, child_pgt: &mut Self, size: usize) -> Result<(), ()> {
-        for i in (0..size).step_by(PGSIZE) {
+    /// Physical pages are shared instead of copied.
+    /// If not `shared`, writable pages become read-only copy-on-write pages in both page tables,
+    /// so that the memory is copied lazily when either side stores to it.
+    /// Note: `start` must be page aligned.
+    pub fn uvm_copy(&mut self, child_pgt: &mut Self, start: usize, end: usize, shared: bool)
+        -> Result<(), Errno>
+    {
+        for i in (start..end).step_by(PGSIZE) {
             let va = unsafe { VirtAddr::from_raw(i) };
-            let pte = self.walk(va).expect("pte not exist");
-            let mem = unsafe { pte.try_clone() };
-            if let Ok(mem) = mem {
-                let perm = pte.read_perm();
-                if child_pgt.map_pages(va, PGSIZE,
-                    unsafe { PhysAddr::from_raw(mem as usize) }, perm).is_ok()
-                {
-                    continue
+            // lazily allocated pages not touched yet are left for the child to fault in
+            let pte = match self.walk_mut(va) {
+                Some(pte) if pte.is_valid() => pte,
+                _ => continue,
+            };
+            if !shared && pte.is_writable() {
+                pte.set_cow();
+            }
+            let pa = pte.as_phys_addr();
+            let perm = pte.read_perm();
+            if child_pgt.map_pages(va, PGSIZE, pa, perm).is_err() {
+                child_pgt.uvm_unmap(start, (i-start)/PGSIZE, true);
+                return Err(Errno::ENOMEM)
+            }
+            PAGE_REF.share(pa);
+        }
+        Ok(())
+    }
+
+    /// Handle a page fault at the user virtual address `va`,
+    /// which the caller has checked to be within the user's memory.
+    /// A store to a copy-on-write page is resolved by [`uvm_cow`].
+    /// Faults on a page not accessible for user, e.g. the guard page, are errors.
+    /// Return false if the page is not mapped yet, see [`uvm_map_new`].
+    pub fn uvm_fault(&mut self, mut va: VirtAddr, store: bool) -> Result<bool, &'static str> {
+        va.pg_round_down();
+        match self.walk(va) {
+            Some(pte) if pte.is_valid() => {
+                if !pte.is_user() {
+                    Err("pte not mapped for user")
+                } else if store && pte.is_cow() {
+                    self.uvm_cow(va).map(|_| true)
+                } else if store && !pte.is_writable() {
+                    Err("pte not writable")
+                } else {
+                    Ok(true)
                 }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Map a newly allocated page `mem` at the user virtual address `va`,
+    /// unless another thread sharing this page table has mapped one there in the meantime.
+    /// The page is freed if it ends up not mapped.
+    pub fn uvm_map_new(&mut self, mut va: VirtAddr, mem: *mut u8, perm: PteFlag)
+        -> Result<(), &'static str>
+    {
+        va.pg_round_down();
+        if let Some(pte) = self.walk(va) {
+            if pte.is_valid() {
                 unsafe { RawSinglePage::from_raw_and_drop(mem); }
+                return Ok(())
             }
-            child_pgt.uvm_unmap(0, i/PGSIZE, true);
-            return Err(())
         }
-        Ok(())
+        let ret = self.map_pages(va, PGSIZE, unsafe { PhysAddr::from_raw(mem as usize) }, perm);
+        if ret.is_err() {
+            unsafe { RawSinglePage::from_raw_and_drop(mem); }
+        }
+        ret
+    }
+
+    /// Return the physical address of the user page at `va`,
+    /// if it is mapped and has been written since mapped.
+    /// The page is pinned so that it stays alive even if unmapped,
+    /// and the caller should release it by [`put_user_page`].
+    pub fn pin_dirty_page(&self, va: VirtAddr) -> Option<PhysAddr> {
+        match self.walk(va) {
+            Some(pte) if pte.is_valid() && pte.is_user() && pte.is_dirty() => {
+                let pa = pte.as_phys_addr();
+                PAGE_REF.share(pa);
+                Some(pa)
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve a copy-on-write page at the virtual address `va`.
+    /// If the page is still shared, copy it to a newly allocated page,
+    /// otherwise it is only referred by this page table and just made writable again.
+    /// Return the physical address that is now writable.
+    pub fn uvm_cow(&mut self, mut va: VirtAddr) -> Result<PhysAddr, &'static str> {
+        va.pg_round_down();
+        let pte = self.walk_mut(va).ok_or("va not mapped")?;
+        if !pte.is_valid() || !pte.is_user() {
+            return Err("pte not valid for user")
+        }
+        if !pte.is_cow() {
+            return Err("pte not copy-on-write")
+        }
+
+        let pa = pte.as_phys_addr();
+        let perm = (pte.read_perm() - PteFlag::COW) | PteFlag::W;
+        if PAGE_REF.is_shared(pa) {
+            let mem = unsafe { pte.try_clone() }
+                .map_err(|_| "not enough memory for copy-on-write page")?;
+            let new_pa = unsafe { PhysAddr::from_raw(mem as usize) };
+            pte.write_perm(new_pa, perm);
+            put_user_page(pa);
+            Ok(new_pa)
+        } else {
+            pte.write_perm(pa, perm);
+            Ok(pa)
+        }
     }
 
     /// Copy a null-terminated string from virtual address starting at srcva,
     /// to a kernel u8 slice.
+    /// Fail with [`Errno::ENAMETOOLONG`] if the string does not fit in the slice.
     pub fn copy_in_str(&self, srcva: usize, dst: &mut [u8])
-        -> Result<(), &'static str>
+        -> Result<(), Errno>
     {
         let mut i: usize = 0;
-        let mut va = VirtAddr::try_from(srcva)?;
+        let mut va = VirtAddr::try_from(srcva).map_err(|_| Errno::EFAULT)?;
 
         // iterate through the raw content page by page
         while i < dst.len() {
@@ -444,7 +603,7 @@ impl PageTable {
             base.pg_round_down();
             let distance = (va - base).as_usize();
             let mut pa_ptr = unsafe {
-                self.walk_addr(base)?
+                self.walk_addr(base).map_err(|_| Errno::EFAULT)?
                     .as_ptr()
                     .offset(distance as isize)
             };
@@ -469,19 +628,19 @@ impl PageTable {
             va = base;
         }
 
-        Err("copy_in_str: dst not enough space")
+        Err(Errno::ENAMETOOLONG)
     }
 
     /// Copy content from src to the user's dst virtual address.
     /// Copy `count` bytes in total.
     pub fn copy_out(&mut self, mut src: *const u8, mut dst: usize, mut count: usize)
-        -> Result<(), ()>
+        -> Result<(), Errno>
     {
         if count == 0 {
             return Ok(())
         }
 
-        let mut va = VirtAddr::try_from(dst).map_err(|_| ())?;
+        let mut va = VirtAddr::try_from(dst).map_err(|_| Errno::EFAULT)?;
         va.pg_round_down();
         loop {
             let mut pa;
@@ -490,7 +649,7 @@ impl PageTable {
                 Err(s) => {
                     #[cfg(feature = "kernel_warning")]
                     println!("kernel warning: {} when pagetable copy_out", s);
-                    return Err(())
+                    return Err(Errno::EFAULT)
                 }
             }
             let off = dst - va.as_usize();
@@ -513,9 +672,9 @@ impl PageTable {
     /// Copy content from user's src virtual address to dst.
     /// Copy `count` bytes in total.
     pub fn copy_in(&self, mut src: usize, mut dst: *mut u8, mut count: usize)
-        -> Result<(), ()>
+        -> Result<(), Errno>
     {
-        let mut va = VirtAddr::try_from(src).unwrap();
+        let mut va = VirtAddr::try_from(src).map_err(|_| Errno::EFAULT)?;
         va.pg_round_down();
 
         if count == 0 {
@@ -524,7 +683,7 @@ impl PageTable {
                 Err(s) => {
                     #[cfg(feature = "kernel_warning")]
                     println!("kernel warning: {} when pagetable copy_in", s);
-                    return Err(())
+                    return Err(Errno::EFAULT)
                 }
             }
         }
@@ -536,7 +695,7 @@ impl PageTable {
                 Err(s) => {
                     #[cfg(feature = "kernel_warning")]
                     println!("kernel warning: {} when pagetable copy_in", s);
-                    return Err(())
+                    return Err(Errno::EFAULT)
                 }
             }
             let off = src - va.as_usize();
@@ -557,6 +716,14 @@ impl PageTable {
     }
 }
 
+/// Drop one mapping of a user physical page,
+/// and free it if no page table refers to it anymore.
+pub fn put_user_page(pa: PhysAddr) {
+    if PAGE_REF.put(pa) {
+        unsafe { RawSinglePage::from_raw_and_drop(pa.into_raw() as *mut u8); }
+    }
+}
+
 impl Drop for PageTable {
     /// Recursively free non-first-level pagetables.
     /// Physical memory should already be freed.
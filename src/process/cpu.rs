@@ -75,7 +75,7 @@ impl CpuManager {
             sstatus::intr_on();
 
             // use ProcManager to find a runnable process
-            match PROC_MANAGER.alloc_runnable() {
+            match PROC_MANAGER.alloc_runnable(Self::cpu_id()) {
                 Some(p) => {
                     c.proc = p as *mut _;
                     let mut guard = p.excl.lock();
@@ -90,7 +90,7 @@ impl CpuManager {
                     c.proc = ptr::null_mut();
                     drop(guard);
                 },
-                None => {},
+                None => PROC_MANAGER.idle(Self::cpu_id()),
             }
         }
     }
@@ -0,0 +1,117 @@
+//! Futexes for user-space synchronization
+//!
+//! A futex is keyed by the physical address of the user word,
+//! so that processes sharing the page wait on the same futex,
+//! wherever it is mapped in their user spaces.
+
+use core::ptr;
+
+use crate::consts::NPROC;
+use crate::errno::Errno;
+use crate::spinlock::SpinLock;
+use crate::trap::clock_read;
+
+use super::{PROC_MANAGER, Proc};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WaitState {
+    Waiting,
+    Woken,
+    TimedOut,
+}
+
+#[derive(Clone, Copy)]
+struct Waiter {
+    /// physical address of the user word
+    pa: usize,
+    /// tick when the waiting times out
+    deadline: Option<usize>,
+    state: WaitState,
+}
+
+impl Waiter {
+    /// Check if the deadline has come at tick `now`, which may have wrapped around.
+    fn expired(&self, now: usize) -> bool {
+        match self.deadline {
+            Some(deadline) => now.wrapping_sub(deadline) as isize >= 0,
+            None => false,
+        }
+    }
+}
+
+/// Waiters on the futexes, indexed by the process index.
+/// The address of each entry is the channel the process sleeps on,
+/// so that a wake only wakes up the wanted number of waiters.
+static WAITERS: SpinLock<[Option<Waiter>; NPROC]> = SpinLock::new([None; NPROC], "futex");
+
+/// Sleep on the futex at `pa` if the user word still holds `val`,
+/// until woken by [`futex_wake`], or for at most `timeout` ticks if it is not 0.
+/// The word is checked with the lock held,
+/// so a wake after changing the word is not missed.
+pub fn futex_wait(p: &Proc, pa: usize, val: u32, timeout: usize) -> Result<(), Errno> {
+    let mut waiters = WAITERS.lock();
+    // the user page is directly mapped in the kernel
+    if unsafe { ptr::read_volatile(pa as *const u32) } != val {
+        return Err(Errno::EAGAIN)
+    }
+    // read the ticks with the lock held, so that no tick is missed by futex_tick
+    let deadline = if timeout == 0 {
+        None
+    } else {
+        Some(clock_read().wrapping_add(timeout))
+    };
+    let i = p.index();
+    let channel = &waiters[i] as *const Option<Waiter> as usize;
+    waiters[i] = Some(Waiter { pa, deadline, state: WaitState::Waiting });
+
+    let ret = loop {
+        match waiters[i].unwrap().state {
+            WaitState::Woken => break Ok(()),
+            WaitState::TimedOut => break Err(Errno::ETIMEDOUT),
+            WaitState::Waiting => {},
+        }
+        if p.interrupted() {
+            break Err(Errno::EINTR)
+        }
+        p.sleep_interruptible(channel, waiters);
+        waiters = WAITERS.lock();
+    };
+    waiters[i] = None;
+    ret
+}
+
+/// Wake up at most `count` waiters on the futex at `pa`.
+/// Return the number of waiters woken up.
+pub fn futex_wake(pa: usize, count: usize) -> usize {
+    let mut waiters = WAITERS.lock();
+    let mut woken = 0;
+    for waiter in waiters.iter_mut() {
+        if woken == count {
+            break
+        }
+        match waiter {
+            Some(w) if w.pa == pa && w.state == WaitState::Waiting => {
+                w.state = WaitState::Woken;
+                woken += 1;
+                unsafe { PROC_MANAGER.wakeup(waiter as *const Option<Waiter> as usize); }
+            },
+            _ => {},
+        }
+    }
+    woken
+}
+
+/// Time out the waiters whose deadlines have come, called on every tick
+/// without holding the ticks lock.
+pub fn futex_tick(now: usize) {
+    let mut waiters = WAITERS.lock();
+    for waiter in waiters.iter_mut() {
+        match waiter {
+            Some(w) if w.state == WaitState::Waiting && w.expired(now) => {
+                w.state = WaitState::TimedOut;
+                unsafe { PROC_MANAGER.wakeup(waiter as *const Option<Waiter> as usize); }
+            },
+            _ => {},
+        }
+    }
+}
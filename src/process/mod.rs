@@ -1,27 +1,35 @@
 use array_macro::array;
 
+use alloc::sync::Arc;
 use core::convert::TryFrom;
 use core::ptr;
 use core::mem;
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::consts::{NPROC, PGSIZE, TRAMPOLINE, fs::ROOTDEV};
-use crate::mm::{kvm_map, PhysAddr, PteFlag, VirtAddr, RawPage, RawSinglePage, PageTable, RawQuadPage};
+use crate::consts::{NCPU, NSMP, NPROC, PGSIZE, TRAMPOLINE, TRAPFRAME, NICE_MIN, NICE_MAX, fs::ROOTDEV, signal::SIGKILL, WNOHANG, WUNTRACED};
+use crate::errno::Errno;
+use crate::mm::{kvm_map, PhysAddr, PteFlag, VirtAddr, RawPage, RawSinglePage, RawQuadPage};
+use crate::register::{self, clint, sstatus};
 use crate::spinlock::SpinLock;
 use crate::trap::user_trap_ret;
 use crate::fs;
+use crate::driver::console;
 
 pub use cpu::{CPU_MANAGER, CpuManager};
 pub use cpu::{push_off, pop_off};
 pub use proc::Proc;
+pub use futex::{futex_wait, futex_wake, futex_tick};
 
 mod context;
 mod proc;
 mod cpu;
 mod trapframe;
+mod runqueue;
+mod futex;
 
 use context::Context;
-use proc::ProcState;
+use proc::{ProcExcl, ProcState, UserSpace};
+use runqueue::RunQueue;
 use trapframe::TrapFrame;
 
 // no lock to protect PROC_MANAGER, i.e.,
@@ -36,6 +44,10 @@ pub struct ProcManager {
     parents: SpinLock<[Option<usize>; NPROC]>,
     init_proc: usize,
     pid: SpinLock<usize>,
+    /// runnable procs of each cpu, always locked after the proc's [`ProcExcl`]
+    run_queues: [SpinLock<RunQueue>; NCPU],
+    /// bitmap of the cpus waiting for interrupts in [`ProcManager::idle`]
+    idle_cpus: AtomicUsize,
 }
 
 impl ProcManager {
@@ -45,6 +57,8 @@ impl ProcManager {
             parents: SpinLock::new(array![_ => None; NPROC], "proc parents"),
             init_proc: 0,
             pid: SpinLock::new(0, "pid"),
+            run_queues: array![_ => SpinLock::new(RunQueue::new(), "run queue"); NCPU],
+            idle_cpus: AtomicUsize::new(0),
         }
     }
 
@@ -80,14 +94,16 @@ impl ProcManager {
     /// Look in the process table for an UNUSED proc.
     /// If found, initialize state required to run in the kernel,
     /// and return without its ProcExcl held.
+    /// If `shared` is some, the new proc is a thread running in the given user space,
+    /// otherwise a new user space is allocated.
     /// Note: The returned [`Proc`] is in [`ProcState::ALLOCATED`].
     /// LTODO - Should recover from OOM?
-    fn alloc_proc(&mut self) ->
+    fn alloc_proc(&mut self, shared: Option<&Arc<SpinLock<UserSpace>>>) ->
         Option<&mut Proc>
     {
         let new_pid = self.alloc_pid();
 
-        for p in self.table.iter_mut() {
+        for (pos, p) in self.table.iter_mut().enumerate() {
             let mut guard = p.excl.lock();
             match guard.state {
                 ProcState::UNUSED => {
@@ -98,16 +114,33 @@ impl ProcManager {
                     // alloc trapframe
                     pd.tf = unsafe { RawSinglePage::try_new_zeroed().ok()? as *mut TrapFrame };
 
-                    debug_assert!(pd.pagetable.is_none());
-                    match PageTable::alloc_proc_pagetable(pd.tf as usize) {
-                        Some(pgt) => pd.pagetable = Some(pgt),
+                    // map trapframe in the user space
+                    let space = match shared {
+                        Some(space) => {
+                            let tf_va = thread_trapframe(pos);
+                            match space.lock().attach(tf_va, pd.tf as usize) {
+                                Ok(()) => Some((Arc::clone(space), tf_va)),
+                                Err(_) => None,
+                            }
+                        },
+                        None => UserSpace::new(pd.tf as usize)
+                            .and_then(|space| Arc::try_new(SpinLock::new(space, "user space")).ok())
+                            .map(|space| (space, TRAPFRAME.into())),
+                    };
+                    match space {
+                        Some((space, tf_va)) => pd.set_space(space, tf_va),
                         None => {
                             unsafe { RawSinglePage::from_raw_and_drop(pd.tf as *mut u8); }
+                            pd.tf = ptr::null_mut();
                             return None
                         },
                     }
                     pd.init_context();
                     guard.pid = new_pid;
+                    guard.tgid = new_pid;
+                    guard.pgid = new_pid;
+                    guard.sid = new_pid;
+                    guard.reset_level();
                     guard.state = ProcState::ALLOCATED;
 
                     drop(guard);
@@ -120,38 +153,100 @@ impl ProcManager {
         None
     }
 
-    /// Look in the process table for an RUNNABLE proc,
+    /// Put a RUNNABLE proc into a run queue, with its [`ProcExcl`] held.
+    /// It goes back to the cpu it last ran on if allowed,
+    /// otherwise to the first cpu in its affinity.
+    /// The cpu is woken up by an IPI if it is idle.
+    fn enqueue(&self, index: usize, excl: &ProcExcl) {
+        debug_assert_eq!(excl.state, ProcState::RUNNABLE);
+        let cpu = if excl.affinity & (1 << excl.cpu) != 0 {
+            excl.cpu
+        } else {
+            excl.affinity.trailing_zeros() as usize
+        };
+        let mut rq = self.run_queues[cpu].lock();
+        rq.push(index, excl.level, excl.base_level(), excl.affinity);
+        drop(rq);
+        let this_cpu = unsafe { CpuManager::cpu_id() };
+        if cpu != this_cpu && self.idle_cpus.load(Ordering::SeqCst) & (1 << cpu) != 0 {
+            unsafe { clint::send_ipi(cpu); }
+        }
+    }
+
+    /// Wait for an interrupt on the cpu that finds nothing to run or steal.
+    /// The cpu is marked idle before the run queues are checked again with interrupts off,
+    /// so that a proc enqueued after the check sends an IPI,
+    /// which ends the wfi even if it is raised before.
+    pub fn idle(&self, cpu: usize) {
+        let allowed = 1 << cpu;
+        sstatus::intr_off();
+        self.idle_cpus.fetch_or(allowed, Ordering::SeqCst);
+        let runnable = (0..NSMP).any(|i| self.run_queues[i].lock().has(allowed));
+        if !runnable {
+            register::wfi();
+        }
+        self.idle_cpus.fetch_and(!allowed, Ordering::SeqCst);
+        sstatus::intr_on();
+    }
+
+    /// Take a RUNNABLE proc at the highest priority level from the cpu's run queue,
     /// set its state to ALLOCATED and return without the proc's lock held.
+    /// If the run queue is empty, steal one from the other cpus.
     /// Typically used in each cpu's scheduler
-    fn alloc_runnable(&mut self) ->
+    fn alloc_runnable(&mut self, cpu: usize) ->
         Option<&mut Proc>
     {
-        for p in self.table.iter_mut() {
-            let mut guard = p.excl.lock();
-            match guard.state {
-                ProcState::RUNNABLE => {
-                    guard.state = ProcState::ALLOCATED;
-                    drop(guard);
-                    return Some(p)
-                },
-                _ => {
-                    drop(guard);
-                },
+        let allowed = 1 << cpu;
+        loop {
+            let i = (0..NSMP)
+                .map(|off| (cpu + off) % NSMP)
+                .find_map(|victim| {
+                    let mut rq = self.run_queues[victim].lock();
+                    let i = if rq.len() > 0 { rq.pop(allowed) } else { None };
+                    drop(rq);
+                    i
+                })?;
+
+            let mut guard = self.table[i].excl.lock();
+            if guard.state != ProcState::RUNNABLE {
+                panic!("alloc_runnable(): proc in run queue is not runnable");
             }
+            if guard.affinity & allowed == 0 {
+                // affinity changed after it was queued, look for another one
+                self.enqueue(i, &guard);
+                continue;
+            }
+            guard.state = ProcState::ALLOCATED;
+            guard.cpu = cpu;
+            drop(guard);
+            return Some(&mut self.table[i])
         }
+    }
 
-        None
+    /// Move every process back to its base level,
+    /// so that the demoted ones would not starve.
+    pub fn boost(&self) {
+        for p in self.table.iter() {
+            let mut guard = p.excl.lock();
+            if guard.state != ProcState::UNUSED {
+                guard.reset_level();
+            }
+            drop(guard);
+        }
+        for rq in self.run_queues.iter() {
+            rq.lock().boost();
+        }
     }
 
     /// Set up first process.
     /// SAFETY: Only called once by the initial hart,
     /// which can guarantee the init proc's index at table is 0.
     pub unsafe fn user_init(&mut self) {
-        let p = self.alloc_proc()
+        let p = self.alloc_proc(None)
             .expect("all process should be unused");
         p.user_init();
         let mut guard = p.excl.lock();
-        guard.state = ProcState::RUNNABLE;
+        p.set_runnable(&mut guard);
     }
 
     /// Check if the given process is the init_proc 
@@ -165,7 +260,9 @@ impl ProcManager {
         for p in self.table.iter() {
             let mut guard = p.excl.lock();
             if guard.state == ProcState::SLEEPING && guard.channel == channel {
-                guard.state = ProcState::RUNNABLE;
+                // ahead of the cpu-bound ones
+                guard.reset_level();
+                p.set_runnable(&mut guard);
             }
             drop(guard);
         }
@@ -179,13 +276,35 @@ impl ProcManager {
         drop(guard);
     }
 
+    /// Number of the children of the process at `pi`, including the zombies and threads.
+    fn children(&self, pi: usize) -> usize {
+        self.parents.lock().iter().filter(|&&parent| parent == Some(pi)).count()
+    }
+
     /// Put a process to exit, does not return.
     fn exiting(&self, exit_pi: usize, exit_status: i32) {
         if exit_pi == self.init_proc {
             panic!("init process exiting");
         }
 
-        unsafe { self.table[exit_pi].data.get().as_mut().unwrap().close_files(); }
+        unsafe {
+            let exit_pdata = self.table[exit_pi].data.get().as_mut().unwrap();
+            exit_pdata.release_space();
+            exit_pdata.close_files();
+        }
+
+        // The other threads exit along with the process.
+        let exit_pexcl = self.table[exit_pi].excl.lock();
+        let (exit_pid, exit_tgid, exit_sid) = (exit_pexcl.pid, exit_pexcl.tgid, exit_pexcl.sid);
+        drop(exit_pexcl);
+        if exit_pid == exit_tgid {
+            self.kill_group(exit_tgid, exit_pi);
+        }
+
+        // The console is no longer controlled by the session after its leader exits.
+        if exit_pid == exit_sid {
+            console::release_session(exit_sid);
+        }
 
         let mut parent_map = self.parents.lock();
 
@@ -205,6 +324,10 @@ impl ProcManager {
         }
         let exit_parenti = *parent_map[exit_pi].as_ref().unwrap();
         self.wakeup(&self.table[exit_parenti] as *const Proc as usize);
+        if exit_pid != exit_tgid {
+            // wake up the thread joining it
+            self.wakeup(&self.table[exit_pi] as *const Proc as usize);
+        }
 
         let mut exit_pexcl = self.table[exit_pi].excl.lock();
         exit_pexcl.exit_status = exit_status;
@@ -218,64 +341,397 @@ impl ProcManager {
         unreachable!("exiting {}", exit_pi);
     }
 
-    /// Wait for a child process to exit/ZOMBIE.
-    /// Return the child's pid if any, return `Err(())` if none. 
-    fn waiting(&self, pi: usize, addr: usize) -> Result<usize, ()> {
+    /// Stop the process at `pi` by the signal `sig`,
+    /// and wake up its parent, which may be waiting for it with [`WUNTRACED`].
+    /// Return when it is continued, or if it is killed/continued before stopped.
+    fn stopping(&self, pi: usize, sig: usize) {
+        let p = &self.table[pi];
+        let parent_map = self.parents.lock();
+        if let Some(parenti) = parent_map[pi] {
+            self.wakeup(&self.table[parenti] as *const Proc as usize);
+        }
+
+        // hold the parents lock until it is stopped,
+        // so that the parent sees it after woken up
+        let mut excl = p.excl.lock();
+        if p.killed.load(Ordering::Relaxed) || excl.sig.cont_pending() {
+            return
+        }
+        excl.state = ProcState::STOPPED;
+        excl.stop_sig = sig;
+        drop(parent_map);
+        unsafe {
+            let ctx = p.data.get().as_mut().unwrap().get_context();
+            excl = CPU_MANAGER.my_cpu_mut().sched(excl, ctx);
+        }
+        drop(excl);
+    }
+
+    /// Wait for a child process in `target` to exit/ZOMBIE,
+    /// or to stop if [`WUNTRACED`] is set in `options`.
+    /// Threads in the same thread group are not waited, they should be joined.
+    /// Return the child's pid and how it changed, return [`Errno::ECHILD`] if none.
+    /// Return `None` if [`WNOHANG`] is set and no child has changed yet.
+    /// A zombie child is recycled here, so the caller should not fail after it.
+    fn waiting(&self, pi: usize, target: WaitTarget, options: i32)
+        -> Result<Option<(usize, WaitStatus)>, Errno>
+    {
         let mut parent_map = self.parents.lock();
-        let p = unsafe { CPU_MANAGER.my_proc() };
-        let pdata = unsafe { p.data.get().as_mut().unwrap() };
+        let p = &self.table[pi];
+        let tgid = p.excl.lock().tgid;
 
         loop {
             let mut have_child = false;
             for i in 0..NPROC {
-                if parent_map[i].is_none() || *parent_map[i].as_ref().unwrap() != pi {
+                if parent_map[i] != Some(pi) {
                     continue;
                 }
 
                 let mut child_excl = self.table[i].excl.lock();
-                have_child = true;
-                if child_excl.state != ProcState::ZOMBIE {
+                if child_excl.tgid == tgid || !target.matches(&child_excl) {
                     continue;
                 }
+                have_child = true;
                 let child_pid = child_excl.pid;
-                if addr != 0 && pdata.copy_out(&child_excl.exit_status as *const _ as *const u8,
-                    addr, mem::size_of_val(&child_excl.exit_status)).is_err()
-                {
-                    return Err(())
+                match child_excl.state {
+                    ProcState::ZOMBIE => {
+                        let status = if child_excl.term_sig != 0 {
+                            WaitStatus::Killed(child_excl.term_sig)
+                        } else {
+                            WaitStatus::Exited(child_excl.exit_status)
+                        };
+                        parent_map[i].take();
+                        self.table[i].killed.store(false, Ordering::Relaxed);
+                        let child_data = unsafe { self.table[i].data.get().as_mut().unwrap() };
+                        child_data.cleanup();
+                        child_excl.cleanup();
+                        return Ok(Some((child_pid, status)))
+                    },
+                    ProcState::STOPPED if options & WUNTRACED != 0 && child_excl.stop_sig != 0 => {
+                        // only reported once for each stop
+                        let sig = mem::replace(&mut child_excl.stop_sig, 0);
+                        return Ok(Some((child_pid, WaitStatus::Stopped(sig))))
+                    },
+                    _ => {},
+                }
+            }
+
+            if !have_child {
+                return Err(Errno::ECHILD)
+            }
+            if options & WNOHANG != 0 {
+                return Ok(None)
+            }
+            if p.interrupted() {
+                return Err(Errno::EINTR)
+            }
+
+            // have children, but none of them changed
+            let channel = p as *const Proc as usize;
+            p.sleep_interruptible(channel, parent_map);
+            parent_map = self.parents.lock();
+        }
+    }
+
+    /// Wait for a thread with given tid in the same thread group to exit.
+    /// Return the thread's tid, return [`Errno::ESRCH`] if there is no such thread.
+    fn joining(&self, pi: usize, tid: usize, addr: usize) -> Result<usize, Errno> {
+        let mut parent_map = self.parents.lock();
+        let p = &self.table[pi];
+        let pdata = unsafe { p.data.get().as_mut().unwrap() };
+        let tgid = p.excl.lock().tgid;
+
+        loop {
+            let mut found = None;
+            for i in 0..NPROC {
+                if i == pi || parent_map[i].is_none() {
+                    continue;
+                }
+                let excl = self.table[i].excl.lock();
+                if excl.pid == tid && excl.tgid == tgid && excl.pid != excl.tgid {
+                    found = Some((i, excl));
+                    break;
+                }
+            }
+            let (i, mut thread_excl) = found.ok_or(Errno::ESRCH)?;
+
+            if thread_excl.state == ProcState::ZOMBIE {
+                if addr != 0 {
+                    pdata.copy_out(&thread_excl.exit_status as *const _ as *const u8,
+                        addr, mem::size_of_val(&thread_excl.exit_status))?;
                 }
                 parent_map[i].take();
                 self.table[i].killed.store(false, Ordering::Relaxed);
-                let child_data = unsafe { self.table[i].data.get().as_mut().unwrap() };
-                child_data.cleanup();
-                child_excl.cleanup();           
-                return Ok(child_pid)
+                let thread_data = unsafe { self.table[i].data.get().as_mut().unwrap() };
+                thread_data.cleanup();
+                thread_excl.cleanup();
+                return Ok(tid)
             }
+            drop(thread_excl);
 
-            if !have_child || p.killed.load(Ordering::Relaxed) {
-                return Err(())
+            if p.interrupted() {
+                return Err(Errno::EINTR)
             }
 
-            // have children, but none of them exit
-            let channel = p as *const Proc as usize;
+            // the thread is still running
+            let channel = &self.table[i] as *const Proc as usize;
+            p.sleep_interruptible(channel, parent_map);
+            parent_map = self.parents.lock();
+        }
+    }
+
+    /// Kill the other threads in the thread group of the process at `pi`,
+    /// wait for them to exit and recycle them, typically when exec.
+    /// Return [`Errno::EINTR`] if the process itself is killed meanwhile.
+    fn kill_threads(&self, pi: usize) -> Result<(), Errno> {
+        let p = &self.table[pi];
+        let tgid = p.excl.lock().tgid;
+        self.kill_group(tgid, pi);
+
+        let mut parent_map = self.parents.lock();
+        loop {
+            let mut running = None;
+            for i in 0..NPROC {
+                if i == pi || parent_map[i].is_none() {
+                    continue;
+                }
+                let mut thread_excl = self.table[i].excl.lock();
+                if thread_excl.tgid != tgid {
+                    continue;
+                }
+                if thread_excl.state == ProcState::ZOMBIE {
+                    parent_map[i].take();
+                    self.table[i].killed.store(false, Ordering::Relaxed);
+                    let thread_data = unsafe { self.table[i].data.get().as_mut().unwrap() };
+                    thread_data.cleanup();
+                    thread_excl.cleanup();
+                } else {
+                    running = Some(i);
+                }
+            }
+            let i = match running {
+                Some(i) => i,
+                None => return Ok(()),
+            };
+
+            if p.killed.load(Ordering::Relaxed) {
+                return Err(Errno::EINTR)
+            }
+
+            // woken up when the thread exits, as if joining it
+            let channel = &self.table[i] as *const Proc as usize;
             p.sleep(channel, parent_map);
             parent_map = self.parents.lock();
         }
     }
 
-    /// Kill a process with given pid.
-    pub fn kill(&self, pid: usize) -> Result<(), ()> {
+    /// Kill the threads in the given thread group, except the one at `except`.
+    fn kill_group(&self, tgid: usize, except: usize) {
+        for i in 0..NPROC {
+            if i == except {
+                continue;
+            }
+            let mut guard = self.table[i].excl.lock();
+            if guard.tgid == tgid && guard.state != ProcState::UNUSED {
+                self.table[i].post_signal(&mut guard, SIGKILL);
+            }
+        }
+    }
+
+    /// Set the nice value of a process with given pid,
+    /// which also moves it to the new base level.
+    pub fn set_priority(&self, pid: usize, nice: i32) -> Result<(), Errno> {
+        if nice < NICE_MIN || nice > NICE_MAX {
+            return Err(Errno::EINVAL)
+        }
+        for i in 0..NPROC {
+            let mut guard = self.table[i].excl.lock();
+            if guard.pid == pid && guard.state != ProcState::UNUSED {
+                guard.nice = nice;
+                guard.reset_level();
+                return Ok(())
+            }
+        }
+
+        Err(Errno::ESRCH)
+    }
+
+    /// Set the cpus allowed to run a process with given pid, as a bitmask of hart ids.
+    /// A queued process is moved when a cpu picks it up, see [`alloc_runnable`].
+    pub fn set_affinity(&self, pid: usize, mask: usize) -> Result<(), Errno> {
+        let mask = mask & ((1 << NSMP) - 1);
+        if mask == 0 {
+            return Err(Errno::EINVAL)
+        }
+        for i in 0..NPROC {
+            let mut guard = self.table[i].excl.lock();
+            if guard.pid == pid && guard.state != ProcState::UNUSED {
+                guard.affinity = mask;
+                return Ok(())
+            }
+        }
+
+        Err(Errno::ESRCH)
+    }
+
+    /// Send a signal to a process with given pid.
+    /// A zero signal only checks if the process exists.
+    pub fn kill(&self, pid: usize, sig: usize) -> Result<(), Errno> {
         for i in 0..NPROC {
             let mut guard = self.table[i].excl.lock();
             if guard.pid == pid {
-                self.table[i].killed.store(true, Ordering::Relaxed);
-                if guard.state == ProcState::SLEEPING {
-                    guard.state = ProcState::RUNNABLE;
+                if sig != 0 {
+                    self.table[i].post_signal(&mut guard, sig);
                 }
                 return Ok(())
             }
         }
 
-        Err(())
+        Err(Errno::ESRCH)
+    }
+
+    /// Send a signal to the processes in the process group.
+    /// Threads are not signaled on their own, the signal goes to their process.
+    pub fn kill_pgrp(&self, pgid: usize, sig: usize) -> Result<(), Errno> {
+        let mut found = false;
+        for i in 0..NPROC {
+            let mut guard = self.table[i].excl.lock();
+            if guard.pgid == pgid && guard.pid == guard.tgid
+                && guard.state != ProcState::UNUSED && guard.state != ProcState::ZOMBIE
+            {
+                found = true;
+                if sig != 0 {
+                    self.table[i].post_signal(&mut guard, sig);
+                }
+            }
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(Errno::ESRCH)
+        }
+    }
+
+    /// Check if there is any process in the process group within the session.
+    pub fn pgrp_in_session(&self, pgid: usize, sid: usize) -> bool {
+        self.table.iter().any(|p| {
+            let guard = p.excl.lock();
+            guard.pgid == pgid && guard.sid == sid
+                && guard.state != ProcState::UNUSED && guard.state != ProcState::ZOMBIE
+        })
+    }
+
+    /// Move the process with given pid into the process group `pgid`,
+    /// on behalf of the process at `pi`.
+    /// The process should be the caller itself or its child in the same session,
+    /// and the group should be a new one led by the process or exist in the session.
+    fn set_pgid(&self, pi: usize, pid: usize, pgid: usize) -> Result<(), Errno> {
+        let parent_map = self.parents.lock();
+        let sid = self.table[pi].excl.lock().sid;
+        let ti = (0..NPROC)
+            .find(|&i| {
+                let guard = self.table[i].excl.lock();
+                guard.pid == pid && guard.pid == guard.tgid && guard.state != ProcState::UNUSED
+            })
+            .filter(|&i| i == pi || parent_map[i] == Some(pi))
+            .ok_or(Errno::ESRCH)?;
+
+        let guard = self.table[ti].excl.lock();
+        if guard.sid != sid || guard.sid == guard.pid {
+            return Err(Errno::EPERM)
+        }
+        drop(guard);
+        if pgid != pid && !self.pgrp_in_session(pgid, sid) {
+            return Err(Errno::EPERM)
+        }
+        self.table[ti].excl.lock().pgid = pgid;
+        drop(parent_map);
+        Ok(())
+    }
+
+    /// Get the process group id of the process with given pid.
+    pub fn get_pgid(&self, pid: usize) -> Result<usize, Errno> {
+        for i in 0..NPROC {
+            let guard = self.table[i].excl.lock();
+            if guard.pid == pid && guard.state != ProcState::UNUSED {
+                return Ok(guard.pgid)
+            }
+        }
+
+        Err(Errno::ESRCH)
+    }
+
+    /// Create a new session led by the process at `pi`,
+    /// which is also the leader of a new process group.
+    /// Return the new session id.
+    fn set_sid(&self, pi: usize) -> Result<usize, Errno> {
+        let parent_map = self.parents.lock();
+        let pid = self.table[pi].excl.lock().pid;
+        let leader = self.table.iter().any(|p| {
+            let guard = p.excl.lock();
+            guard.pgid == pid && guard.state != ProcState::UNUSED
+        });
+        if leader {
+            return Err(Errno::EPERM)
+        }
+        let mut guard = self.table[pi].excl.lock();
+        guard.sid = pid;
+        guard.pgid = pid;
+        drop(guard);
+        drop(parent_map);
+        Ok(pid)
+    }
+}
+
+/// Children to wait for.
+#[derive(Clone, Copy)]
+pub enum WaitTarget {
+    Any,
+    Pid(usize),
+    /// children in the process group
+    Group(usize),
+}
+
+impl WaitTarget {
+    fn matches(&self, excl: &ProcExcl) -> bool {
+        match *self {
+            Self::Any => true,
+            Self::Pid(pid) => excl.pid == pid,
+            Self::Group(pgid) => excl.pgid == pgid,
+        }
+    }
+}
+
+/// How a waited child changed.
+#[derive(Clone, Copy, Debug)]
+pub enum WaitStatus {
+    /// exited with the status
+    Exited(i32),
+    /// terminated by the signal
+    Killed(usize),
+    /// stopped by the signal
+    Stopped(usize),
+}
+
+impl WaitStatus {
+    /// Encode into the status word of waitpid,
+    /// which is decoded by the macros in include/wait.h.
+    pub fn encode(self) -> i32 {
+        match self {
+            Self::Exited(status) => (status & 0xff) << 8,
+            Self::Killed(sig) => sig as i32 & 0x7f,
+            Self::Stopped(sig) => (sig as i32) << 8 | 0x7f,
+        }
+    }
+
+    /// The raw status of the old wait,
+    /// where a killed child is seen as exiting with -1.
+    pub fn raw(self) -> i32 {
+        match self {
+            Self::Exited(status) => status,
+            Self::Killed(_) | Self::Stopped(_) => -1,
+        }
     }
 }
 
@@ -306,3 +762,10 @@ unsafe fn fork_ret() -> ! {
 fn kstack(pos: usize) -> usize {
     Into::<usize>::into(TRAMPOLINE) - (pos + 1) * 5 * PGSIZE
 }
+
+/// Where a thread's trapframe is mapped in the shared user space,
+/// one page for each process slot below the TRAPFRAME.
+#[inline]
+fn thread_trapframe(pos: usize) -> usize {
+    Into::<usize>::into(TRAPFRAME) - (pos + 1) * PGSIZE
+}
@@ -0,0 +1,92 @@
+//! Periodic user alarms, set by sigalarm
+
+use core::ptr;
+
+use crate::consts::signal::SIG_SETMASK;
+
+use super::{Proc, TrapFrame};
+
+/// Alarm state of a process, only accessed by itself.
+/// It is not inherited by the children, and cleared by exec.
+pub struct Alarm {
+    /// ticks of cpu time in the user space between two alarms, 0 if disabled
+    interval: usize,
+    /// user handler, which should call sigreturn when done
+    handler: usize,
+    /// ticks since the last alarm
+    ticks: usize,
+    /// user registers and blocked mask when the handler is called,
+    /// present until sigreturn, so that the handler is not re-entered
+    saved: Option<(TrapFrame, u32)>,
+}
+
+impl Alarm {
+    pub const fn new() -> Self {
+        Self {
+            interval: 0,
+            handler: 0,
+            ticks: 0,
+            saved: None,
+        }
+    }
+
+    /// Start calling the handler every interval ticks, or stop if interval is 0.
+    /// A handler already running is still returned from by sigreturn.
+    pub fn set(&mut self, interval: usize, handler: usize) {
+        self.interval = interval;
+        self.handler = handler;
+        self.ticks = 0;
+    }
+
+    /// Clean up when the process is freed.
+    pub fn cleanup(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Proc {
+    /// Count a timer tick taken in the user space,
+    /// and redirect the user to the alarm handler when the interval elapses.
+    /// The handler runs with all the signals blocked.
+    pub fn alarm_tick(&mut self) {
+        let pdata = self.data.get_mut();
+        let alarm = &mut pdata.alarm;
+        if alarm.interval == 0 || alarm.saved.is_some() {
+            return
+        }
+        alarm.ticks += 1;
+        if alarm.ticks < alarm.interval {
+            return
+        }
+        alarm.ticks = 0;
+
+        let tf = unsafe { pdata.tf.as_mut().unwrap() };
+        let blocked = self.excl.lock().sig.set_blocked(SIG_SETMASK, Some(!0)).unwrap();
+        alarm.saved = Some((unsafe { ptr::read(tf) }, blocked));
+        tf.epc = alarm.handler;
+    }
+
+    /// Restore the user registers and blocked mask saved by [`Proc::alarm_tick`],
+    /// if the alarm handler is running.
+    /// Return the restored a0, which is then written back by the syscall path.
+    pub fn alarm_return(&mut self) -> Option<usize> {
+        let pdata = self.data.get_mut();
+        let (saved, blocked) = pdata.alarm.saved.take()?;
+        let tf = unsafe { pdata.tf.as_mut().unwrap() };
+
+        // the kernel part of the trapframe is set again when returning to the user
+        *tf = saved;
+        self.excl.lock().sig.set_blocked(SIG_SETMASK, Some(blocked)).unwrap();
+        Some(tf.a0)
+    }
+
+    /// Stop the alarm when exec, since the handler is gone with the old user space.
+    /// The blocked mask from before the handler is kept if it is running.
+    pub fn alarm_reset(&mut self) {
+        let alarm = &mut self.data.get_mut().alarm;
+        if let Some((_, blocked)) = alarm.saved.take() {
+            self.excl.lock().sig.set_blocked(SIG_SETMASK, Some(blocked)).unwrap();
+        }
+        alarm.cleanup();
+    }
+}
@@ -1,31 +1,243 @@
-//! ELF loader
+//! ELF loader, which also runs scripts by their `#!` interpreters
 
 use alloc::boxed::Box;
-use alloc::str;
+use alloc::sync::Arc;
 use core::{cmp::min, convert::TryFrom, mem::{self, MaybeUninit}};
 
-use crate::{consts::{MAXARGLEN, PGSIZE, MAXARG}, sleeplock::SleepLockGuard};
-use crate::mm::{Address, PageTable, Addr, VirtAddr, pg_round_up};
+use crate::{consts::{ARG_MAX, MAXINTERP, MAXPATH, MAXSTACK, PGSIZE, RLIMIT_STACK, STACKGAP, TRAPFRAME, fs::MAY_EXEC}, sleeplock::SleepLockGuard};
+use crate::errno::Errno;
+use crate::mm::{Address, PageTable, PteFlag, VirtAddr, pg_round_down, pg_round_up};
 use crate::fs::{ICACHE, Inode, LOG, InodeData};
+use crate::register::clint;
+use crate::spinlock::SpinLock;
 
-use super::Proc;
+use crate::process::PROC_MANAGER;
+
+use super::{Proc, UserSpace};
+
+/// Arguments and environment strings of exec, copied from the old user space.
+/// They are packed one after another with their ending nuls, arguments first.
+/// The total size, including the pointers to them, is limited to [`ARG_MAX`],
+/// but there is no limit on a single string.
+pub struct ExecArgs {
+    buf: Box<[u8; ARG_MAX]>,
+    /// bytes used in buf
+    len: usize,
+    argc: usize,
+    envc: usize,
+}
+
+impl ExecArgs {
+    pub fn new() -> Result<Self, Errno> {
+        let buf = Box::<[u8; ARG_MAX]>::try_new_zeroed().map_err(|_| Errno::ENOMEM)?;
+        Ok(Self {
+            buf: unsafe { buf.assume_init() },
+            len: 0,
+            argc: 0,
+            envc: 0,
+        })
+    }
+
+    /// Bytes taken by the pointers of `count` strings,
+    /// plus the null pointers ending argv and envp.
+    #[inline]
+    fn ptrs_size(count: usize) -> usize {
+        (count + 2) * mem::size_of::<usize>()
+    }
+
+    /// Append an argument, or an environment string if `env` is true.
+    /// `copy` should copy the nul-terminated string into the given free space,
+    /// and fail with [`Errno::ENAMETOOLONG`] if it does not fit.
+    /// The arguments should all be appended before the environment.
+    pub fn push<F>(&mut self, env: bool, copy: F) -> Result<(), Errno>
+        where F: FnOnce(&mut [u8]) -> Result<(), Errno>
+    {
+        debug_assert!(env || self.envc == 0);
+        let used = self.len + Self::ptrs_size(self.argc + self.envc + 1);
+        if used >= ARG_MAX {
+            return Err(Errno::E2BIG)
+        }
+        let free = &mut self.buf[self.len..self.len + ARG_MAX - used];
+        copy(free).map_err(|e| if e == Errno::ENAMETOOLONG { Errno::E2BIG } else { e })?;
+        self.len += free.iter().position(|&c| c == 0).unwrap() + 1;
+        if env {
+            self.envc += 1;
+        } else {
+            self.argc += 1;
+        }
+        Ok(())
+    }
+
+    /// Append a kernel string, which should not contain nul.
+    fn push_str(&mut self, env: bool, s: &[u8]) -> Result<(), Errno> {
+        self.push(env, |free| {
+            if s.len() >= free.len() {
+                return Err(Errno::ENAMETOOLONG)
+            }
+            free[..s.len()].copy_from_slice(s);
+            free[s.len()] = 0;
+            Ok(())
+        })
+    }
+
+    /// Replace argv[0] with the strings in `head`, keeping the rest and the environment.
+    fn replace_argv0(&mut self, head: &[&[u8]]) -> Result<(), Errno> {
+        let mut new = Self::new()?;
+        for s in head {
+            new.push_str(false, s)?;
+        }
+        let (skip, kept) = if self.argc > 0 {
+            (self.buf.iter().position(|&c| c == 0).unwrap() + 1, self.argc - 1)
+        } else {
+            (0, 0)
+        };
+        let rest = self.len - skip;
+        if new.len + rest + Self::ptrs_size(new.argc + kept + self.envc) > ARG_MAX {
+            return Err(Errno::E2BIG)
+        }
+        new.buf[new.len..new.len + rest].copy_from_slice(&self.buf[skip..self.len]);
+        new.len += rest;
+        new.argc += kept;
+        new.envc = self.envc;
+        *self = new;
+        Ok(())
+    }
+}
+
+/// Execute the file at `path` with the arguments and environment in `args`.
+/// If it is a script starting with `#!interpreter [arg]`,
+/// the interpreter is executed instead, with argv being
+/// the interpreter, the optional arg, the script path and the original argv[1..].
+/// The interpreter can be a script too, nested up to [`MAXINTERP`] levels.
+pub fn exec(p: &mut Proc, path: &mut [u8; MAXPATH], args: &mut ExecArgs) -> Result<usize, Errno> {
+    let mut depth = 0;
+    loop {
+        let script = match Shebang::read(&path[..])? {
+            Some(script) => script,
+            None => return load(p, &path[..], args),
+        };
+        if depth == MAXINTERP {
+            return Err(Errno::ELOOP)
+        }
+        depth += 1;
+
+        let interp = cstr(&script.interp);
+        let script_path = cstr(&path[..]);
+        match script.arg {
+            Some(ref arg) => args.replace_argv0(&[interp, cstr(arg), script_path])?,
+            None => args.replace_argv0(&[interp, script_path])?,
+        }
+        *path = script.interp;
+    }
+}
+
+/// The bytes before the ending nul.
+fn cstr(s: &[u8]) -> &[u8] {
+    let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    &s[..len]
+}
+
+/// Interpreter line of a script, i.e., `#!interpreter [arg]`.
+/// The arg is the rest of the line after the interpreter, if any.
+/// Both are nul-terminated.
+struct Shebang {
+    interp: [u8; MAXPATH],
+    arg: Option<[u8; MAXPATH]>,
+}
+
+impl Shebang {
+    /// Read the interpreter line if the file at `path` is a script.
+    /// The whole line should fit in [`MAXPATH`] bytes,
+    /// and it ends at the end of file if there is no newline.
+    fn read(path: &[u8]) -> Result<Option<Self>, Errno> {
+        let mut buf = [0u8; MAXPATH];
+        LOG.begin_op();
+        let inode = match ICACHE.namei(path) {
+            Ok(i) => i,
+            Err(e) => {
+                LOG.end_op();
+                return Err(e)
+            },
+        };
+        let mut idata = inode.lock();
+        if let Err(e) = idata.permit(MAY_EXEC) {
+            drop(idata); drop(inode); LOG.end_op();
+            return Err(e)
+        }
+        let ret = idata.try_iread(Address::KernelMut(buf.as_mut_ptr()), 0, MAXPATH as u32);
+        drop(idata); drop(inode); LOG.end_op();
+        let n = ret? as usize;
+        if n < 2 || &buf[..2] != b"#!" {
+            return Ok(None)
+        }
+
+        let line = &buf[2..n];
+        let end = match line.iter().position(|&c| c == b'\n') {
+            Some(end) => end,
+            None if n < MAXPATH => line.len(),
+            None => return Err(Errno::ENOEXEC),
+        };
+        let line = trim(&line[..end]);
+        let split = line.iter().position(|&c| c == b' ' || c == b'\t').unwrap_or(line.len());
+        if split == 0 {
+            return Err(Errno::ENOEXEC)
+        }
+        let mut interp = [0u8; MAXPATH];
+        interp[..split].copy_from_slice(&line[..split]);
+        let arg = trim(&line[split..]);
+        let arg = if arg.is_empty() {
+            None
+        } else {
+            let mut buf = [0u8; MAXPATH];
+            buf[..arg.len()].copy_from_slice(arg);
+            Some(buf)
+        };
+        Ok(Some(Self { interp, arg }))
+    }
+}
+
+/// Strip the leading and trailing blanks.
+fn trim(s: &[u8]) -> &[u8] {
+    let blank = |c: &u8| *c == b' ' || *c == b'\t' || *c == b'\r';
+    let start = s.iter().position(|c| !blank(c)).unwrap_or(s.len());
+    let end = s.iter().rposition(|c| !blank(c)).map_or(start, |i| i + 1);
+    &s[start..end]
+}
 
 /// Load an elf executable into the process's user space.
-pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) -> Result<usize, &'static str> {
+/// The arguments and environment are laid out on the new user stack with the auxv,
+/// see [`setup_stack`]. The stack is at the top of the loaded image, below the heap.
+/// Exec is allowed only in the main thread of a process, i.e., the one whose pid is the tgid,
+/// and fails with EBUSY in any other thread, which never takes over the process.
+/// When the main thread execs, the other threads are killed once the executable is loaded,
+/// and the process continues alone in a new user space.
+pub fn load(p: &mut Proc, path: &[u8], args: &ExecArgs) -> Result<usize, Errno> {
+    let excl = p.excl.lock();
+    let main_thread = excl.pid == excl.tgid;
+    drop(excl);
+    if !main_thread {
+        return Err(Errno::EBUSY)
+    }
+    let pi = p.index();
+
     // get relevant inode using path
     let inode: Inode;
     LOG.begin_op();
     match ICACHE.namei(path) {
-        Some(i) => inode = i,
-        None => {
+        Ok(i) => inode = i,
+        Err(e) => {
             LOG.end_op();
-            return Err("cannot name inode")
+            return Err(e)
         },
     }
 
-    // check elf header
+    // check execute permission and elf header
     // create a new empty pagetable, but not assign yet
     let mut idata = inode.lock();
+    if let Err(e) = idata.permit(MAY_EXEC) {
+        drop(idata); drop(inode); LOG.end_op();
+        return Err(e)
+    }
     let mut elf = MaybeUninit::<ElfHeader>::uninit();
     if idata.iread(
         Address::KernelMut(elf.as_mut_ptr() as *mut u8),
@@ -33,12 +245,12 @@ pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) ->
         mem::size_of::<ElfHeader>() as u32
     ).is_err() {
         drop(idata); drop(inode); LOG.end_op();
-        return Err("cannot read elf inode")
+        return Err(Errno::ENOEXEC)
     }
     let elf = unsafe { elf.assume_init() };
     if elf.magic != ELF_MAGIC {
         drop(idata); drop(inode); LOG.end_op();
-        return Err("bad elf magic number")
+        return Err(Errno::ENOEXEC)
     }
 
     // allocate new pagetable, not assign to proc yet
@@ -48,10 +260,12 @@ pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) ->
         Some(p) => pgt = p,
         None => {
             drop(idata); drop(inode); LOG.end_op();
-            return Err("mem not enough")
+            return Err(Errno::ENOMEM)
         },
     }
     let mut proc_size = 0usize;
+    // where the program headers are loaded, if any
+    let mut phdr = None;
 
     // load each program section
     let ph_size = mem::size_of::<ProgHeader>() as u32;
@@ -61,7 +275,7 @@ pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) ->
         if idata.iread(Address::KernelMut(ph.as_mut_ptr() as *mut u8), off, ph_size).is_err() {
             pgt.dealloc_proc_pagetable(proc_size);
             drop(pgt); drop(idata); drop(inode); LOG.end_op();
-            return Err("cannot read elf program header")
+            return Err(Errno::ENOEXEC)
         }
         let ph = unsafe { ph.assume_init() };
         
@@ -70,25 +284,36 @@ pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) ->
             continue;
         }
 
-        if ph.memsz < ph.filesz || ph.vaddr + ph.memsz < ph.vaddr || ph.vaddr % (PGSIZE as u64) != 0 {
+        // segments should be in ascending order without overlapping,
+        // but may start and end in the middle of a page
+        if ph.memsz < ph.filesz || ph.vaddr + ph.memsz < ph.vaddr || ph.vaddr < proc_size as u64 {
             pgt.dealloc_proc_pagetable(proc_size);
             drop(pgt); drop(idata); drop(inode); LOG.end_op();
-            return Err("one program header meta not correct")
+            return Err(Errno::ENOEXEC)
         }
 
-        match pgt.uvm_alloc(proc_size, (ph.vaddr + ph.memsz) as usize) {
+        // the first page may be shared with the previous segment
+        let perm = ph.perm();
+        let start = pg_round_down(ph.vaddr as usize);
+        if start < pg_round_up(proc_size) {
+            pgt.uvm_add_perm(start, perm);
+        }
+        match pgt.uvm_alloc(proc_size, (ph.vaddr + ph.memsz) as usize, perm) {
             Ok(cur_size) => proc_size = cur_size,
-            Err(_) => {
+            Err(e) => {
                 pgt.dealloc_proc_pagetable(proc_size);
                 drop(pgt); drop(idata); drop(inode); LOG.end_op();
-                return Err("not enough uvm for program header")
+                return Err(e)
             }
         }
 
         if load_seg(pgt.as_mut(), ph.vaddr as usize, &mut idata, ph.off as u32, ph.filesz as u32).is_err() {
             pgt.dealloc_proc_pagetable(proc_size);
             drop(pgt); drop(idata); drop(inode); LOG.end_op();
-            return Err("load program section error")
+            return Err(Errno::ENOEXEC)
+        }
+        if ph.off <= elf.phoff && elf.phoff < ph.off + ph.filesz {
+            phdr = Some((ph.vaddr + elf.phoff - ph.off) as usize);
         }
 
         off += ph_size;
@@ -97,96 +322,174 @@ pub fn load(p: &mut Proc, path: &[u8], argv: &[Option<Box<[u8; MAXARGLEN]>>]) ->
     drop(inode);
     LOG.end_op();
 
-    // allocate two page for user stack
-    // one for usage, the other for guarding
-    proc_size = pg_round_up(proc_size);
-    match pgt.uvm_alloc(proc_size, proc_size + 2*PGSIZE) {
-        Ok(ret_size) => proc_size = ret_size,
-        Err(_) => {
-            pgt.dealloc_proc_pagetable(proc_size);
-            return Err("not enough uvm for user stack")
-        },
-    }
-    pgt.uvm_clear(proc_size - 2*PGSIZE);
-    let mut stack_pointer = proc_size;
-    let stack_base = stack_pointer - PGSIZE;
-
-    // prepare command line content in the user stack
-    let argc = argv.len();
-    debug_assert!(argc < MAXARG);
-    let mut ustack = [0usize; MAXARG+1];
-    for i in 0..argc {
-        let arg_slice = argv[i].as_deref().unwrap();
-        let max_pos = arg_slice.iter().position(|x| *x==0).unwrap();
-        let count = max_pos + 1;    // counting the ending zero
-        stack_pointer -= count;
-        stack_pointer = align_sp(stack_pointer);
-        if stack_pointer < stack_base {
-            pgt.dealloc_proc_pagetable(proc_size);
-            return Err("cmd args too much for stack")
-        }
-        if pgt.copy_out(arg_slice.as_ptr(), stack_pointer, count).is_err() {
-            pgt.dealloc_proc_pagetable(proc_size);
-            return Err("copy cmd args to pagetable go wrong")
-        }
-        ustack[i] = stack_pointer;
-    }
-    debug_assert!(argc == 0 || ustack[argc-1] != 0);    // ustack[argc-1] should not be zero
-    debug_assert_eq!(ustack[argc], 0);                  // ustack[argc] should be zero
-    stack_pointer -= (argc+1) * mem::size_of::<usize>();
-    stack_pointer = align_sp(stack_pointer);
-    if stack_pointer < stack_base {
+    // reserve the user stack above a guard gap, which grows on demand up to RLIMIT_STACK,
+    // and allocate the pages for the arguments and environment in advance
+    let auxv = [
+        (AT_PHDR, phdr),
+        (AT_PHENT, Some(elf.phentsize as usize)),
+        (AT_PHNUM, Some(elf.phnum as usize)),
+        (AT_PAGESZ, Some(PGSIZE)),
+        (AT_ENTRY, Some(elf.entry as usize)),
+        (AT_RANDOM, Some(0)),
+    ];
+    let stack_used = pg_round_up(stack_size(args, &auxv));
+    let stack_limit = pg_round_up(min(pdata.rlimits.cur(RLIMIT_STACK), MAXSTACK));
+    if stack_used > stack_limit {
         pgt.dealloc_proc_pagetable(proc_size);
-        return Err("cmd args too much for stack")
+        return Err(Errno::E2BIG)
     }
-    if pgt.copy_out(ustack.as_ptr() as *const u8, stack_pointer, (argc+1)*mem::size_of::<usize>()).is_err() {
+    let stack_top = pg_round_up(proc_size) + STACKGAP + stack_limit;
+    if let Err(e) = pgt.uvm_alloc(stack_top - stack_used, stack_top, PteFlag::R | PteFlag::W | PteFlag::U) {
         pgt.dealloc_proc_pagetable(proc_size);
-        return Err("copy cmd args to pagetable go wrong")
+        return Err(e)
     }
+    proc_size = stack_top;
+
+    let random = random_bytes(p.excl.lock().pid);
+    let (stack_pointer, argv, envp) = match setup_stack(pgt.as_mut(), proc_size, args, &auxv, &random) {
+        Ok(ret) => ret,
+        Err(e) => {
+            pgt.dealloc_proc_pagetable(proc_size);
+            return Err(e)
+        },
+    };
+
+    // wrap the new pagetable, it will be freed when dropped
+    let space = UserSpace::from_pagetable(pgt, proc_size, stack_top, stack_limit);
+    let space = Arc::try_new(SpinLock::new(space, "user space"))
+        .map_err(|_| Errno::ENOMEM)?;
+
+    // no way back from here, the other threads are gone with the old user space
+    unsafe { PROC_MANAGER.kill_threads(pi)?; }
 
     // update the process's info
     let tf = unsafe { pdata.tf.as_mut().unwrap() };
-    tf.a1 = stack_pointer;
+    tf.a1 = argv;
+    tf.a2 = envp;
     let off = path.iter().position(|x| *x!=b'/').unwrap();
     let count = min(path.len()-off, pdata.name.len());
     for i in 0..count {
         pdata.name[i] = path[i+off];
     }
-    let mut old_pgt = pdata.pagetable.replace(pgt).unwrap();
-    let old_size = pdata.sz;
-    pdata.sz = proc_size;
+    pdata.release_space();
+    pdata.set_space(space, TRAPFRAME.into());
+    pdata.close_on_exec();
+    p.excl.lock().sig.reset_handlers();
+    p.alarm_reset();
     tf.epc = elf.entry as usize;
     tf.sp = stack_pointer;
-    old_pgt.dealloc_proc_pagetable(old_size);
-    
-    Ok(argc)
+
+    Ok(args.argc)
+}
+
+/// Bytes of the user stack taken by [`setup_stack`], including the alignment.
+fn stack_size(args: &ExecArgs, auxv: &[(usize, Option<usize>)]) -> usize {
+    args.len + RANDOM_SIZE + vec_size(args, auxv) + 32
+}
+
+/// Bytes of argc, argv, envp and auxv.
+fn vec_size(args: &ExecArgs, auxv: &[(usize, Option<usize>)]) -> usize {
+    let naux = auxv.iter().filter(|(_, val)| val.is_some()).count() + 1;
+    mem::size_of::<usize>() * (1 + 2 * naux) + ExecArgs::ptrs_size(args.argc + args.envc)
+}
+
+/// Lay out the user stack below `top`, from the top down:
+/// the strings, the [`RANDOM_SIZE`] bytes for AT_RANDOM, and then
+/// argc, argv, NULL, envp, NULL and the auxv pairs ending with AT_NULL,
+/// where the stack pointer points at argc.
+/// The auxv entries without a value are left out, and AT_RANDOM is filled in here.
+/// Return the stack pointer, argv and envp.
+fn setup_stack(
+    pgt: &mut PageTable,
+    top: usize,
+    args: &ExecArgs,
+    auxv: &[(usize, Option<usize>)],
+    random: &[u8; RANDOM_SIZE],
+) -> Result<(usize, usize, usize), Errno> {
+    let strs = top - args.len;
+    pgt.copy_out(args.buf.as_ptr(), strs, args.len)?;
+    let random_addr = align_sp(strs - RANDOM_SIZE);
+    pgt.copy_out(random.as_ptr(), random_addr, RANDOM_SIZE)?;
+
+    let word = mem::size_of::<usize>();
+    let sp = align_sp(random_addr - vec_size(args, auxv));
+    let mut addr = sp;
+    let mut push = |pgt: &mut PageTable, val: usize| -> Result<(), Errno> {
+        pgt.copy_out(&val as *const usize as *const u8, addr, word)?;
+        addr += word;
+        Ok(())
+    };
+
+    push(pgt, args.argc)?;
+    let argv = sp + word;
+    let envp = argv + (args.argc + 1) * word;
+    let mut start = 0;
+    let mut count = 0;
+    for i in 0..args.len {
+        if args.buf[i] != 0 {
+            continue
+        }
+        if count == args.argc {
+            // end of argv
+            push(pgt, 0)?;
+        }
+        push(pgt, strs + start)?;
+        start = i + 1;
+        count += 1;
+    }
+    if args.envc == 0 {
+        push(pgt, 0)?;
+    }
+    push(pgt, 0)?;
+    for &(key, val) in auxv {
+        match (key, val) {
+            (AT_RANDOM, _) => { push(pgt, key)?; push(pgt, random_addr)?; },
+            (_, Some(val)) => { push(pgt, key)?; push(pgt, val)?; },
+            (_, None) => {},
+        }
+    }
+    push(pgt, AT_NULL)?;
+    push(pgt, 0)?;
+    debug_assert!(addr <= random_addr);
+
+    Ok((sp, argv, envp))
+}
+
+/// Bytes for AT_RANDOM, mixed from the machine time and the pid by splitmix64.
+/// They are different for each exec, but not cryptographically secure.
+fn random_bytes(pid: usize) -> [u8; RANDOM_SIZE] {
+    let mut x = unsafe { clint::read_mtime() } ^ ((pid as u64) << 32);
+    let mut bytes = [0u8; RANDOM_SIZE];
+    for chunk in bytes.chunks_mut(8) {
+        x = x.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = x;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        chunk.copy_from_slice(&z.to_le_bytes());
+    }
+    bytes
 }
 
 /// Load a program segment into the user's virtual memory.
-/// Note: va should be page-aligned and [va, offset+size) should already be mapped.
+/// Note: [va, va+size) should already be mapped, but va need not be page aligned.
+/// The pages are written through their physical addresses, so they could be read-only for user.
 fn load_seg(pgt: &mut PageTable, va: usize, idata: &mut SleepLockGuard<'_, InodeData>, offset: u32, size: u32)
     -> Result<(), ()>
 {
-    if va % PGSIZE != 0 {
-        panic!("va={} is not page aligned", va);
-    }
-    let mut va = VirtAddr::try_from(va).unwrap();
-
-    for i in (0..size).step_by(PGSIZE) {
+    let mut i = 0;
+    while i < size {
+        let cur = va + i as usize;
         let pa: usize;
-        match pgt.walk_addr_mut(va) {
-            Ok(phys_addr) => pa = phys_addr.into_raw(),
-            Err(s) => panic!("va={} should already be mapped, {}", va.into_raw(), s),
+        match pgt.walk_addr(VirtAddr::try_from(cur).unwrap()) {
+            Ok(phys_addr) => pa = phys_addr.into_raw() + cur % PGSIZE,
+            Err(s) => panic!("va={} should already be mapped, {}", cur, s),
         }
-        let count = if size - i < (PGSIZE as u32) {
-            size - i
-        } else {
-            PGSIZE as u32
-        };
+        let count = min(size - i, (PGSIZE - cur % PGSIZE) as u32);
         if idata.iread(Address::KernelMut(pa as *mut u8), offset+i, count).is_err() {
             return Err(())
         }
-        va.add_page();
+        i += count;
     }
 
     Ok(())
@@ -230,5 +533,40 @@ struct ProgHeader {
     align: u64,
 }
 
+impl ProgHeader {
+    /// User permissions of the segment pages.
+    /// Writable pages are also readable, as required by the pagetable.
+    fn perm(&self) -> PteFlag {
+        let mut perm = PteFlag::U;
+        if self.flags & (ELF_PROG_FLAG_READ | ELF_PROG_FLAG_WRITE) != 0 {
+            perm |= PteFlag::R;
+        }
+        if self.flags & ELF_PROG_FLAG_WRITE != 0 {
+            perm |= PteFlag::W;
+        }
+        if self.flags & ELF_PROG_FLAG_EXEC != 0 {
+            perm |= PteFlag::X;
+        }
+        perm
+    }
+}
+
 const ELF_MAGIC: u32 = 0x464C457F;
 const ELF_PROG_LOAD: u32 = 1;
+
+// flag bits of ProgHeader
+const ELF_PROG_FLAG_EXEC: u32 = 1;
+const ELF_PROG_FLAG_WRITE: u32 = 2;
+const ELF_PROG_FLAG_READ: u32 = 4;
+
+// auxv keys
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// bytes pointed to by AT_RANDOM
+const RANDOM_SIZE: usize = 16;
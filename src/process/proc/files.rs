@@ -0,0 +1,107 @@
+//! Opened files and cwd, shared by the threads of a process
+
+use array_macro::array;
+
+use alloc::sync::Arc;
+
+use crate::consts::fs::NFILE;
+use crate::fs::{File, Inode, LOG};
+
+/// File descriptor table and current working directory of a process.
+/// It is shared by the threads of a process, and protected by a spinlock,
+/// so the files and cwd taken out of it should be dropped after unlocking,
+/// since closing them may sleep.
+#[derive(Clone)]
+pub struct FileTable {
+    open_files: [Option<Arc<File>>; NFILE],
+    /// FD_CLOEXEC of each file descriptor, false if it is not opened
+    cloexec: [bool; NFILE],
+    /// current working directory
+    cwd: Option<Inode>,
+}
+
+impl FileTable {
+    /// An empty table with the given cwd, typically for the first process.
+    pub fn new(cwd: Inode) -> Self {
+        Self {
+            open_files: array![_ => None; NFILE],
+            cloexec: [false; NFILE],
+            cwd: Some(cwd),
+        }
+    }
+
+    /// Get the file that the file descriptor refers to.
+    pub fn get(&self, fd: usize) -> Option<Arc<File>> {
+        self.open_files.get(fd)?.clone()
+    }
+
+    /// Find the lowest free file descriptor not less than `min`, below `limit`.
+    pub fn alloc_fd(&self, min: usize, limit: usize) -> Option<usize> {
+        self.open_files.iter()
+            .take(limit)
+            .enumerate()
+            .skip(min)
+            .find(|(_, f)| f.is_none())
+            .map(|(i, _)| i)
+    }
+
+    /// Find a pair of free file descriptors below `limit`.
+    /// Typically used for pipe creation.
+    pub fn alloc_fd2(&self, limit: usize) -> Option<(usize, usize)> {
+        let mut iter = self.open_files.iter()
+            .take(limit)
+            .enumerate()
+            .filter(|(_, f)| f.is_none())
+            .take(2)
+            .map(|(i, _)| i);
+        let fd1 = iter.next()?;
+        let fd2 = iter.next()?;
+        Some((fd1, fd2))
+    }
+
+    /// Let the free file descriptor refer to the file.
+    pub fn install(&mut self, fd: usize, file: Arc<File>) {
+        let none_file = self.open_files[fd].replace(file);
+        debug_assert!(none_file.is_none());
+    }
+
+    /// Close the file descriptor, return the file it refers to.
+    pub fn close(&mut self, fd: usize) -> Option<Arc<File>> {
+        let file = self.open_files.get_mut(fd)?.take();
+        self.cloexec[fd] = false;
+        file
+    }
+
+    /// Get FD_CLOEXEC of the file descriptor.
+    pub fn cloexec(&self, fd: usize) -> bool {
+        self.cloexec[fd]
+    }
+
+    /// Set FD_CLOEXEC of the opened file descriptor.
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) {
+        debug_assert!(self.open_files[fd].is_some());
+        self.cloexec[fd] = cloexec;
+    }
+
+    /// Get the current working directory.
+    pub fn cwd(&self) -> Inode {
+        self.cwd.clone().unwrap()
+    }
+
+    /// Change the current working directory, return the old one.
+    pub fn set_cwd(&mut self, cwd: Inode) -> Inode {
+        self.cwd.replace(cwd).unwrap()
+    }
+}
+
+impl Drop for FileTable {
+    /// Close the files and cwd, when the last thread sharing the table leaves.
+    fn drop(&mut self) {
+        for file in self.open_files.iter_mut() {
+            drop(file.take());
+        }
+        LOG.begin_op();
+        drop(self.cwd.take());
+        LOG.end_op();
+    }
+}
@@ -1,19 +1,18 @@
-use array_macro::array;
-
-use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::mem;
 use core::sync::atomic::{AtomicBool, Ordering};
 use core::option::Option;
 use core::ptr;
 use core::cell::UnsafeCell;
+use core::convert::TryFrom;
 
-use crate::consts::{PGSIZE, fs::{NFILE, ROOTIPATH}};
-use crate::mm::{PageTable, RawPage, RawSinglePage};
+use crate::consts::{PGSIZE, MAXPATH, NSMP, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_NPROC, RLIMIT_STACK, NQUEUE, TIME_SLICE, NICE_MIN, NICE_MAX, fs::{NFILE, ROOTIPATH, AF_UNIX}};
+use crate::errno::Errno;
+use crate::mm::{RawPage, RawSinglePage, VirtAddr, pg_round_down};
 use crate::register::{satp, sepc, sstatus};
 use crate::spinlock::{SpinLock, SpinLockGuard};
 use crate::trap::user_trap;
-use crate::fs::{Inode, ICACHE, LOG, File};
+use crate::fs::{Inode, ICACHE, File};
 
 use super::CpuManager;
 use super::PROC_MANAGER;
@@ -21,9 +20,23 @@ use super::cpu::CPU_MANAGER;
 use super::{fork_ret, Context, TrapFrame};
 
 use self::syscall::Syscall;
+use self::signal::SigState;
+use self::elf::ExecArgs;
+use self::rlimit::RLimits;
+use self::alarm::Alarm;
+use self::files::FileTable;
+
+pub use self::space::UserSpace;
 
 mod syscall;
 mod elf;
+mod signal;
+mod space;
+mod vma;
+mod trace;
+mod rlimit;
+mod alarm;
+mod files;
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum ProcState {
@@ -33,14 +46,38 @@ pub enum ProcState {
     RUNNING,
     ALLOCATED,
     ZOMBIE,
+    STOPPED,
 }
 
 /// Exclusive to the process
 pub struct ProcExcl {
     pub state: ProcState,
     pub exit_status: i32,
+    /// signal that terminated the process, zero if it exits by itself
+    pub term_sig: usize,
+    /// signal that stopped the process, cleared once reported to the parent
+    pub stop_sig: usize,
     pub channel: usize,
     pub pid: usize,
+    /// thread group id, i.e., the pid of the process that the thread belongs to
+    pub tgid: usize,
+    /// process group id
+    pub pgid: usize,
+    /// session id
+    pub sid: usize,
+    pub sig: SigState,
+    /// nice value, which decides the base priority level
+    pub nice: i32,
+    /// current level in the multilevel feedback queues
+    pub level: usize,
+    /// ticks used at the current level
+    pub ticks: usize,
+    /// ticks used in total, limited by RLIMIT_CPU
+    pub cpu_ticks: usize,
+    /// bitmask of the cpus allowed to run the process
+    pub affinity: usize,
+    /// cpu that the process last ran on
+    pub cpu: usize,
 }
 
 impl ProcExcl {
@@ -48,18 +85,88 @@ impl ProcExcl {
         Self {
             state: ProcState::UNUSED,
             exit_status: 0,
+            term_sig: 0,
+            stop_sig: 0,
             channel: 0,
             pid: 0,
+            tgid: 0,
+            pgid: 0,
+            sid: 0,
+            sig: SigState::new(),
+            nice: 0,
+            level: 0,
+            ticks: 0,
+            cpu_ticks: 0,
+            affinity: (1 << NSMP) - 1,
+            cpu: 0,
         }
     }
 
     /// Clean up the content in [`ProcExcl`],
     pub fn cleanup(&mut self) {
         self.pid = 0;
+        self.tgid = 0;
+        self.pgid = 0;
+        self.sid = 0;
+        self.sig.cleanup();
+        self.nice = 0;
+        self.level = 0;
+        self.ticks = 0;
+        self.cpu_ticks = 0;
+        self.affinity = (1 << NSMP) - 1;
+        self.cpu = 0;
         self.channel = 0;
         self.exit_status = 0;
+        self.term_sig = 0;
+        self.stop_sig = 0;
         self.state = ProcState::UNUSED;
     }
+
+    /// The base priority level decided by the nice value.
+    pub fn base_level(&self) -> usize {
+        (self.nice - NICE_MIN) as usize * NQUEUE / (NICE_MAX - NICE_MIN + 1) as usize
+    }
+
+    /// Move back to the base level,
+    /// when the process is boosted, or woken up since it did not use the cpu.
+    pub fn reset_level(&mut self) {
+        self.level = self.base_level();
+        self.ticks = 0;
+    }
+
+    /// Charge a timer tick to the running process,
+    /// and demote it if it has used up the time slice at its level.
+    fn charge_tick(&mut self) {
+        self.cpu_ticks += 1;
+        self.ticks += 1;
+        if self.ticks >= TIME_SLICE[self.level] {
+            self.ticks = 0;
+            if self.level + 1 < NQUEUE {
+                self.level += 1;
+            }
+        }
+    }
+}
+
+/// User and group identity of a process,
+/// checked against the owner and mode of the inodes.
+#[derive(Clone, Copy, Debug)]
+pub struct Cred {
+    pub uid: u16,
+    pub gid: u16,
+}
+
+impl Cred {
+    /// The identity of the first process.
+    pub const fn root() -> Self {
+        Self { uid: 0, gid: 0 }
+    }
+
+    /// The superuser, which passes most of the permission checks.
+    #[inline]
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
 }
 
 /// Data private to the process
@@ -67,29 +174,47 @@ impl ProcExcl {
 /// or initialed by other process(e.g. fork) with ProcExcl lock held
 pub struct ProcData {
     kstack: usize,
-    sz: usize,
     context: Context,
     name: [u8; 16],
-    open_files: [Option<Arc<File>>; NFILE],
+    /// opened files and cwd, shared by the threads of a process
+    files: Option<Arc<SpinLock<FileTable>>>,
     /// trapframe to hold temp user register value, etc
     pub tf: *mut TrapFrame,
-    /// user pagetable
-    pub pagetable: Option<Box<PageTable>>,
-    /// current working directory
-    pub cwd: Option<Inode>,
+    /// user virtual address that the trapframe is mapped at
+    tf_va: usize,
+    /// user address space, shared by the threads of a process
+    space: Option<Arc<SpinLock<UserSpace>>>,
+    /// bitmask of the syscall numbers to log, set by the trace syscall
+    trace_mask: usize,
+    /// resource limits, set by setrlimit
+    rlimits: RLimits,
+    /// user and group identity
+    cred: Cred,
+    /// periodic alarm, set by sigalarm
+    alarm: Alarm,
+    /// a0 of the syscall interrupted by signals,
+    /// which is restarted if none of them runs a handler
+    restart: Option<usize>,
+    /// deadline of the nanosleep interrupted by signals, kept for its restart
+    sleep_deadline: Option<u64>,
 }
 
 impl ProcData {
     const fn new() -> Self {
         Self {
             kstack: 0,
-            sz: 0,
             context: Context::new(),
             name: [0; 16],
-            open_files: array![_ => None; NFILE],
+            files: None,
             tf: ptr::null_mut(),
-            pagetable: None,
-            cwd: None,
+            tf_va: 0,
+            space: None,
+            trace_mask: 0,
+            rlimits: RLimits::new(),
+            cred: Cred::root(),
+            alarm: Alarm::new(),
+            restart: None,
+            sleep_deadline: None,
         }
     }
 
@@ -113,8 +238,9 @@ impl ProcData {
     }
 
     /// Prepare for the user trap return
-    /// Return current proc's satp for assembly code to switch page table
-    pub fn user_ret_prepare(&mut self) -> usize {
+    /// Return current proc's trapframe virtual address and satp
+    /// for assembly code to switch page table
+    pub fn user_ret_prepare(&mut self) -> (usize, usize) {
         let tf: &mut TrapFrame = unsafe { self.tf.as_mut().unwrap() };
         tf.kernel_satp = satp::read();
         // current kernel stack's content is cleaned
@@ -126,103 +252,267 @@ impl ProcData {
         // restore the user pc previously stored in sepc
         sepc::write(tf.epc);
 
-        self.pagetable.as_ref().unwrap().as_satp()
+        (self.tf_va, self.space().as_satp())
+    }
+
+    /// Set the user space and where the trapframe is mapped in it.
+    /// Typically used when the process is allocated.
+    pub fn set_space(&mut self, space: Arc<SpinLock<UserSpace>>, tf_va: usize) {
+        debug_assert!(self.space.is_none());
+        self.space = Some(space);
+        self.tf_va = tf_va;
+    }
+
+    /// Lock the user space of the process.
+    /// Note: Do not sleep with the returned guard held.
+    #[inline]
+    fn space(&self) -> SpinLockGuard<'_, UserSpace> {
+        self.space.as_ref().unwrap().lock()
     }
 
     /// Simply check if the user passed-in virtual address is in range.
-    fn check_user_addr(&self, user_addr: usize) -> Result<(), ()> {
-        if user_addr > self.sz {
-            Err(())
-        } else {
-            Ok(())
-        }
+    fn check_user_addr(&self, user_addr: usize) -> Result<(), Errno> {
+        self.space().check_user_addr(user_addr)
     }
 
     /// Copy content from src to the user's dst virtual address.
     /// Copy `count` bytes in total.
-    /// It will redirect the call to pagetable.
+    /// It will redirect the call to the user space.
     #[inline]
-    pub fn copy_out(&mut self, src: *const u8, dst: usize, count: usize) -> Result<(), ()> {
-        self.pagetable.as_mut().unwrap().copy_out(src, dst, count)
+    pub fn copy_out(&mut self, src: *const u8, dst: usize, count: usize) -> Result<(), Errno> {
+        self.lazy_populate(dst, count);
+        self.space().copy_out(src, dst, count)
     }
 
     /// Copy content from the user's src virtual address to dst.
     /// Copy `count` bytes in total.
-    /// It will redirect the call to pagetable.
+    /// It will redirect the call to the user space.
     #[inline]
-    pub fn copy_in(&self, src: usize, dst: *mut u8, count: usize) -> Result<(), ()> {
-        self.pagetable.as_ref().unwrap().copy_in(src, dst, count)
+    pub fn copy_in(&mut self, src: usize, dst: *mut u8, count: usize) -> Result<(), Errno> {
+        self.lazy_populate(src, count);
+        self.space().copy_in(src, dst, count)
+    }
+
+    /// Copy a null-terminated string from the user's src virtual address to dst.
+    /// It will redirect the call to the user space.
+    pub fn copy_in_str(&mut self, src: usize, dst: &mut [u8]) -> Result<(), Errno> {
+        self.lazy_populate(src, dst.len());
+        self.space().copy_in_str(src, dst)
+    }
+
+    /// Handle a page fault at the user virtual address `va`.
+    /// `store` is true if the fault is caused by a store.
+    /// A file mapped page is read without the user space locked.
+    pub fn page_fault(&mut self, va: usize, store: bool) -> Result<(), &'static str> {
+        let stack_rlimit = self.rlimits.cur(RLIMIT_STACK);
+        let fill = self.space().fault(va, store, stack_rlimit)?;
+        if let Some(fill) = fill {
+            let mem = fill.load()?;
+            self.space().map_filled(fill, mem)?;
+        }
+        Ok(())
     }
 
-    /// Allocate a new file descriptor.
-    /// The returned fd could be used directly to index, because it is private to the process.
-    fn alloc_fd(&mut self) -> Option<usize> {
-        self.open_files.iter()
-            .enumerate()
-            .find(|(_, f)| f.is_none())
-            .map(|(i, _)| i)
+    /// Get the physical address of the user word at `va`, as the key of a futex.
+    /// The page is faulted in as if written, so that a copy-on-write page is
+    /// made private now, instead of moving to another page when written later.
+    pub fn futex_key(&mut self, va: usize) -> Result<usize, Errno> {
+        self.check_user_addr(va)?;
+        self.page_fault(va, true).map_err(|_| Errno::EFAULT)?;
+        let page = VirtAddr::try_from(pg_round_down(va)).map_err(|_| Errno::EFAULT)?;
+        let pa = self.space().pagetable().walk_addr_mut(page).map_err(|_| Errno::EFAULT)?;
+        Ok(pa.into_raw() + va - page.into_raw())
     }
 
-    /// Allocate a pair of file descriptors.
+    /// Handle an instruction page fault at the user virtual address `va`,
+    /// where the page may be mapped but not executable.
+    pub fn exec_fault(&mut self, va: usize) -> Result<(), &'static str> {
+        self.page_fault(va, false)?;
+        if self.space().executable(va) {
+            Ok(())
+        } else {
+            Err("pte not executable")
+        }
+    }
+
+    /// Fault in the lazily allocated pages within [va, va+count),
+    /// before the kernel accesses them on behalf of the user.
+    /// It stops at the first page failed to fault in,
+    /// and leaves the error for the page table to report when accessing.
+    /// Callers should populate user buffers in advance when they are about to hold any lock,
+    /// because faulting in a file mapped page may sleep.
+    pub fn lazy_populate(&mut self, va: usize, count: usize) {
+        let end = match va.checked_add(count) {
+            Some(end) => end,
+            None => return,
+        };
+        let mut cur = pg_round_down(va);
+        while cur < end {
+            if self.page_fault(cur, false).is_err() {
+                return
+            }
+            cur += PGSIZE;
+        }
+    }
+
+    /// Lock the opened files and cwd of the process.
+    /// Note: Do not sleep with the returned guard held,
+    ///     nor drop any file or inode taken out of it.
+    #[inline]
+    fn files(&self) -> SpinLockGuard<'_, FileTable> {
+        self.files.as_ref().unwrap().lock()
+    }
+
+    /// Get the current working directory.
+    pub fn cwd(&self) -> Inode {
+        self.files().cwd()
+    }
+
+    /// Get the file that the file descriptor refers to.
+    /// The file is held by the caller,
+    /// so that it stays open even if the fd is closed by another thread meanwhile.
+    fn file(&self, fd: usize) -> Result<Arc<File>, Errno> {
+        self.files().get(fd).ok_or(Errno::EBADF)
+    }
+
+    /// Check if there is a free file descriptor below RLIMIT_NOFILE,
+    /// before doing anything not easy to undo for a new file.
+    /// The fd may still be taken by another thread before the file is installed.
+    fn check_free_fd(&self) -> Result<(), Errno> {
+        self.files().alloc_fd(0, self.rlimits.cur(RLIMIT_NOFILE)).map(|_| ()).ok_or(Errno::EMFILE)
+    }
+
+    /// Install the file at a new file descriptor, below RLIMIT_NOFILE.
+    fn install_fd(&mut self, file: Arc<File>) -> Result<usize, Errno> {
+        self.install_fd_from(0, file)
+    }
+
+    /// Install the file at the lowest free file descriptor not less than `min`, below RLIMIT_NOFILE.
+    /// The file is dropped without the table locked if there is none.
+    fn install_fd_from(&mut self, min: usize, file: Arc<File>) -> Result<usize, Errno> {
+        let limit = self.rlimits.cur(RLIMIT_NOFILE);
+        let mut files = self.files();
+        match files.alloc_fd(min, limit) {
+            Some(fd) => {
+                files.install(fd, file);
+                Ok(fd)
+            },
+            None => {
+                drop(files);
+                drop(file);
+                Err(Errno::EMFILE)
+            },
+        }
+    }
+
+    /// Install a pair of files at two new file descriptors.
     /// Typically used for pipe creation.
-    fn alloc_fd2(&mut self) -> Option<(usize, usize)> {
-        let mut iter = self.open_files.iter()
-            .enumerate()
-            .filter(|(_, f)| f.is_none())
-            .take(2)
-            .map(|(i, _)| i);
-        let fd1 = iter.next()?;
-        let fd2 = iter.next()?;
-        Some((fd1, fd2))
+    fn install_fd2(&mut self, file1: Arc<File>, file2: Arc<File>) -> Result<(usize, usize), Errno> {
+        let limit = self.rlimits.cur(RLIMIT_NOFILE);
+        let mut files = self.files();
+        match files.alloc_fd2(limit) {
+            Some((fd1, fd2)) => {
+                files.install(fd1, file1);
+                files.install(fd2, file2);
+                Ok((fd1, fd2))
+            },
+            None => {
+                drop(files);
+                drop(file1);
+                drop(file2);
+                Err(Errno::EMFILE)
+            },
+        }
+    }
+
+    /// Close the file descriptor, return the file it refers to.
+    fn close_fd(&mut self, fd: usize) -> Option<Arc<File>> {
+        self.files().close(fd)
+    }
+
+    /// Close the file descriptors with FD_CLOEXEC set, when exec.
+    fn close_on_exec(&mut self) {
+        for fd in 0..NFILE {
+            let mut files = self.files();
+            let file = if files.cloexec(fd) { files.close(fd) } else { None };
+            drop(files);
+            drop(file);
+        }
     }
 
     /// Clean up the content in [`ProcData`],
     /// except kernel stack, context, opened files and cwd.
+    /// The user space should already be released when the process exits,
+    /// otherwise it is a newly allocated one and not shared.
     /// LTODO - should excl must be held by caller during this cleanup?
     pub fn cleanup(&mut self) {
         self.name[0] = 0;
+        self.trace_mask = 0;
+        self.rlimits = RLimits::new();
+        self.cred = Cred::root();
+        self.alarm.cleanup();
+        self.restart = None;
+        self.sleep_deadline = None;
+        drop(self.space.take());
+        self.tf_va = 0;
         let tf = self.tf;
         self.tf = ptr::null_mut();
         if !tf.is_null() {
             unsafe { RawSinglePage::from_raw_and_drop(tf as *mut u8); }
         }
-        let pgt = self.pagetable.take();
-        if let Some(mut pgt) = pgt {
-            pgt.dealloc_proc_pagetable(self.sz);
+    }
+
+    /// Detach from the user space when the process exits.
+    /// The last one leaving the space unmaps the memory mapped areas and writes them back,
+    /// and the user memory is freed when the space is dropped.
+    pub fn release_space(&mut self) {
+        let space = self.space.take().unwrap();
+        let unmapped = space.lock().detach(self.tf_va);
+        for area in unmapped {
+            area.finish();
         }
-        self.sz = 0;
+        drop(space);
     }
 
-    /// Close any opened files and cwd,
-    /// except kernel stack and context.
+    /// Detach from the opened files and cwd,
+    /// which are closed by the last thread leaving them.
     /// Should only be called when the process exits.
     pub fn close_files(&mut self) {
-        for f in self.open_files.iter_mut() {
-            drop(f.take())
-        }
-        LOG.begin_op();
-        debug_assert!(self.cwd.is_some());
-        drop(self.cwd.take());
-        LOG.end_op();
-    }
-
-    /// Increase/Decrease the user program break for the process.
-    /// Return the previous program break if succeed.
-    fn sbrk(&mut self, increment: i32) -> Result<usize, ()> {
-        let old_size = self.sz;
-        if increment > 0 {
-            let new_size = old_size + (increment as usize);
-            self.pagetable.as_mut().unwrap().uvm_alloc(old_size, new_size)?;
-            self.sz = new_size;
-        } else if increment < 0 {
-            let new_size = old_size - ((-increment) as usize);
-            self.pagetable.as_mut().unwrap().uvm_dealloc(old_size, new_size);
-            self.sz = new_size;
+        debug_assert!(self.files.is_some());
+        drop(self.files.take());
+    }
+
+    /// Redirect to [`UserSpace::sbrk`], limited by RLIMIT_AS.
+    fn sbrk(&mut self, increment: i32) -> Result<usize, Errno> {
+        let limit = self.rlimits.cur(RLIMIT_AS);
+        self.space().sbrk(increment, limit)
+    }
+
+    /// Redirect to [`UserSpace::mmap`].
+    fn mmap(&mut self, len: usize, prot: i32, flags: i32, file: Option<Arc<File>>, offset: usize)
+        -> Result<usize, Errno>
+    {
+        // keep a reference, so that the file is not released with the user space locked
+        let _file = file.clone();
+        self.space().mmap(len, prot, flags, file, offset)
+    }
+
+    /// Redirect to [`UserSpace::munmap`],
+    /// and write back the unmapped pages without the user space locked.
+    fn munmap(&mut self, addr: usize, len: usize) -> Result<(), Errno> {
+        let unmapped = self.space().munmap(addr, len)?;
+        unmapped.finish();
+        Ok(())
+    }
+
+    /// Unmap all the memory mapped areas and write them back.
+    fn munmap_all(&mut self) {
+        let unmapped = self.space().munmap_all();
+        for area in unmapped {
+            area.finish();
         }
-        Ok(old_size)
     }
 }
-
 /// Process Struct
 /// 
 /// LTODO - ProcData could be protected by RefCell,
@@ -247,14 +537,19 @@ impl Proc {
         }
     }
 
+    /// Index into the process table.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
     /// Called by ProcManager's user_init,
     /// Only be called once for the first user process
     pub fn user_init(&mut self) {
         let pd = self.data.get_mut();
 
         // map initcode in user pagetable
-        pd.pagetable.as_mut().unwrap().uvm_init(&INITCODE);
-        pd.sz = PGSIZE;
+        pd.space().init(&INITCODE);
 
         // prepare return pc and stack pointer
         let tf = unsafe { pd.tf.as_mut().unwrap() };
@@ -270,8 +565,15 @@ impl Proc {
             );
         }
 
-        debug_assert!(pd.cwd.is_none());
-        pd.cwd = Some(ICACHE.namei(&ROOTIPATH).expect("cannot find root inode by b'/'"));
+        debug_assert!(pd.files.is_none());
+        let cwd = ICACHE.namei(&ROOTIPATH).expect("cannot find root inode by b'/'");
+        let files = Arc::try_new(SpinLock::new(FileTable::new(cwd), "files"));
+        pd.files = Some(files.expect("cannot allocate the file table"));
+    }
+
+    /// User and group identity of the process.
+    pub fn cred(&self) -> Cred {
+        unsafe { self.data.get().as_ref().unwrap().cred }
     }
 
     /// Abondon current process if
@@ -296,8 +598,13 @@ impl Proc {
         sstatus::intr_on();
 
         let tf = unsafe { self.data.get_mut().tf.as_mut().unwrap() };
-        let a7 = tf.a7;
+        let (a0, a7) = (tf.a0, tf.a7);
         tf.admit_ecall();
+        let trace = if self.traced(a7) {
+            Some(self.trace_enter(a7))
+        } else {
+            None
+        };
         let sys_result = match a7 {
             1 => self.sys_fork(),
             2 => self.sys_exit(),
@@ -320,27 +627,85 @@ impl Proc {
             19 => self.sys_link(),
             20 => self.sys_mkdir(),
             21 => self.sys_close(),
+            22 => self.sys_mmap(),
+            23 => self.sys_munmap(),
+            24 => self.sys_clone(),
+            25 => self.sys_join(),
+            26 => self.sys_sigaction(),
+            27 => self.sys_sigprocmask(),
+            28 => self.sys_sigreturn(),
+            29 => self.sys_setpriority(),
+            30 => self.sys_sched_setaffinity(),
+            31 => self.sys_trace(),
+            32 => self.sys_waitpid(),
+            33 => self.sys_setpgid(),
+            34 => self.sys_getpgid(),
+            35 => self.sys_setsid(),
+            36 => self.sys_tcsetpgrp(),
+            37 => self.sys_tcgetpgrp(),
+            38 => self.sys_execve(),
+            39 => self.sys_getrlimit(),
+            40 => self.sys_setrlimit(),
+            41 => self.sys_chmod(),
+            42 => self.sys_chown(),
+            43 => self.sys_setuid(),
+            44 => self.sys_getuid(),
+            45 => self.sys_sigalarm(),
+            46 => self.sys_clock_gettime(),
+            47 => self.sys_nanosleep(),
+            48 => self.sys_gettimeofday(),
+            49 => self.sys_futex(),
+            50 => self.sys_fcntl(),
+            51 => self.sys_poll(),
+            52 => self.sys_mkfifo(),
+            53 => self.sys_socket(),
+            54 => self.sys_bind(),
+            55 => self.sys_listen(),
+            56 => self.sys_accept(),
+            57 => self.sys_connect(),
+            58 => self.sys_socketpair(),
+            59 => self.sys_setgid(),
+            60 => self.sys_getgid(),
             _ => {
-                panic!("unknown syscall num: {}", a7);
+                #[cfg(feature = "kernel_warning")]
+                println!("kernel warning: unknown syscall num: {}", a7);
+                Err(Errno::ENOSYS)
             }
         };
+        if let Some(call) = trace {
+            self.trace_exit(&call, &sys_result);
+        }
         tf.a0 = match sys_result {
             Ok(ret) => ret,
-            Err(()) => -1isize as usize,
+            Err(errno) => errno.as_ret(),
+        };
+        self.data.get_mut().restart = match sys_result {
+            Err(Errno::EINTR) => Some(a0),
+            _ => None,
         };
     }
 
     /// Give up the current runing process in this cpu
     /// Change the name to yielding, because `yield` is a key word
+    /// It is called on timer interrupts, so a tick is charged to the process.
     pub fn yielding(&mut self) {
         let mut guard = self.excl.lock();
         assert_eq!(guard.state, ProcState::RUNNING);
-        guard.state = ProcState::RUNNABLE;
+        guard.charge_tick();
+        self.check_cpu_limit(&mut guard);
+        self.set_runnable(&mut guard);
         guard = unsafe { CPU_MANAGER.my_cpu_mut().sched(guard,
             self.data.get_mut().get_context()) };
         drop(guard);
     }
 
+    /// Make the process RUNNABLE and put it into a run queue,
+    /// with its [`ProcExcl`] held.
+    pub fn set_runnable(&self, excl: &mut ProcExcl) {
+        excl.state = ProcState::RUNNABLE;
+        unsafe { PROC_MANAGER.enqueue(self.index, excl); }
+    }
+
     /// Atomically release a spinlock and sleep on chan.
     /// The passed-in guard should not the proc's guard,
     /// otherwise it will deadlock(because it acquires proc's lock first).
@@ -353,7 +718,28 @@ impl Proc {
         // guaranteed that we won't miss any wakeup
         // (wakeup locks p->lock),
         // so it's okay to release lk.
-        let mut excl_guard = self.excl.lock();
+        let excl_guard = self.excl.lock();
+        self.sleep_locked(channel, guard, excl_guard);
+    }
+
+    /// Like [`Proc::sleep`], but return at once if [`Proc::interrupted`],
+    /// which is checked with [`ProcExcl`] held,
+    /// so that a signal posted right before sleeping is not missed.
+    /// The caller should check [`Proc::interrupted`] after reacquiring the lock.
+    pub fn sleep_interruptible<T>(&self, channel: usize, guard: SpinLockGuard<'_, T>) {
+        let excl_guard = self.excl.lock();
+        if self.killed.load(Ordering::Relaxed) || excl_guard.sig.interrupting() {
+            drop(excl_guard);
+            drop(guard);
+            return
+        }
+        self.sleep_locked(channel, guard, excl_guard);
+    }
+
+    /// Release the spinlock and sleep on `channel`, with [`ProcExcl`] held.
+    fn sleep_locked<T>(&self, channel: usize, guard: SpinLockGuard<'_, T>,
+        mut excl_guard: SpinLockGuard<'_, ProcExcl>)
+    {
         drop(guard);
 
         // go to sleep
@@ -371,23 +757,41 @@ impl Proc {
     }
 
     /// Fork a child process.
-    fn fork(&mut self) -> Result<usize, ()> {
+    /// The number of children is limited by RLIMIT_NPROC.
+    fn fork(&mut self) -> Result<usize, Errno> {
         let pdata = self.data.get_mut();
-        let child = unsafe { PROC_MANAGER.alloc_proc().ok_or(())? };
+        if unsafe { PROC_MANAGER.children(self.index) } >= pdata.rlimits.cur(RLIMIT_NPROC) {
+            return Err(Errno::EAGAIN)
+        }
+        // copy the opened files and cwd,
+        // the copy should be dropped without any lock held if failed
+        let files = pdata.files().clone();
+        let files = Arc::try_new(SpinLock::new(files, "files")).map_err(|_| Errno::ENOMEM)?;
+        let child = unsafe { PROC_MANAGER.alloc_proc(None).ok_or(Errno::EAGAIN)? };
+
+        // populate the shared memory mapped areas,
+        // so that the child refers to the same pages
+        let areas = pdata.space().shared_areas();
+        for &(start, end) in areas.iter().flatten() {
+            pdata.lazy_populate(start, end - start);
+        }
+
+        let pexcl = self.excl.lock();
+        let (sig, nice, affinity, cpu) = (pexcl.sig.inherited(), pexcl.nice, pexcl.affinity, pexcl.cpu);
+        let (pgid, sid) = (pexcl.pgid, pexcl.sid);
+        drop(pexcl);
         let mut cexcl = child.excl.lock();
         let cdata = unsafe { child.data.get().as_mut().unwrap() };
 
         // clone memory
-        let cpgt = cdata.pagetable.as_mut().unwrap();
-        let size = pdata.sz;
-        if pdata.pagetable.as_mut().unwrap().uvm_copy(cpgt, size).is_err() {
+        let ret = pdata.space().copy_to(&mut cdata.space());
+        if let Err(e) = ret {
             debug_assert_eq!(child.killed.load(Ordering::Relaxed), false);
             child.killed.store(false, Ordering::Relaxed);
             cdata.cleanup();
             cexcl.cleanup();
-            return Err(())
+            return Err(e)
         }
-        cdata.sz = size;
 
         // clone trapframe and return 0 on a0
         unsafe {
@@ -395,13 +799,22 @@ impl Proc {
             cdata.tf.as_mut().unwrap().a0 = 0;
         }
 
-        // clone opened files and cwd
-        cdata.open_files.clone_from(&pdata.open_files);
-        cdata.cwd.clone_from(&pdata.cwd);
+        // refer to the same opened files and cwd, but in a table of its own
+        cdata.files = Some(files);
         
-        // copy process name
+        // copy process name, trace mask, resource limits and identity
         cdata.name.copy_from_slice(&pdata.name);
-
+        cdata.trace_mask = pdata.trace_mask;
+        cdata.rlimits = pdata.rlimits;
+        cdata.cred = pdata.cred;
+
+        cexcl.pgid = pgid;
+        cexcl.sid = sid;
+        cexcl.sig = sig;
+        cexcl.nice = nice;
+        cexcl.reset_level();
+        cexcl.affinity = affinity;
+        cexcl.cpu = cpu;
         let cpid = cexcl.pid;
 
         drop(cexcl);
@@ -409,11 +822,63 @@ impl Proc {
         unsafe { PROC_MANAGER.set_parent(child.index, self.index); }
 
         let mut cexcl = child.excl.lock();
-        cexcl.state = ProcState::RUNNABLE;
+        child.set_runnable(&mut cexcl);
         drop(cexcl);
 
         Ok(cpid)
     }
+
+    /// Create a thread sharing the user space, opened files and cwd with the current process.
+    /// The thread starts at `func` with `arg` in a0, `stack` as its stack pointer and `tls` in tp.
+    /// It should call exit when finished, since there is no return address.
+    fn clone_thread(&mut self, func: usize, arg: usize, stack: usize, tls: usize) -> Result<usize, Errno> {
+        let pdata = self.data.get_mut();
+        let pexcl = self.excl.lock();
+        let (tgid, pgid, sid) = (pexcl.tgid, pexcl.pgid, pexcl.sid);
+        let (sig, nice) = (pexcl.sig.inherited(), pexcl.nice);
+        let (affinity, cpu) = (pexcl.affinity, pexcl.cpu);
+        drop(pexcl);
+        let child = unsafe { PROC_MANAGER.alloc_proc(pdata.space.as_ref()).ok_or(Errno::EAGAIN)? };
+        let mut cexcl = child.excl.lock();
+        let cdata = unsafe { child.data.get().as_mut().unwrap() };
+
+        // start at func with its own stack and thread pointer
+        let tf = unsafe {
+            ptr::copy_nonoverlapping(pdata.tf, cdata.tf, 1);
+            cdata.tf.as_mut().unwrap()
+        };
+        tf.epc = func;
+        tf.a0 = arg;
+        tf.sp = stack;
+        tf.tp = tls;
+
+        // share the same table of opened files and cwd
+        cdata.files = pdata.files.clone();
+        cdata.name.copy_from_slice(&pdata.name);
+        cdata.trace_mask = pdata.trace_mask;
+        cdata.rlimits = pdata.rlimits;
+        cdata.cred = pdata.cred;
+
+        cexcl.tgid = tgid;
+        cexcl.pgid = pgid;
+        cexcl.sid = sid;
+        cexcl.sig = sig;
+        cexcl.nice = nice;
+        cexcl.reset_level();
+        cexcl.affinity = affinity;
+        cexcl.cpu = cpu;
+        let tid = cexcl.pid;
+
+        drop(cexcl);
+
+        unsafe { PROC_MANAGER.set_parent(child.index, self.index); }
+
+        let mut cexcl = child.excl.lock();
+        child.set_runnable(&mut cexcl);
+        drop(cexcl);
+
+        Ok(tid)
+    }
 }
 
 impl Proc {
@@ -446,48 +911,93 @@ impl Proc {
         self.arg_raw(n)
     }
 
-    /// Fetch a file descriptor from register value.
-    /// Also Check if the fd is valid.
+    /// Fetch a file descriptor from register value,
+    /// and get the file it refers to, see [`ProcData::file`].
     #[inline]
-    fn arg_fd(&mut self, n: usize) -> Result<usize, ()> {
+    fn arg_file(&mut self, n: usize) -> Result<Arc<File>, Errno> {
         let fd = self.arg_raw(n);
-        if fd >= NFILE || self.data.get_mut().open_files[fd].is_none() {
-            Err(())
-        } else {
-            Ok(fd)
-        }
+        self.data.get_mut().file(fd)
     }
 
     /// Fetch a null-terminated string from register pointer.
-    fn arg_str(&self, n: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+    fn arg_str(&self, n: usize, buf: &mut [u8]) -> Result<(), Errno> {
         let addr: usize = self.arg_raw(n);
-        let pagetable = unsafe { self.data.get().as_ref().unwrap().pagetable.as_ref().unwrap() };
-        pagetable.copy_in_str(addr, buf)?;
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
+        pd.copy_in_str(addr, buf)?;
+        Ok(())
+    }
+
+    /// Fetch the path of a `struct sockaddr_un` from register pointer,
+    /// whose length is in the next register.
+    fn arg_sockaddr(&self, n: usize, path: &mut [u8; MAXPATH]) -> Result<(), Errno> {
+        let addr = self.arg_raw(n);
+        let len = self.arg_i32(n+1);
+        let family_size = mem::size_of::<u16>();
+        if len <= family_size as i32 {
+            return Err(Errno::EINVAL)
+        }
+        let path_size = core::cmp::min(len as usize - family_size, MAXPATH);
+
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
+        let mut family: u16 = 0;
+        pd.copy_in(addr, &mut family as *mut u16 as *mut u8, family_size)?;
+        if family != AF_UNIX as u16 {
+            return Err(Errno::EAFNOSUPPORT)
+        }
+        pd.copy_in(addr+family_size, path.as_mut_ptr(), path_size)?;
+        if !path.contains(&0) {
+            return Err(Errno::ENAMETOOLONG)
+        }
         Ok(())
     }
 
     /// Fetch a virtual address at virtual address `addr`.
-    fn fetch_addr(&self, addr: usize) -> Result<usize, &'static str> {
-        let pd = unsafe { self.data.get().as_ref().unwrap() };
-        if addr + mem::size_of::<usize>() > pd.sz {
-            Err("input addr > proc's mem size")
+    fn fetch_addr(&self, addr: usize) -> Result<usize, Errno> {
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
+        if addr + mem::size_of::<usize>() > pd.space().size() {
+            Err(Errno::EFAULT)
         } else {
             let mut ret: usize = 0;
-            match pd.copy_in(
+            pd.copy_in(
                 addr, 
                 &mut ret as *mut usize as *mut u8, 
                 mem::size_of::<usize>()
-            ) {
-                Ok(_) => Ok(ret),
-                Err(_) => Err("pagetable copy_in eror"),
-            }
+            )?;
+            Ok(ret)
         }
     }
 
     /// Fetch a null-nullterminated string from virtual address `addr` into the kernel buffer.
-    fn fetch_str(&self, addr: usize, dst: &mut [u8]) -> Result<(), &'static str>{
-        let pd = unsafe { self.data.get().as_ref().unwrap() };
-        pd.pagetable.as_ref().unwrap().copy_in_str(addr, dst)
+    fn fetch_str(&self, addr: usize, dst: &mut [u8]) -> Result<(), Errno>{
+        let pd = unsafe { self.data.get().as_mut().unwrap() };
+        pd.copy_in_str(addr, dst)
+    }
+
+    /// Execute the file at `path`, with the arguments and environment
+    /// fetched from the user arrays `uargv` and `uenvp`.
+    fn execve(&mut self, path: &mut [u8; MAXPATH], uargv: usize, uenvp: usize) -> Result<usize, Errno> {
+        let mut args = ExecArgs::new()?;
+        self.fetch_exec_strs(uargv, false, &mut args)?;
+        self.fetch_exec_strs(uenvp, true, &mut args)?;
+        elf::exec(self, path, &mut args)
+    }
+
+    /// Fetch the null-terminated array of strings at virtual address `addr` into `args`,
+    /// as the environment if `env` is true, or else the arguments of exec.
+    /// A null `addr` is taken as an empty array.
+    fn fetch_exec_strs(&self, addr: usize, env: bool, args: &mut ExecArgs) -> Result<(), Errno> {
+        if addr == 0 {
+            return Ok(())
+        }
+        let mut uptr = addr;
+        loop {
+            let ustr = self.fetch_addr(uptr)?;
+            if ustr == 0 {
+                return Ok(())
+            }
+            args.push(env, |free| self.fetch_str(ustr, free))?;
+            uptr += mem::size_of::<usize>();
+        }
     }
 }
 
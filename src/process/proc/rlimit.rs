@@ -0,0 +1,96 @@
+//! Per-process resource limits, set by setrlimit and inherited by the children
+
+use core::cmp::min;
+
+use crate::consts::{MAXSTACK, RLIMIT_AS, RLIMIT_CPU, RLIMIT_FSIZE, RLIMIT_NOFILE, RLIMIT_NPROC,
+    RLIMIT_STACK, RLIM_INFINITY, RLIM_NLIMITS, fs::NFILE, signal::{SIGKILL, SIGXCPU, SIGXFSZ}};
+use crate::errno::Errno;
+
+use super::{Proc, ProcExcl};
+
+/// Soft and hard limit of a resource.
+/// Its layout should be the same as `struct rlimit` in the user space.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RLimit {
+    /// enforced by the kernel
+    pub cur: usize,
+    /// ceiling of the soft limit, which can only be lowered
+    pub max: usize,
+}
+
+const UNLIMITED: RLimit = RLimit { cur: RLIM_INFINITY, max: RLIM_INFINITY };
+const NOFILE: RLimit = RLimit { cur: NFILE, max: NFILE };
+const STACK: RLimit = RLimit { cur: MAXSTACK, max: MAXSTACK };
+
+/// Resource limits of a process, indexed by the RLIMIT_* numbers.
+#[derive(Clone, Copy)]
+pub struct RLimits([RLimit; RLIM_NLIMITS]);
+
+impl RLimits {
+    /// The limits of the first process.
+    /// Open files are limited by the size of the file table,
+    /// the stack by the space reserved for it, and others are unlimited.
+    pub const fn new() -> Self {
+        Self([
+            UNLIMITED, UNLIMITED, UNLIMITED, STACK, UNLIMITED,
+            UNLIMITED, UNLIMITED, NOFILE, UNLIMITED, UNLIMITED,
+        ])
+    }
+
+    /// Check if the resource is limited by the kernel.
+    fn supported(resource: usize) -> bool {
+        matches!(resource, RLIMIT_CPU | RLIMIT_FSIZE | RLIMIT_STACK | RLIMIT_NPROC | RLIMIT_NOFILE | RLIMIT_AS)
+    }
+
+    /// The soft limit of the resource.
+    #[inline]
+    pub fn cur(&self, resource: usize) -> usize {
+        self.0[resource].cur
+    }
+
+    pub fn get(&self, resource: usize) -> Result<RLimit, Errno> {
+        if !Self::supported(resource) {
+            return Err(Errno::EINVAL)
+        }
+        Ok(self.0[resource])
+    }
+
+    /// Set the limits of the resource.
+    /// The soft limit should not exceed the hard one, and the hard one cannot be raised.
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> Result<(), Errno> {
+        if !Self::supported(resource) || limit.cur > limit.max {
+            return Err(Errno::EINVAL)
+        }
+        if limit.max > self.0[resource].max {
+            return Err(Errno::EPERM)
+        }
+        self.0[resource] = limit;
+        Ok(())
+    }
+}
+
+impl Proc {
+    /// Check the cpu time against RLIMIT_CPU after a tick is charged, with its [`ProcExcl`] held.
+    /// SIGXCPU is posted on each tick past the soft limit, and SIGKILL at the hard limit.
+    pub(super) fn check_cpu_limit(&self, excl: &mut ProcExcl) {
+        let limit = unsafe { self.data.get().as_ref().unwrap().rlimits.0[RLIMIT_CPU] };
+        if excl.cpu_ticks >= limit.max {
+            self.post_signal(excl, SIGKILL);
+        } else if excl.cpu_ticks >= limit.cur {
+            self.post_signal(excl, SIGXCPU);
+        }
+    }
+
+    /// Limit `count` bytes written by the process at `offset` of a file to RLIMIT_FSIZE.
+    /// Return the bytes allowed to write, or EFBIG with SIGXFSZ posted if none.
+    pub fn fsize_limit(&self, offset: usize, count: usize) -> Result<usize, Errno> {
+        let limit = unsafe { self.data.get().as_ref().unwrap().rlimits.cur(RLIMIT_FSIZE) };
+        if offset >= limit && count > 0 {
+            let mut excl = self.excl.lock();
+            self.post_signal(&mut excl, SIGXFSZ);
+            return Err(Errno::EFBIG)
+        }
+        Ok(min(count, limit.saturating_sub(offset)))
+    }
+}
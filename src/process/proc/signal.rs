@@ -0,0 +1,367 @@
+//! POSIX-style signals
+
+use core::mem;
+use core::sync::atomic::Ordering;
+
+use crate::consts::signal::*;
+use crate::errno::Errno;
+use crate::process::PROC_MANAGER;
+
+use super::{Proc, ProcExcl, ProcState, TrapFrame};
+
+/// Action taken when a signal is delivered, set by sigaction.
+/// Its layout should be the same as `struct sigaction` in the user space.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SigAction {
+    /// user handler, or [`SIG_DFL`] and [`SIG_IGN`]
+    handler: usize,
+    /// signals blocked during the handler
+    mask: u32,
+    flags: u32,
+    /// where the handler returns to, which should call sigreturn
+    restorer: usize,
+}
+
+impl SigAction {
+    const fn new() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: 0,
+            flags: 0,
+            restorer: 0,
+        }
+    }
+}
+
+/// Default action of a signal.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+impl DefaultAction {
+    fn of(sig: usize) -> Self {
+        match sig {
+            SIGCHLD => Self::Ignore,
+            SIGCONT => Self::Continue,
+            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => Self::Stop,
+            _ => Self::Terminate,
+        }
+    }
+}
+
+/// Signals that cannot be caught, blocked or ignored.
+const UNBLOCKABLE: u32 = (1 << SIGKILL) | (1 << SIGSTOP);
+/// Signals that stop the process by default.
+const STOP_MASK: u32 = (1 << SIGSTOP) | (1 << SIGTSTP) | (1 << SIGTTIN) | (1 << SIGTTOU);
+
+#[inline]
+fn sig_bit(sig: usize) -> u32 {
+    1 << sig
+}
+
+/// Signal state of a process, protected by [`ProcExcl`],
+/// because signals are posted by other processes.
+pub struct SigState {
+    /// posted but not delivered yet
+    pending: u32,
+    /// blocked from delivery
+    blocked: u32,
+    actions: [SigAction; NSIG],
+}
+
+impl SigState {
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::new(); NSIG],
+        }
+    }
+
+    /// Clean up when the process is freed.
+    pub fn cleanup(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The state inherited by a child, typically when forking.
+    /// Pending signals are not inherited.
+    pub fn inherited(&self) -> Self {
+        Self {
+            pending: 0,
+            blocked: self.blocked,
+            actions: self.actions,
+        }
+    }
+
+    /// Reset the caught signals to their default actions when exec,
+    /// since the handlers are gone with the old user space.
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::new();
+            }
+        }
+    }
+
+    /// Check if SIGCONT is posted but not delivered yet.
+    pub fn cont_pending(&self) -> bool {
+        self.pending & sig_bit(SIGCONT) != 0
+    }
+
+    /// Check if the signal would terminate the process when delivered.
+    fn terminates(&self, sig: usize) -> bool {
+        self.blocked & sig_bit(sig) == 0
+            && self.actions[sig].handler == SIG_DFL
+            && DefaultAction::of(sig) == DefaultAction::Terminate
+    }
+
+    /// Check if the signal would be discarded when delivered.
+    /// SIGCONT by default is also, since it has done its work when posted.
+    fn ignored(&self, sig: usize) -> bool {
+        match self.actions[sig].handler {
+            SIG_IGN => true,
+            SIG_DFL => match DefaultAction::of(sig) {
+                DefaultAction::Ignore | DefaultAction::Continue => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Check if the signal would be delivered to take effect, i.e., neither blocked nor ignored.
+    fn interrupts(&self, sig: usize) -> bool {
+        self.blocked & sig_bit(sig) == 0 && !self.ignored(sig)
+    }
+
+    /// Check if any pending signal would be delivered to take effect,
+    /// which interrupts the process sleeping in a syscall.
+    pub fn interrupting(&self) -> bool {
+        (1..NSIG).any(|sig| self.pending & sig_bit(sig) != 0 && self.interrupts(sig))
+    }
+
+    /// Set the action of a signal and return the old one.
+    pub fn set_action(&mut self, sig: usize, action: Option<SigAction>) -> Result<SigAction, Errno> {
+        if sig == 0 || sig >= NSIG {
+            return Err(Errno::EINVAL)
+        }
+        let old = self.actions[sig];
+        if let Some(action) = action {
+            if sig_bit(sig) & UNBLOCKABLE != 0 {
+                return Err(Errno::EINVAL)
+            }
+            self.actions[sig] = action;
+            // discard the pending one that is going to be ignored
+            if action.handler == SIG_IGN
+                || (action.handler == SIG_DFL && DefaultAction::of(sig) == DefaultAction::Ignore)
+            {
+                self.pending &= !sig_bit(sig);
+            }
+        }
+        Ok(old)
+    }
+
+    /// Change the blocked mask and return the old one.
+    pub fn set_blocked(&mut self, how: i32, set: Option<u32>) -> Result<u32, Errno> {
+        let old = self.blocked;
+        if let Some(set) = set {
+            let blocked = match how {
+                SIG_BLOCK => old | set,
+                SIG_UNBLOCK => old & !set,
+                SIG_SETMASK => set,
+                _ => return Err(Errno::EINVAL),
+            };
+            self.blocked = blocked & !UNBLOCKABLE;
+        }
+        Ok(old)
+    }
+}
+
+/// Saved on the user stack when delivering a signal to the user handler,
+/// and restored by sigreturn.
+#[repr(C)]
+struct SigFrame {
+    /// user registers when the signal is delivered
+    tf: TrapFrame,
+    /// blocked mask before the handler
+    blocked: u32,
+}
+
+impl Proc {
+    /// Post a signal to the process, with its [`ProcExcl`] held.
+    /// SIGKILL and signals that terminate the process are also noticed by the killed flag,
+    /// so that the process exits.
+    /// A sleeping process is woken up for any signal neither blocked nor ignored,
+    /// and its interruptible sleep returns EINTR.
+    pub fn post_signal(&self, excl: &mut ProcExcl, sig: usize) {
+        match sig {
+            SIGKILL => {
+                self.killed.store(true, Ordering::Relaxed);
+                if excl.term_sig == 0 {
+                    excl.term_sig = SIGKILL;
+                }
+                if excl.state == ProcState::SLEEPING || excl.state == ProcState::STOPPED {
+                    self.set_runnable(excl);
+                }
+                return
+            },
+            SIGCONT => {
+                excl.sig.pending &= !STOP_MASK;
+                if excl.state == ProcState::STOPPED {
+                    // not reported any more once continued
+                    excl.stop_sig = 0;
+                    self.set_runnable(excl);
+                }
+            },
+            _ if sig_bit(sig) & STOP_MASK != 0 => {
+                excl.sig.pending &= !sig_bit(SIGCONT);
+            },
+            _ => {},
+        }
+        excl.sig.pending |= sig_bit(sig);
+        if excl.sig.terminates(sig) {
+            self.killed.store(true, Ordering::Relaxed);
+            if excl.term_sig == 0 {
+                excl.term_sig = sig;
+            }
+        }
+        if excl.state == ProcState::SLEEPING && excl.sig.interrupts(sig) {
+            self.set_runnable(excl);
+        }
+    }
+
+    /// Check if the process is killed or has a signal to deliver,
+    /// which should interrupt its sleeping in a syscall with EINTR.
+    pub fn interrupted(&self) -> bool {
+        self.killed.load(Ordering::Relaxed) || self.excl.lock().sig.interrupting()
+    }
+
+    /// Post a signal caused by the process itself, e.g., SIGSEGV for a bad memory access.
+    /// Returning to the faulting instruction with the signal blocked or ignored
+    /// would only fault again, so its default action is restored in that case.
+    pub fn force_signal(&self, sig: usize) {
+        let mut excl = self.excl.lock();
+        if excl.sig.blocked & sig_bit(sig) != 0 || excl.sig.actions[sig].handler == SIG_IGN {
+            excl.sig.blocked &= !sig_bit(sig);
+            excl.sig.actions[sig] = SigAction::new();
+        }
+        self.post_signal(&mut excl, sig);
+    }
+
+    /// Deliver the pending signals before returning to the user space.
+    /// The process may exit or stop here.
+    /// At most one signal is delivered to a user handler at a time,
+    /// by building a [`SigFrame`] on the user stack.
+    /// A syscall interrupted by the signals fails with EINTR if a handler is run,
+    /// otherwise it is restarted, e.g., after the process is stopped and continued.
+    pub fn handle_signals(&mut self) {
+        loop {
+            self.check_abondon(-1);
+
+            let mut excl = self.excl.lock();
+            let deliverable = excl.sig.pending & !excl.sig.blocked;
+            if deliverable == 0 {
+                drop(excl);
+                self.restart_syscall();
+                return
+            }
+            let sig = deliverable.trailing_zeros() as usize;
+            excl.sig.pending &= !sig_bit(sig);
+            let action = excl.sig.actions[sig];
+            match action.handler {
+                SIG_IGN => continue,
+                SIG_DFL => match DefaultAction::of(sig) {
+                    DefaultAction::Ignore | DefaultAction::Continue => continue,
+                    DefaultAction::Terminate => {
+                        if excl.term_sig == 0 {
+                            excl.term_sig = sig;
+                        }
+                        drop(excl);
+                        self.abondon(-1);
+                    },
+                    DefaultAction::Stop => {
+                        drop(excl);
+                        unsafe { PROC_MANAGER.stopping(self.index, sig); }
+                        continue
+                    },
+                },
+                handler => {
+                    let blocked = excl.sig.blocked;
+                    let mut mask = action.mask;
+                    if action.flags & SA_NODEFER == 0 {
+                        mask |= sig_bit(sig);
+                    }
+                    excl.sig.blocked = (blocked | mask) & !UNBLOCKABLE;
+                    if action.flags & SA_RESETHAND != 0 {
+                        excl.sig.actions[sig] = SigAction::new();
+                    }
+                    drop(excl);
+                    let pdata = self.data.get_mut();
+                    pdata.restart = None;
+                    pdata.sleep_deadline = None;
+                    if self.push_sig_frame(sig, handler, action.restorer, blocked).is_err() {
+                        // cannot deliver it on the user stack
+                        self.excl.lock().term_sig = SIGSEGV;
+                        self.abondon(-1);
+                    }
+                    return
+                },
+            }
+        }
+    }
+
+    /// Execute the interrupted syscall again when returning to the user space.
+    /// A nanosleep goes on to its original deadline,
+    /// while other timed waits start over with their full timeout.
+    fn restart_syscall(&mut self) {
+        let pdata = self.data.get_mut();
+        if let Some(a0) = pdata.restart.take() {
+            let tf = unsafe { pdata.tf.as_mut().unwrap() };
+            tf.a0 = a0;
+            tf.epc -= 4;
+        }
+    }
+
+    /// Save the user registers on the user stack,
+    /// and redirect the user to the signal handler.
+    fn push_sig_frame(&mut self, sig: usize, handler: usize, restorer: usize, blocked: u32)
+        -> Result<(), Errno>
+    {
+        let pdata = self.data.get_mut();
+        let tf = unsafe { pdata.tf.as_mut().unwrap() };
+        let frame_size = mem::size_of::<SigFrame>();
+        let sp = tf.sp.checked_sub(frame_size).ok_or(Errno::EFAULT)? & !0xf;
+        let frame = SigFrame {
+            tf: unsafe { core::ptr::read(tf) },
+            blocked,
+        };
+        pdata.copy_out(&frame as *const SigFrame as *const u8, sp, frame_size)?;
+
+        tf.epc = handler;
+        tf.a0 = sig;
+        tf.ra = restorer;
+        tf.sp = sp;
+        Ok(())
+    }
+
+    /// Restore the user registers and blocked mask saved by [`push_sig_frame`].
+    /// Return the restored a0, which is then written back by the syscall path.
+    pub fn sig_return(&mut self) -> Result<usize, Errno> {
+        let pdata = self.data.get_mut();
+        let tf = unsafe { pdata.tf.as_mut().unwrap() };
+        let mut frame = mem::MaybeUninit::<SigFrame>::uninit();
+        pdata.copy_in(tf.sp, frame.as_mut_ptr() as *mut u8, mem::size_of::<SigFrame>())?;
+        let frame = unsafe { frame.assume_init() };
+
+        // the kernel part of the trapframe is set again when returning to the user
+        *tf = frame.tf;
+        let mut excl = self.excl.lock();
+        excl.sig.blocked = frame.blocked & !UNBLOCKABLE;
+        drop(excl);
+        Ok(tf.a0)
+    }
+}
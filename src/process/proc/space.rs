@@ -0,0 +1,221 @@
+//! User address space, shared by the threads of a process
+
+use array_macro::array;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::convert::TryFrom;
+
+use crate::consts::{PGSIZE, NVMA};
+use crate::errno::Errno;
+use crate::mm::{PageTable, PhysAddr, PteFlag, RawPage, RawSinglePage, VirtAddr, pg_round_down};
+
+use super::vma::{Vma, PageFill, Unmapped};
+
+/// User address space of a process,
+/// including the user pagetable, the program break and the memory mapped areas.
+/// It is shared by the threads of a process, and protected by a spinlock,
+/// so it should not be held across anything that may sleep.
+///
+/// Note: There is no remote TLB shootdown.
+/// Other harts running in the same address space only see
+/// the modification after they trap into the kernel.
+pub struct UserSpace {
+    pagetable: Box<PageTable>,
+    /// size of the user memory starting from zero, i.e., the program break
+    sz: usize,
+    /// the user stack grows down from here, which is also where the heap starts
+    stack_top: usize,
+    /// maximum size of the user stack
+    stack_limit: usize,
+    /// memory mapped areas above the program break
+    pub(super) vmas: [Option<Vma>; NVMA],
+    /// number of threads running in this space
+    users: usize,
+}
+
+impl UserSpace {
+    /// Allocate a new user space with the trapframe mapped at TRAPFRAME.
+    pub fn new(tf: usize) -> Option<Self> {
+        let pagetable = PageTable::alloc_proc_pagetable(tf)?;
+        Some(Self::from_pagetable(pagetable, 0, 0, 0))
+    }
+
+    /// Wrap an already loaded user pagetable, typically by exec.
+    /// The user stack below `stack_top` may grow up to `stack_limit`,
+    /// see [`fault`].
+    ///
+    /// [`fault`]: UserSpace::fault
+    pub fn from_pagetable(pagetable: Box<PageTable>, sz: usize, stack_top: usize, stack_limit: usize) -> Self {
+        Self {
+            pagetable,
+            sz,
+            stack_top,
+            stack_limit,
+            vmas: array![_ => None; NVMA],
+            users: 1,
+        }
+    }
+
+    /// Load the initcode for the very first process.
+    pub fn init(&mut self, code: &[u8]) {
+        self.pagetable.uvm_init(code);
+        self.sz = PGSIZE;
+    }
+
+    #[inline]
+    pub fn as_satp(&self) -> usize {
+        self.pagetable.as_satp()
+    }
+
+    /// Size of the user memory, i.e., the program break.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.sz
+    }
+
+    #[inline]
+    pub fn pagetable(&mut self) -> &mut PageTable {
+        &mut self.pagetable
+    }
+
+    /// Attach a new thread to this space, mapping its trapframe at `va`.
+    pub fn attach(&mut self, va: usize, tf: usize) -> Result<(), &'static str> {
+        self.pagetable.map_pages(
+            VirtAddr::try_from(va)?,
+            PGSIZE,
+            PhysAddr::try_from(tf)?,
+            PteFlag::R | PteFlag::W,
+        )?;
+        self.users += 1;
+        Ok(())
+    }
+
+    /// Detach a thread from this space, unmapping its trapframe at `va`.
+    /// The trapframe itself is freed by its owner.
+    /// If it is the last one, unmap all the memory mapped areas.
+    pub fn detach(&mut self, va: usize) -> Vec<Unmapped> {
+        self.pagetable.uvm_unmap(va, 1, false);
+        self.users -= 1;
+        if self.users == 0 {
+            self.munmap_all()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Simply check if the user passed-in virtual address is in range.
+    pub fn check_user_addr(&self, user_addr: usize) -> Result<(), Errno> {
+        if user_addr > self.sz && !self.in_vma(user_addr) {
+            Err(Errno::EFAULT)
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    pub fn copy_out(&mut self, src: *const u8, dst: usize, count: usize) -> Result<(), Errno> {
+        self.pagetable.copy_out(src, dst, count)
+    }
+
+    #[inline]
+    pub fn copy_in(&self, src: usize, dst: *mut u8, count: usize) -> Result<(), Errno> {
+        self.pagetable.copy_in(src, dst, count)
+    }
+
+    #[inline]
+    pub fn copy_in_str(&self, src: usize, dst: &mut [u8]) -> Result<(), Errno> {
+        self.pagetable.copy_in_str(src, dst)
+    }
+
+    /// Handle a page fault at the user virtual address `va`.
+    /// `store` is true if the fault is caused by a store.
+    /// Pages within the program break are allocated and zeroed on demand,
+    /// which are not executable.
+    /// The user stack grows this way too, down to its limit reserved by exec,
+    /// or the current `stack_rlimit` if lower,
+    /// while the pages below it are either loaded by exec or in the guard gap.
+    /// Return a [`PageFill`] if the page should be read from a mapped file,
+    /// which may sleep and thus must be done without the lock held.
+    pub fn fault(&mut self, va: usize, store: bool, stack_rlimit: usize) -> Result<Option<PageFill>, &'static str> {
+        if va >= self.sz {
+            return self.vma_fault(va, store)
+        }
+        let page = VirtAddr::try_from(pg_round_down(va))?;
+        if self.pagetable.uvm_fault(page, store)? {
+            return Ok(None)
+        }
+        if va < self.stack_top - min(self.stack_limit, stack_rlimit) {
+            return Err("stack overflow beyond its limit")
+        }
+        let mem = unsafe { RawSinglePage::try_new_zeroed() }
+            .map_err(|_| "not enough memory for lazy allocation")?;
+        self.pagetable.uvm_map_new(page, mem, PteFlag::R | PteFlag::W | PteFlag::U)?;
+        Ok(None)
+    }
+
+    /// Check if the user virtual address `va` can be executed,
+    /// after an instruction page fault is handled by [`fault`].
+    ///
+    /// [`fault`]: UserSpace::fault
+    pub fn executable(&self, va: usize) -> bool {
+        match VirtAddr::try_from(va) {
+            Ok(va) => self.pagetable.uvm_executable(va),
+            Err(_) => false,
+        }
+    }
+
+    /// Increase/Decrease the user program break.
+    /// Return the previous program break if succeed.
+    /// Growing only moves the size, the pages are allocated lazily when first accessed.
+    /// The user memory, including the memory mapped areas, should not exceed `limit` bytes.
+    pub fn sbrk(&mut self, increment: i32, limit: usize) -> Result<usize, Errno> {
+        let old_size = self.sz;
+        if increment > 0 {
+            let new_size = old_size + (increment as usize);
+            if new_size > self.mmap_bottom() || new_size > limit.saturating_sub(self.vma_size()) {
+                return Err(Errno::ENOMEM)
+            }
+            self.sz = new_size;
+        } else if increment < 0 {
+            let decrement = (-(increment as isize)) as usize;
+            if decrement > old_size {
+                return Err(Errno::EINVAL)
+            }
+            let new_size = old_size - decrement;
+            self.pagetable.uvm_dealloc(old_size, new_size);
+            self.sz = new_size;
+        }
+        Ok(old_size)
+    }
+
+    /// Copy the user memory to the child's user space, typically when forking.
+    /// Shared memory mapped areas should be populated in advance by the caller,
+    /// so that both refer to the same pages.
+    pub fn copy_to(&mut self, child: &mut Self) -> Result<(), Errno> {
+        self.pagetable.uvm_copy(&mut child.pagetable, 0, self.sz, false)?;
+        child.sz = self.sz;
+        child.stack_top = self.stack_top;
+        child.stack_limit = self.stack_limit;
+        for i in 0..NVMA {
+            let (start, end, shared) = match self.vmas[i] {
+                Some(ref vma) => (vma.start, vma.end, vma.is_shared()),
+                None => continue,
+            };
+            self.pagetable.uvm_copy(&mut child.pagetable, start, end, shared)?;
+            child.vmas[i] = self.vmas[i].clone();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UserSpace {
+    /// Free the user memory and the pagetable.
+    /// Dirty pages of shared file mappings should already be written back,
+    /// see [`ProcData::release_space`].
+    fn drop(&mut self) {
+        self.vma_clear();
+        self.pagetable.dealloc_proc_pagetable(self.sz);
+    }
+}
@@ -1,20 +1,26 @@
 use array_macro::array;
 
 use alloc::string::String;
-use alloc::boxed::Box;
 use alloc::sync::Arc;
+use core::cmp::max;
 use core::convert::TryInto;
 use core::fmt::Display;
 use core::mem;
 
-use crate::consts::{MAXPATH, MAXARG, MAXARGLEN, fs::MAX_DIR_SIZE};
-use crate::process::PROC_MANAGER;
-use crate::fs::{ICACHE, Inode, InodeType, LOG, File, Pipe, FileStat};
+use crate::consts::{driver::{DEV_CONSOLE, DEV_FIFO}, MAXPATH, MAP_ANONYMOUS, WNOHANG, WUNTRACED, CLOCK_MONOTONIC, CLOCK_REALTIME,
+    FUTEX_WAIT, FUTEX_WAKE, signal::NSIG};
+use crate::consts::fs::{MAX_DIR_SIZE, MAY_EXEC, MAY_WRITE, AF_UNIX, SOCK_STREAM, NFILE, F_DUPFD, F_GETFD, F_SETFD, F_GETFL, F_SETFL, FD_CLOEXEC};
+use crate::errno::Errno;
+use crate::process::{PROC_MANAGER, WaitTarget, futex_wait, futex_wake};
+use crate::fs::{self, ICACHE, Inode, InodeType, LOG, File, Pipe, Socket, FileStat, PollFd};
 use crate::trap;
+use crate::timer::{self, TimeSpec, TimeVal};
+use crate::driver::{console, rtc};
 
-use super::{Proc, elf};
+use super::{Proc, rlimit::RLimit, signal::SigAction};
 
-pub type SysResult = Result<usize, ()>;
+/// Result of a system call, the error is returned to the user as a negative number.
+pub type SysResult = Result<usize, Errno>;
 
 pub trait Syscall {
     fn sys_fork(&mut self) -> SysResult;
@@ -38,6 +44,45 @@ pub trait Syscall {
     fn sys_link(&mut self) -> SysResult;
     fn sys_mkdir(&mut self) -> SysResult;
     fn sys_close(&mut self) -> SysResult;
+    fn sys_mmap(&mut self) -> SysResult;
+    fn sys_munmap(&mut self) -> SysResult;
+    fn sys_clone(&mut self) -> SysResult;
+    fn sys_join(&mut self) -> SysResult;
+    fn sys_sigaction(&mut self) -> SysResult;
+    fn sys_sigprocmask(&mut self) -> SysResult;
+    fn sys_sigreturn(&mut self) -> SysResult;
+    fn sys_setpriority(&mut self) -> SysResult;
+    fn sys_sched_setaffinity(&mut self) -> SysResult;
+    fn sys_trace(&mut self) -> SysResult;
+    fn sys_waitpid(&mut self) -> SysResult;
+    fn sys_setpgid(&mut self) -> SysResult;
+    fn sys_getpgid(&mut self) -> SysResult;
+    fn sys_setsid(&mut self) -> SysResult;
+    fn sys_tcsetpgrp(&mut self) -> SysResult;
+    fn sys_tcgetpgrp(&mut self) -> SysResult;
+    fn sys_execve(&mut self) -> SysResult;
+    fn sys_getrlimit(&mut self) -> SysResult;
+    fn sys_setrlimit(&mut self) -> SysResult;
+    fn sys_chmod(&mut self) -> SysResult;
+    fn sys_chown(&mut self) -> SysResult;
+    fn sys_setuid(&mut self) -> SysResult;
+    fn sys_getuid(&mut self) -> SysResult;
+    fn sys_sigalarm(&mut self) -> SysResult;
+    fn sys_clock_gettime(&mut self) -> SysResult;
+    fn sys_nanosleep(&mut self) -> SysResult;
+    fn sys_gettimeofday(&mut self) -> SysResult;
+    fn sys_futex(&mut self) -> SysResult;
+    fn sys_fcntl(&mut self) -> SysResult;
+    fn sys_poll(&mut self) -> SysResult;
+    fn sys_mkfifo(&mut self) -> SysResult;
+    fn sys_socket(&mut self) -> SysResult;
+    fn sys_bind(&mut self) -> SysResult;
+    fn sys_listen(&mut self) -> SysResult;
+    fn sys_accept(&mut self) -> SysResult;
+    fn sys_connect(&mut self) -> SysResult;
+    fn sys_socketpair(&mut self) -> SysResult;
+    fn sys_setgid(&mut self) -> SysResult;
+    fn sys_getgid(&mut self) -> SysResult;
 }
 
 impl Syscall for Proc {
@@ -67,14 +112,23 @@ impl Syscall for Proc {
 
     /// Wait for any child(if any) process to exit.
     /// Recycle the chile process and return its pid.
+    /// The exit status is copied out as it is, or -1 if the child is killed.
     fn sys_wait(&mut self) -> SysResult {
         let addr = self.arg_addr(0);
-        let ret =  unsafe { PROC_MANAGER.waiting(self.index, addr) };
+        if addr != 0 {
+            self.data.get_mut().lazy_populate(addr, mem::size_of::<i32>());
+        }
+        let ret = unsafe { PROC_MANAGER.waiting(self.index, WaitTarget::Any, 0) };
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].wait(addr={:#x}) = {:?}(pid)", self.excl.lock().pid, addr, ret);
+        println!("[{}].wait(addr={:#x}) = {:?}", self.excl.lock().pid, addr, ret);
 
-        ret
+        let (pid, status) = ret?.unwrap();
+        if addr != 0 {
+            let status = status.raw();
+            self.data.get_mut().copy_out(&status as *const i32 as *const u8, addr, mem::size_of::<i32>())?;
+        }
+        Ok(pid)
     }
 
     /// Create pipe for user.
@@ -83,22 +137,21 @@ impl Syscall for Proc {
         let addr_fdread = pipefds_addr;
         let addr_fdwrite = pipefds_addr+mem::size_of::<u32>();
 
-        // alloc fd
+        // alloc pipe and assign the files to process
         let pdata = self.data.get_mut();
-        let (fd_read, fd_write) = pdata.alloc_fd2().ok_or(())?;
+        let (file_read, file_write) = Pipe::create()?;
+        let (fd_read, fd_write) = pdata.install_fd2(file_read, file_write)?;
 
-        // alloc pipe
-        let (file_read, file_write) = Pipe::create().ok_or(())?;
-
-        // transfer fd to user
+        // transfer fd to user, or close them if failed
         let fd_read_u32: u32 = fd_read.try_into().unwrap();
         let fd_write_u32: u32 = fd_write.try_into().unwrap();
-        pdata.copy_out(&fd_read_u32 as *const u32 as *const u8, addr_fdread, mem::size_of::<u32>())?;
-        pdata.copy_out(&fd_write_u32 as *const u32 as *const u8, addr_fdwrite, mem::size_of::<u32>())?;
-
-        // assign the file to process
-        pdata.open_files[fd_read].replace(file_read);
-        pdata.open_files[fd_write].replace(file_write);
+        let ret = pdata.copy_out(&fd_read_u32 as *const u32 as *const u8, addr_fdread, mem::size_of::<u32>())
+            .and_then(|()| pdata.copy_out(&fd_write_u32 as *const u32 as *const u8, addr_fdwrite, mem::size_of::<u32>()));
+        if let Err(e) = ret {
+            drop(pdata.close_fd(fd_read));
+            drop(pdata.close_fd(fd_write));
+            return Err(e)
+        }
 
         #[cfg(feature = "trace_syscall")]
         println!("[{}].pipe(addr={:#x}) = ok, fd=[{},{}]", self.excl.lock().pid, pipefds_addr, fd_read, fd_write);
@@ -108,110 +161,83 @@ impl Syscall for Proc {
 
     /// Read form file descriptor.
     fn sys_read(&mut self) -> SysResult {
-        let fd = self.arg_fd(0)?;
+        let file = self.arg_file(0)?;
         let user_addr = self.arg_addr(1);
         let count = self.arg_i32(2);
-        if count <= 0 || self.data.get_mut().check_user_addr(user_addr).is_err() {
-            return Err(())
+        if count <= 0 {
+            return Err(Errno::EINVAL)
         }
+        self.data.get_mut().check_user_addr(user_addr)?;
         let count = count as u32;
-        
-        let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
+
+        // populate the user buffer before the file holds any lock
+        let pdata = self.data.get_mut();
+        pdata.lazy_populate(user_addr, count as usize);
         let ret = file.fread(user_addr, count);
+        drop(file);
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].read(fd={}, addr={:#x}, count={}) = {:?}", self.excl.lock().pid, fd, user_addr, count, ret);
+        println!("[{}].read(fd={}, addr={:#x}, count={}) = {:?}", self.excl.lock().pid, self.arg_i32(0), user_addr, count, ret);
 
         ret.map(|count| count as usize)
     }
 
-    /// Kill a process.
-    /// Note: Other signals are not supported yet.
+    /// Send a signal to a process.
+    /// Zero pid means the current process group,
+    /// and a pid less than -1 means the process group of its absolute value.
     fn sys_kill(&mut self) -> SysResult {
         let pid = self.arg_i32(0);
-        if pid < 0 {
-            return Err(())
+        let sig = self.arg_i32(1);
+        if pid == -1 || sig < 0 || sig as usize >= NSIG {
+            return Err(Errno::EINVAL)
         }
-        let pid = pid as usize;
-        let ret = unsafe { PROC_MANAGER.kill(pid) };
+        let sig = sig as usize;
+        let ret = match pid {
+            0 => {
+                let pgid = self.excl.lock().pgid;
+                unsafe { PROC_MANAGER.kill_pgrp(pgid, sig) }
+            },
+            pid if pid < 0 => unsafe { PROC_MANAGER.kill_pgrp(pid.wrapping_neg() as usize, sig) },
+            pid => unsafe { PROC_MANAGER.kill(pid as usize, sig) },
+        };
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].kill(pid={}) = {:?}", self.excl.lock().pid, pid, ret);
+        println!("[{}].kill(pid={}, sig={}) = {:?}", self.excl.lock().pid, pid, sig, ret);
 
         ret.map(|()| 0)
     }
 
     /// Load an elf binary and execuate it the currrent process context.
+    /// A script is run by its interpreter, see [`elf::exec`](super::elf::exec).
+    /// The environment is empty.
     fn sys_exec(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
-
-        let mut result: SysResult = Err(());
-        let mut error = "too many arguments";
-        let mut uarg: usize;
+        self.arg_str(0, &mut path)?;
         let uargv = self.arg_addr(1);
-        let mut argv: [Option<Box<[u8; MAXARGLEN]>>; MAXARG] = array![_ => None; MAXARG];
-        for i in 0..MAXARG {
-            // fetch ith arg's address into uarg
-            match self.fetch_addr(uargv+i*mem::size_of::<usize>()) {
-                Ok(addr) => uarg = addr,
-                Err(s) => {
-                    error = s;
-                    break
-                },
-            }
-            if uarg == 0 {
-                match elf::load(self, &path, &argv[..i]) {
-                    Ok(ret) => result = Ok(ret),
-                    Err(s) => error = s,
-                }
-                break       
-            }
 
-            // allocate kernel space to copy in user arg
-            match Box::try_new_zeroed() {
-                Ok(b) => unsafe { argv[i] = Some(b.assume_init()) },
-                Err(_) => {
-                    error = "not enough kernel memory";
-                    break
-                },
-            }
-
-            // copy user arg into kernel space
-            if let Err(s) = self.fetch_str(uarg, argv[i].as_deref_mut().unwrap()) {
-                error = s;
-                break
-            }
-        }
+        let result = self.execve(&mut path, uargv, 0);
 
         #[cfg(feature = "trace_syscall")]
         println!("[{}].exec({}, {:#x}) = {:?}", self.excl.lock().pid, String::from_utf8_lossy(&path), uargv, result);
 
-        if result.is_err() {
-            syscall_warning(error);
+        if let Err(e) = result {
+            syscall_warning(e);
         }
         result
     }
 
     /// Given a file descriptor, return the file status.
     fn sys_fstat(&mut self) -> SysResult {
-        let fd = self.arg_fd(0)?;
+        let file = self.arg_file(0)?;
         let addr = self.arg_addr(1);
         let mut stat = FileStat::uninit();
-        let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
-        let ret = if file.fstat(&mut stat).is_err() {
-            Err(())
-        } else {
-            let pgt = self.data.get_mut().pagetable.as_mut().unwrap();
-            if pgt.copy_out(&stat as *const FileStat as *const u8, addr, mem::size_of::<FileStat>()).is_err() {
-                Err(())
-            } else {
-                Ok(0)
-            }
-        };
+        let ret = file.fstat(&mut stat).and_then(|()| {
+            let pdata = self.data.get_mut();
+            pdata.copy_out(&stat as *const FileStat as *const u8, addr, mem::size_of::<FileStat>())
+        }).map(|()| 0);
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].fstat(fd={}, addr={:#x}) = {:?}", self.excl.lock().pid, fd, addr, stat);
+        println!("[{}].fstat(fd={}, addr={:#x}) = {:?}", self.excl.lock().pid, self.arg_i32(0), addr, stat);
 
         ret
     }
@@ -219,24 +245,24 @@ impl Syscall for Proc {
     /// Change the current process's working directory,
     fn sys_chdir(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut path)?;
 
         LOG.begin_op();
         let inode: Inode;
-        if let Some(i) = ICACHE.namei(&path) {
-            inode = i;
-        } else {
-            LOG.end_op();
-            return Err(())
+        match ICACHE.namei(&path) {
+            Ok(i) => inode = i,
+            Err(e) => {
+                LOG.end_op();
+                return Err(e)
+            },
         }
         let idata = inode.lock();
         if idata.get_itype() != InodeType::Directory {
             drop(idata); drop(inode); LOG.end_op();
-            return Err(())
+            return Err(Errno::ENOTDIR)
         }
         drop(idata);
-        let old_cwd = self.data.get_mut().cwd.replace(inode);
-        debug_assert!(old_cwd.is_some());
+        let old_cwd = self.data.get_mut().files().set_cwd(inode);
         drop(old_cwd);
         LOG.end_op();
         Ok(0)
@@ -244,17 +270,11 @@ impl Syscall for Proc {
 
     /// Duplicate a file descriptor.
     fn sys_dup(&mut self) -> SysResult {
-        let old_fd = self.arg_fd(0)?;
-        let pd = self.data.get_mut();
-        let new_fd = pd.alloc_fd().ok_or(())?;
-        
-        let old_file = pd.open_files[old_fd].as_ref().unwrap();
-        let new_file = Arc::clone(old_file);
-        let none_file = pd.open_files[new_fd].replace(new_file);
-        debug_assert!(none_file.is_none());
+        let file = self.arg_file(0)?;
+        let new_fd = self.data.get_mut().install_fd(file)?;
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].dup({}) = {}(fd)", self.excl.lock().pid, old_fd, new_fd);
+        println!("[{}].dup({}) = {}(fd)", self.excl.lock().pid, self.arg_i32(0), new_fd);
 
         Ok(new_fd)
     }
@@ -286,7 +306,7 @@ impl Syscall for Proc {
     fn sys_sleep(&mut self) -> SysResult {
         let count = self.arg_i32(0);
         if count < 0 {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
         let count = count as usize;
         let ret = trap::clock_sleep(self, count);
@@ -311,19 +331,19 @@ impl Syscall for Proc {
     /// Open and optionally create a file.
     /// Note1: It can only possibly create a regular file,
     ///     use [`Syscall::sys_mknod`] to creata special file instead.
-    /// Note2: File permission and modes are not supported yet.
+    /// Note2: The created file is owned by the caller with mode 0644,
+    ///     use [`Syscall::sys_chmod`] to change it.
     fn sys_open(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut path)?;
         let flags = self.arg_i32(1);
         if flags < 0 {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
 
-        let fd = self.data.get_mut().alloc_fd().ok_or(())?;
-        let file = File::open(&path, flags).ok_or(())?;
-        let none_file = self.data.get_mut().open_files[fd].replace(file);
-        debug_assert!(none_file.is_none());
+        self.data.get_mut().check_free_fd()?;
+        let file = File::open(&path, flags)?;
+        let fd = self.data.get_mut().install_fd(file)?;
 
         #[cfg(feature = "trace_syscall")]
         println!("[{}].open({}, {:#x}) = {}(fd)", self.excl.lock().pid, String::from_utf8_lossy(&path), flags, fd);
@@ -334,37 +354,46 @@ impl Syscall for Proc {
     /// Write user content to file descriptor.
     /// Return the conut of bytes written.
     fn sys_write(&mut self) -> SysResult {
-        let fd = self.arg_fd(0)?;
+        let file = self.arg_file(0)?;
         let user_addr = self.arg_addr(1);
         let count = self.arg_i32(2);
-        if count <= 0 || self.data.get_mut().check_user_addr(user_addr).is_err() {
-            return Err(())
+        if count <= 0 {
+            return Err(Errno::EINVAL)
         }
+        self.data.get_mut().check_user_addr(user_addr)?;
         let count = count as u32;
 
-        let file = self.data.get_mut().open_files[fd].as_ref().unwrap();
+        // populate the user buffer before the file holds any lock
+        let pdata = self.data.get_mut();
+        pdata.lazy_populate(user_addr, count as usize);
         let ret = file.fwrite(user_addr, count);
+        drop(file);
 
         #[cfg(feature = "trace_syscall")]
-        println!("[{}].write({}, {:#x}, {}) = {:?}", self.excl.lock().pid, fd, user_addr, count, ret);
+        println!("[{}].write({}, {:#x}, {}) = {:?}", self.excl.lock().pid, self.arg_i32(0), user_addr, count, ret);
 
         ret.map(|count| count as usize)
     }
 
-    /// Create a device file.
+    /// Create a device file, or a FIFO if the major is [`DEV_FIFO`],
+    /// which fails with EEXIST if the path exists, as mkfifo does.
     fn sys_mknod(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut path)?;
         let major = self.arg_i32(1);
         let minor = self.arg_i32(2);
         if major < 0 || minor < 0 {
-            return Err(())
+            return Err(Errno::EINVAL)
         }
 
-        let major: u16 = major.try_into().map_err(|_| ())?;
-        let minor: u16 = minor.try_into().map_err(|_| ())?;
+        let major: u16 = major.try_into().map_err(|_| Errno::EINVAL)?;
+        let minor: u16 = minor.try_into().map_err(|_| Errno::EINVAL)?;
         LOG.begin_op();
-        let ret = ICACHE.create(&path, InodeType::Device, major, minor, true).ok_or(());
+        let ret = if major as usize == DEV_FIFO {
+            ICACHE.create(&path, InodeType::Fifo, 0, 0, false)
+        } else {
+            ICACHE.create(&path, InodeType::Device, major, minor, true)
+        };
 
         #[cfg(feature = "trace_syscall")]
         println!("[{}].mknod(path={}, major={}, minor={}) = {:?}",
@@ -379,20 +408,21 @@ impl Syscall for Proc {
     /// In essence, [`Syscall::sys_unlink`] will decrement the link count of the inode.
     fn sys_unlink(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut path)?;
 
         LOG.begin_op();
         let mut name: [u8; MAX_DIR_SIZE] = [0; MAX_DIR_SIZE];
         let dir_inode: Inode;
-        if let Some(inode) = ICACHE.namei_parent(&path, &mut name) {
-            dir_inode = inode;
-        } else {
-            LOG.end_op();
-            return Err(())
+        match ICACHE.namei_parent(&path, &mut name) {
+            Ok(inode) => dir_inode = inode,
+            Err(e) => {
+                LOG.end_op();
+                return Err(e)
+            },
         }
 
         let mut dir_idata = dir_inode.lock();
-        let ret = dir_idata.dir_unlink(&name);
+        let ret = dir_idata.permit(MAY_WRITE | MAY_EXEC).and_then(|()| dir_idata.dir_unlink(&name));
         drop(dir_idata);
         drop(dir_inode);
         LOG.end_op();
@@ -407,19 +437,20 @@ impl Syscall for Proc {
     fn sys_link(&mut self) -> SysResult {
         let mut old_path: [u8; MAXPATH] = [0; MAXPATH];
         let mut new_path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut old_path).map_err(syscall_warning)?;
-        self.arg_str(1, &mut new_path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut old_path)?;
+        self.arg_str(1, &mut new_path)?;
 
         LOG.begin_op();
 
         // find old path
-        let old_inode = ICACHE.namei(&old_path).ok_or_else(|| {LOG.end_op(); ()})?;
+        let old_inode = ICACHE.namei(&old_path).map_err(|e| {LOG.end_op(); e})?;
         let mut old_idata = old_inode.lock();
         let (old_dev, old_inum) = old_idata.get_dev_inum();
         if old_idata.get_itype() == InodeType::Directory {
             syscall_warning("trying to create new link to a directory");
+            drop(old_idata); drop(old_inode);
             LOG.end_op();
-            return Err(())
+            return Err(Errno::EPERM)
         }
         old_idata.link();
         old_idata.update();
@@ -439,16 +470,25 @@ impl Syscall for Proc {
         let mut name: [u8; MAX_DIR_SIZE] = [0; MAX_DIR_SIZE];
         let new_inode: Inode;
         match ICACHE.namei_parent(&new_path, &mut name) {
-            Some(inode) => new_inode = inode,
-            None => {
+            Ok(inode) => new_inode = inode,
+            Err(e) => {
                 revert_link(old_inode);
-                return Err(())
+                return Err(e)
             }
         }
         let mut new_idata = new_inode.lock();
-        if new_idata.get_dev_inum().0 != old_dev || new_idata.dir_link(&name, old_inum).is_err() {
+        let ret = if new_idata.get_dev_inum().0 != old_dev {
+            Err(Errno::EXDEV)
+        } else if let Err(e) = new_idata.permit(MAY_WRITE | MAY_EXEC) {
+            Err(e)
+        } else {
+            new_idata.dir_link(&name, old_inum)
+        };
+        if let Err(e) = ret {
+            drop(new_idata);
+            drop(new_inode);
             revert_link(old_inode);
-            return Err(())
+            return Err(e)
         }
         drop(new_idata);
         drop(new_inode);
@@ -464,10 +504,10 @@ impl Syscall for Proc {
     }
 
     /// Create a directory.
-    /// Note: Mode is not supported yet.
+    /// Note: It is owned by the caller with mode 0755.
     fn sys_mkdir(&mut self) -> SysResult {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        self.arg_str(0, &mut path).map_err(syscall_warning)?;
+        self.arg_str(0, &mut path)?;
 
         LOG.begin_op();
         let ret = ICACHE.create(&path, InodeType::Directory, 0, 0, false);
@@ -475,21 +515,15 @@ impl Syscall for Proc {
         #[cfg(feature = "trace_syscall")]
         println!("[{}].mkdir(path={}) = {:?}", self.excl.lock().pid, String::from_utf8_lossy(&path), ret);
 
-        let ret = match ret {
-            Some(inode) => {
-                drop(inode);
-                Ok(0)
-            },
-            None => Err(()),
-        };
+        let ret = ret.map(|inode| {drop(inode);0});
         LOG.end_op();
         ret
     }
 
     /// Given a file descriptor, close the opened file.
     fn sys_close(&mut self) -> SysResult {
-        let fd = self.arg_fd(0)?;
-        let file = self.data.get_mut().open_files[fd].take();
+        let fd = self.arg_raw(0);
+        let file = self.data.get_mut().close_fd(fd).ok_or(Errno::EBADF)?;
 
         #[cfg(feature = "trace_syscall")]
         println!("[{}].close(fd={}), file={:?}", self.excl.lock().pid, fd, file);
@@ -497,6 +531,896 @@ impl Syscall for Proc {
         drop(file);
         Ok(0)
     }
+
+    /// Map a file or anonymous memory into the user space.
+    /// The address hint is ignored, the kernel always chooses the address.
+    fn sys_mmap(&mut self) -> SysResult {
+        let len = self.arg_raw(1);
+        let prot = self.arg_i32(2);
+        let flags = self.arg_i32(3);
+        let offset = self.arg_raw(5);
+        let file = if flags & MAP_ANONYMOUS > 0 {
+            None
+        } else {
+            Some(self.arg_file(4)?)
+        };
+        let ret = self.data.get_mut().mmap(len, prot, flags, file, offset);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].mmap(len={}, prot={:#x}, flags={:#x}, fd={}, offset={}) = {:#x?}",
+            self.excl.lock().pid, len, prot, flags, self.arg_i32(4), offset, ret);
+
+        ret
+    }
+
+    /// Unmap a range of the memory mapped areas.
+    fn sys_munmap(&mut self) -> SysResult {
+        let addr = self.arg_addr(0);
+        let len = self.arg_raw(1);
+        let ret = self.data.get_mut().munmap(addr, len);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].munmap(addr={:#x}, len={}) = {:?}", self.excl.lock().pid, addr, len, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Redirect to [`Proc::clone_thread`].
+    ///
+    /// [`Proc::clone_thread`]: Proc::clone_thread
+    fn sys_clone(&mut self) -> SysResult {
+        let func = self.arg_addr(0);
+        let arg = self.arg_raw(1);
+        let stack = self.arg_addr(2);
+        let tls = self.arg_raw(3);
+        let pdata = self.data.get_mut();
+        if stack == 0 {
+            return Err(Errno::EINVAL)
+        }
+        pdata.check_user_addr(func)?;
+        pdata.check_user_addr(stack)?;
+        let ret = self.clone_thread(func, arg, stack, tls);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].clone(fn={:#x}, arg={:#x}, stack={:#x}, tls={:#x}) = {:?}(tid)",
+            self.excl.lock().pid, func, arg, stack, tls, ret);
+
+        ret
+    }
+
+    /// Wait for a thread created by clone to exit.
+    /// Recycle the thread and return its tid.
+    fn sys_join(&mut self) -> SysResult {
+        let tid = self.arg_i32(0);
+        let addr = self.arg_addr(1);
+        if tid <= 0 {
+            return Err(Errno::EINVAL)
+        }
+        let tid = tid as usize;
+        if addr != 0 {
+            self.data.get_mut().lazy_populate(addr, mem::size_of::<i32>());
+        }
+        let ret = unsafe { PROC_MANAGER.joining(self.index, tid, addr) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].join(tid={}, addr={:#x}) = {:?}(tid)", self.excl.lock().pid, tid, addr, ret);
+
+        ret
+    }
+
+    /// Examine and change the action of a signal.
+    fn sys_sigaction(&mut self) -> SysResult {
+        let sig = self.arg_i32(0);
+        let act_addr = self.arg_addr(1);
+        let oldact_addr = self.arg_addr(2);
+        if sig <= 0 {
+            return Err(Errno::EINVAL)
+        }
+        let sig = sig as usize;
+
+        let pdata = self.data.get_mut();
+        let act = if act_addr == 0 {
+            None
+        } else {
+            let mut act = mem::MaybeUninit::<SigAction>::uninit();
+            pdata.copy_in(act_addr, act.as_mut_ptr() as *mut u8, mem::size_of::<SigAction>())?;
+            Some(unsafe { act.assume_init() })
+        };
+        if oldact_addr != 0 {
+            pdata.lazy_populate(oldact_addr, mem::size_of::<SigAction>());
+        }
+        let ret = self.excl.lock().sig.set_action(sig, act);
+        let ret = match ret {
+            Ok(old) if oldact_addr != 0 => self.data.get_mut()
+                .copy_out(&old as *const SigAction as *const u8, oldact_addr, mem::size_of::<SigAction>()),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sigaction(sig={}, act={:#x}, oldact={:#x}) = {:?}",
+            self.excl.lock().pid, sig, act_addr, oldact_addr, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Examine and change the blocked signals.
+    fn sys_sigprocmask(&mut self) -> SysResult {
+        let how = self.arg_i32(0);
+        let set_addr = self.arg_addr(1);
+        let oldset_addr = self.arg_addr(2);
+
+        let pdata = self.data.get_mut();
+        let set = if set_addr == 0 {
+            None
+        } else {
+            let mut set: u32 = 0;
+            pdata.copy_in(set_addr, &mut set as *mut u32 as *mut u8, mem::size_of::<u32>())?;
+            Some(set)
+        };
+        let ret = self.excl.lock().sig.set_blocked(how, set);
+        let ret = match ret {
+            Ok(old) if oldset_addr != 0 => self.data.get_mut()
+                .copy_out(&old as *const u32 as *const u8, oldset_addr, mem::size_of::<u32>()),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sigprocmask(how={}, set={:#x}, oldset={:#x}) = {:?}",
+            self.excl.lock().pid, how, set_addr, oldset_addr, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Return from an alarm handler or a signal handler,
+    /// redirect to [`Proc::alarm_return`] or [`Proc::sig_return`].
+    /// The alarm handler runs with the signals blocked,
+    /// so it is always the innermost one if running.
+    ///
+    /// [`Proc::alarm_return`]: Proc::alarm_return
+    /// [`Proc::sig_return`]: Proc::sig_return
+    fn sys_sigreturn(&mut self) -> SysResult {
+        let ret = match self.alarm_return() {
+            Some(a0) => Ok(a0),
+            None => self.sig_return(),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sigreturn() = {:?}", self.excl.lock().pid, ret);
+
+        if ret.is_err() {
+            // the signal frame is broken
+            self.abondon(-1);
+        }
+        ret
+    }
+
+    /// Set the nice value of a process, zero pid for the current process.
+    fn sys_setpriority(&mut self) -> SysResult {
+        let pid = self.arg_i32(0);
+        let nice = self.arg_i32(1);
+        if pid < 0 {
+            return Err(Errno::EINVAL)
+        }
+        let pid = if pid == 0 {
+            self.excl.lock().pid
+        } else {
+            pid as usize
+        };
+        let ret = unsafe { PROC_MANAGER.set_priority(pid, nice) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setpriority(pid={}, nice={}) = {:?}", self.excl.lock().pid, pid, nice, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Pin the process with given pid to a subset of harts, given as a bitmask of hart ids.
+    /// Zero pid means the current process.
+    fn sys_sched_setaffinity(&mut self) -> SysResult {
+        let pid = self.arg_i32(0);
+        let mask = self.arg_raw(1);
+        if pid < 0 {
+            return Err(Errno::EINVAL)
+        }
+        let pid = if pid == 0 {
+            self.excl.lock().pid
+        } else {
+            pid as usize
+        };
+        let ret = unsafe { PROC_MANAGER.set_affinity(pid, mask) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sched_setaffinity(pid={}, mask={:#x}) = {:?}", self.excl.lock().pid, pid, mask, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Set the bitmask of syscalls to be logged for the current process,
+    /// where bit n selects the syscall numbered n.
+    /// The mask is inherited by the children, and kept across exec.
+    fn sys_trace(&mut self) -> SysResult {
+        let mask = self.arg_raw(0);
+        self.data.get_mut().trace_mask = mask;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].trace(mask={:#x})", self.excl.lock().pid, mask);
+
+        Ok(0)
+    }
+
+    /// Wait for a child with given pid to change, where the pid means:
+    /// -1 for any child, 0 for the children in the same process group,
+    /// and less than -1 for the children in the process group of its absolute value.
+    /// Options are [`WNOHANG`] and [`WUNTRACED`].
+    /// The status word copied out tells how the child changed, see [`WaitStatus::encode`].
+    /// Return the child's pid, or 0 if none has changed with [`WNOHANG`].
+    ///
+    /// [`WaitStatus::encode`]: crate::process::WaitStatus::encode
+    fn sys_waitpid(&mut self) -> SysResult {
+        let pid = self.arg_i32(0);
+        let addr = self.arg_addr(1);
+        let options = self.arg_i32(2);
+        if options & !(WNOHANG | WUNTRACED) != 0 {
+            return Err(Errno::EINVAL)
+        }
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::Group(self.excl.lock().pgid),
+            pid if pid < 0 => WaitTarget::Group(pid.wrapping_neg() as usize),
+            pid => WaitTarget::Pid(pid as usize),
+        };
+        if addr != 0 {
+            self.data.get_mut().lazy_populate(addr, mem::size_of::<i32>());
+        }
+        let ret = unsafe { PROC_MANAGER.waiting(self.index, target, options) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].waitpid(pid={}, addr={:#x}, options={:#x}) = {:?}",
+            self.excl.lock().pid, pid, addr, options, ret);
+
+        match ret? {
+            Some((pid, status)) => {
+                if addr != 0 {
+                    let status = status.encode();
+                    self.data.get_mut().copy_out(&status as *const i32 as *const u8, addr, mem::size_of::<i32>())?;
+                }
+                Ok(pid)
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// Set the process group of a process.
+    /// Zero pid means the current process, and zero pgid means the same as the pid.
+    fn sys_setpgid(&mut self) -> SysResult {
+        let pid = self.arg_i32(0);
+        let pgid = self.arg_i32(1);
+        if pid < 0 || pgid < 0 {
+            return Err(Errno::EINVAL)
+        }
+        let pid = if pid == 0 {
+            self.excl.lock().pid
+        } else {
+            pid as usize
+        };
+        let pgid = if pgid == 0 {
+            pid
+        } else {
+            pgid as usize
+        };
+        let ret = unsafe { PROC_MANAGER.set_pgid(self.index, pid, pgid) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setpgid(pid={}, pgid={}) = {:?}", self.excl.lock().pid, pid, pgid, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Get the process group of a process.
+    /// Zero pid means the current process.
+    fn sys_getpgid(&mut self) -> SysResult {
+        let pid = self.arg_i32(0);
+        if pid < 0 {
+            return Err(Errno::EINVAL)
+        }
+        let ret = if pid == 0 {
+            Ok(self.excl.lock().pgid)
+        } else {
+            unsafe { PROC_MANAGER.get_pgid(pid as usize) }
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].getpgid(pid={}) = {:?}", self.excl.lock().pid, pid, ret);
+
+        ret
+    }
+
+    /// Start a new session with the current process as the leader.
+    fn sys_setsid(&mut self) -> SysResult {
+        let ret = unsafe { PROC_MANAGER.set_sid(self.index) };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setsid() = {:?}", self.excl.lock().pid, ret);
+
+        ret
+    }
+
+    /// Set the foreground process group of the console referred by the fd.
+    /// The group should be in the same session as the current process,
+    /// and the console should be the controlling terminal of the session,
+    /// which is acquired by the first session leader setting its foreground.
+    fn sys_tcsetpgrp(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        let pgid = self.arg_i32(1);
+        if file.major() != Some(DEV_CONSOLE as u16) {
+            return Err(Errno::ENOTTY)
+        }
+        if pgid <= 0 {
+            return Err(Errno::EINVAL)
+        }
+        let pgid = pgid as usize;
+        let excl = self.excl.lock();
+        let (sid, leader) = (excl.sid, excl.sid == excl.pid);
+        drop(excl);
+        if !unsafe { PROC_MANAGER.pgrp_in_session(pgid, sid) } {
+            return Err(Errno::EPERM)
+        }
+        console::set_fg_pgrp(sid, leader, pgid)?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].tcsetpgrp(fd={}, pgid={})", self.excl.lock().pid, self.arg_i32(0), pgid);
+
+        Ok(0)
+    }
+
+    /// Get the foreground process group of the console referred by the fd.
+    fn sys_tcgetpgrp(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        if file.major() != Some(DEV_CONSOLE as u16) {
+            return Err(Errno::ENOTTY)
+        }
+        let pgid = console::fg_pgrp();
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].tcgetpgrp(fd={}) = {}", self.excl.lock().pid, self.arg_i32(0), pgid);
+
+        Ok(pgid)
+    }
+
+    /// Like exec, but also with the environment,
+    /// which is laid out on the new user stack after argv.
+    fn sys_execve(&mut self) -> SysResult {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_str(0, &mut path)?;
+        let uargv = self.arg_addr(1);
+        let uenvp = self.arg_addr(2);
+
+        let result = self.execve(&mut path, uargv, uenvp);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].execve({}, {:#x}, {:#x}) = {:?}",
+            self.excl.lock().pid, String::from_utf8_lossy(&path), uargv, uenvp, result);
+
+        if let Err(e) = result {
+            syscall_warning(e);
+        }
+        result
+    }
+
+    /// Get the soft and hard limits of a resource.
+    fn sys_getrlimit(&mut self) -> SysResult {
+        let resource = self.arg_i32(0);
+        let addr = self.arg_addr(1);
+        if resource < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let pdata = self.data.get_mut();
+        let ret = pdata.rlimits.get(resource as usize).and_then(|limit| {
+            pdata.copy_out(&limit as *const RLimit as *const u8, addr, mem::size_of::<RLimit>())
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].getrlimit(resource={}, rlim={:#x}) = {:?}", self.excl.lock().pid, resource, addr, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Set the soft and hard limits of a resource.
+    /// The hard limit can only be lowered, and the soft one should not exceed it.
+    /// They are inherited by the children.
+    fn sys_setrlimit(&mut self) -> SysResult {
+        let resource = self.arg_i32(0);
+        let addr = self.arg_addr(1);
+        if resource < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let pdata = self.data.get_mut();
+        let mut limit = mem::MaybeUninit::<RLimit>::uninit();
+        pdata.copy_in(addr, limit.as_mut_ptr() as *mut u8, mem::size_of::<RLimit>())?;
+        let limit = unsafe { limit.assume_init() };
+        let ret = pdata.rlimits.set(resource as usize, limit);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setrlimit(resource={}, cur={:#x}, max={:#x}) = {:?}",
+            self.excl.lock().pid, resource, limit.cur, limit.max, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Change the permission bits of a file.
+    /// Only the owner or root can do it.
+    fn sys_chmod(&mut self) -> SysResult {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_str(0, &mut path)?;
+        let mode = self.arg_i32(1);
+        if mode < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        LOG.begin_op();
+        let ret = ICACHE.namei(&path).and_then(|inode| {
+            let ret = inode.lock().chmod(mode as u16);
+            drop(inode);
+            ret
+        });
+        LOG.end_op();
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].chmod(path={}, mode={:#o}) = {:?}",
+            self.excl.lock().pid, String::from_utf8_lossy(&path), mode, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Change the owner and group of a file.
+    /// An id of -1 keeps the current one.
+    /// Only root can do it.
+    fn sys_chown(&mut self) -> SysResult {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_str(0, &mut path)?;
+        let id = |n: i32| -> Result<Option<u16>, Errno> {
+            match n {
+                -1 => Ok(None),
+                n => n.try_into().map(Some).map_err(|_| Errno::EINVAL),
+            }
+        };
+        let uid = id(self.arg_i32(1))?;
+        let gid = id(self.arg_i32(2))?;
+
+        LOG.begin_op();
+        let ret = ICACHE.namei(&path).and_then(|inode| {
+            let ret = inode.lock().chown(uid, gid);
+            drop(inode);
+            ret
+        });
+        LOG.end_op();
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].chown(path={}, uid={:?}, gid={:?}) = {:?}",
+            self.excl.lock().pid, String::from_utf8_lossy(&path), uid, gid, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Set the user id, leaving the group id unchanged.
+    /// Root can become any user, while others can only set their own id.
+    fn sys_setuid(&mut self) -> SysResult {
+        let uid = self.arg_i32(0);
+        let uid: u16 = uid.try_into().map_err(|_| Errno::EINVAL)?;
+
+        let cred = &mut self.data.get_mut().cred;
+        let ret = if cred.is_root() || cred.uid == uid {
+            cred.uid = uid;
+            Ok(0)
+        } else {
+            Err(Errno::EPERM)
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setuid({}) = {:?}", self.excl.lock().pid, uid, ret);
+
+        ret
+    }
+
+    /// Get the user id.
+    fn sys_getuid(&mut self) -> SysResult {
+        let uid = self.data.get_mut().cred.uid as usize;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].getuid() = {}", self.excl.lock().pid, uid);
+
+        Ok(uid)
+    }
+
+    /// Call the user handler every interval ticks of cpu time in the user space,
+    /// or stop if the interval is 0.
+    /// The handler should call sigreturn to resume the interrupted code.
+    fn sys_sigalarm(&mut self) -> SysResult {
+        let interval = self.arg_i32(0);
+        let handler = self.arg_addr(1);
+        if interval < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        self.data.get_mut().alarm.set(interval as usize, handler);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].sigalarm(interval={}, handler={:#x})", self.excl.lock().pid, interval, handler);
+
+        Ok(0)
+    }
+
+    /// Get the time of a clock in nanoseconds.
+    /// [`CLOCK_REALTIME`] is the wall-clock time since the epoch from the rtc,
+    /// and [`CLOCK_MONOTONIC`] is the time since boot.
+    fn sys_clock_gettime(&mut self) -> SysResult {
+        let clock = self.arg_i32(0);
+        let addr = self.arg_addr(1);
+
+        let ret = match clock as usize {
+            CLOCK_REALTIME => Ok(TimeSpec::from_ns(rtc::read_ns())),
+            CLOCK_MONOTONIC => Ok(TimeSpec::from_ns(timer::monotonic_ns())),
+            _ => Err(Errno::EINVAL),
+        }.and_then(|ts| {
+            let pdata = self.data.get_mut();
+            pdata.copy_out(&ts as *const TimeSpec as *const u8, addr, mem::size_of::<TimeSpec>())
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].clock_gettime(clock={}, tp={:#x}) = {:?}", self.excl.lock().pid, clock, addr, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Sleep for the requested time, woken up right at the deadline.
+    /// If interrupted, the remaining time is written to rem if it is not null.
+    /// If restarted after the process is stopped and continued,
+    /// it sleeps until the same deadline instead of the full time again.
+    fn sys_nanosleep(&mut self) -> SysResult {
+        let req_addr = self.arg_addr(0);
+        let rem_addr = self.arg_addr(1);
+
+        let pdata = self.data.get_mut();
+        let restart_deadline = pdata.sleep_deadline.take();
+        let mut req = mem::MaybeUninit::<TimeSpec>::uninit();
+        pdata.copy_in(req_addr, req.as_mut_ptr() as *mut u8, mem::size_of::<TimeSpec>())?;
+        let req = unsafe { req.assume_init() };
+        let ns = req.to_ns().ok_or(Errno::EINVAL)?;
+
+        // a restarted sleep goes on to the deadline of the interrupted one
+        let deadline = match restart_deadline {
+            Some(deadline) => deadline,
+            None => timer::deadline_after(ns),
+        };
+        let ret = timer::nanosleep(self, deadline).or_else(|rem| {
+            let pdata = self.data.get_mut();
+            if rem_addr != 0 {
+                let rem = TimeSpec::from_ns(rem);
+                pdata.copy_out(&rem as *const TimeSpec as *const u8, rem_addr, mem::size_of::<TimeSpec>())?;
+            }
+            pdata.sleep_deadline = Some(deadline);
+            Err(Errno::EINTR)
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].nanosleep(sec={}, nsec={}) = {:?}", self.excl.lock().pid, req.sec, req.nsec, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Get the wall-clock time in microseconds since the epoch.
+    /// The timezone is not supported, and it should be null.
+    fn sys_gettimeofday(&mut self) -> SysResult {
+        let addr = self.arg_addr(0);
+        let tz = self.arg_addr(1);
+        if tz != 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let tv = TimeVal::from_ns(rtc::read_ns());
+        let pdata = self.data.get_mut();
+        let ret = pdata.copy_out(&tv as *const TimeVal as *const u8, addr, mem::size_of::<TimeVal>());
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].gettimeofday(tv={:#x}) = {:?}", self.excl.lock().pid, addr, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Wait on or wake up a futex at the user word `addr`.
+    /// [`FUTEX_WAIT`] sleeps if the word is still val, for at most timeout ticks if it is not 0.
+    /// [`FUTEX_WAKE`] wakes up at most val waiters, and returns the number woken up.
+    /// The futex is the same one wherever the word is mapped,
+    /// so it also works between processes sharing the memory.
+    fn sys_futex(&mut self) -> SysResult {
+        let addr = self.arg_addr(0);
+        let op = self.arg_i32(1);
+        let val = self.arg_i32(2);
+        let timeout = self.arg_i32(3);
+        if addr % mem::size_of::<u32>() != 0 || timeout < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let pa = self.data.get_mut().futex_key(addr)?;
+        let ret = match op {
+            FUTEX_WAIT => futex_wait(self, pa, val as u32, timeout as usize).map(|()| 0),
+            FUTEX_WAKE if val >= 0 => Ok(futex_wake(pa, val as usize)),
+            _ => Err(Errno::EINVAL),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].futex(addr={:#x}, op={}, val={}, timeout={}) = {:?}",
+            self.excl.lock().pid, addr, op, val, timeout, ret);
+
+        ret
+    }
+
+    /// Manipulate a file descriptor.
+    /// F_DUPFD duplicates it to the lowest free fd not less than the argument,
+    /// F_GETFD/F_SETFD get/set its FD_CLOEXEC,
+    /// and F_GETFL/F_SETFL get/set the flags of the file, of which only O_NONBLOCK could be set.
+    fn sys_fcntl(&mut self) -> SysResult {
+        let fd = self.arg_raw(0);
+        let file = self.data.get_mut().file(fd)?;
+        let cmd = self.arg_i32(1);
+        let arg = self.arg_i32(2);
+        let pdata = self.data.get_mut();
+
+        let ret = match cmd {
+            F_DUPFD => {
+                if arg < 0 || arg as usize >= NFILE {
+                    Err(Errno::EINVAL)
+                } else {
+                    pdata.install_fd_from(arg as usize, file)
+                }
+            },
+            F_GETFD | F_SETFD => {
+                // the fd may have been closed by another thread
+                let mut files = pdata.files();
+                match files.get(fd) {
+                    Some(ref f) if Arc::ptr_eq(f, &file) => {
+                        if cmd == F_SETFD {
+                            files.set_cloexec(fd, arg & FD_CLOEXEC > 0);
+                            Ok(0)
+                        } else {
+                            Ok(if files.cloexec(fd) { FD_CLOEXEC as usize } else { 0 })
+                        }
+                    },
+                    _ => Err(Errno::EBADF),
+                }
+            },
+            F_GETFL => Ok(file.get_flags() as usize),
+            F_SETFL => {
+                file.set_flags(arg);
+                Ok(0)
+            },
+            _ => Err(Errno::EINVAL),
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].fcntl(fd={}, cmd={}, arg={:#x}) = {:?}", self.excl.lock().pid, fd, cmd, arg, ret);
+
+        ret
+    }
+
+    /// Wait for any of the user array of [`PollFd`] to be ready,
+    /// for at most the timeout in ticks, or forever if it is negative.
+    /// Return the number of the ready fds, 0 if timed out.
+    fn sys_poll(&mut self) -> SysResult {
+        let addr = self.arg_addr(0);
+        let nfds = self.arg_i32(1);
+        let timeout = self.arg_i32(2);
+        if nfds < 0 || nfds as usize > NFILE {
+            return Err(Errno::EINVAL)
+        }
+        let nfds = nfds as usize;
+
+        let mut fds = [PollFd::default(); NFILE];
+        let size = nfds * mem::size_of::<PollFd>();
+        let pdata = self.data.get_mut();
+        pdata.copy_in(addr, fds.as_mut_ptr() as *mut u8, size)?;
+        // hold the files, in case they are closed by another thread
+        let mut files: [Option<Arc<File>>; NFILE] = array![_ => None; NFILE];
+        for (fd, file) in fds.iter().zip(files.iter_mut()).take(nfds) {
+            if fd.fd >= 0 && (fd.fd as usize) < NFILE {
+                *file = pdata.files().get(fd.fd as usize);
+            }
+        }
+
+        let ret = fs::poll(self, &mut fds[..nfds], &files[..nfds], timeout);
+        drop(files);
+        if ret.is_ok() {
+            self.data.get_mut().copy_out(fds.as_ptr() as *const u8, addr, size)?;
+        }
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].poll(fds={:#x}, nfds={}, timeout={}) = {:?}", self.excl.lock().pid, addr, nfds, timeout, ret);
+
+        ret
+    }
+
+    /// Create a FIFO with the permission bits of mode.
+    /// Opening it for reading or writing waits until the other end is opened.
+    fn sys_mkfifo(&mut self) -> SysResult {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_str(0, &mut path)?;
+        let mode = self.arg_i32(1);
+        if mode < 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        LOG.begin_op();
+        let ret = ICACHE.create(&path, InodeType::Fifo, 0, 0, false).and_then(|inode| {
+            let ret = inode.lock().chmod(mode as u16);
+            drop(inode);
+            ret
+        });
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].mkfifo(path={}, mode={:#o}) = {:?}",
+            self.excl.lock().pid, String::from_utf8_lossy(&path), mode, ret);
+
+        LOG.end_op();
+        ret.map(|()| 0)
+    }
+
+    /// Create an unbound socket, only a Unix domain stream socket is supported.
+    fn sys_socket(&mut self) -> SysResult {
+        let domain = self.arg_i32(0);
+        let stype = self.arg_i32(1);
+        let protocol = self.arg_i32(2);
+        if domain != AF_UNIX {
+            return Err(Errno::EAFNOSUPPORT)
+        }
+        if stype != SOCK_STREAM || protocol != 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let file = Socket::create()?;
+        let fd = self.data.get_mut().install_fd(file)?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].socket({}, {}, {}) = {}(fd)", self.excl.lock().pid, domain, stype, protocol, fd);
+
+        Ok(fd)
+    }
+
+    /// Bind a socket to the path in the address,
+    /// where a socket inode is created, and should not exist before.
+    fn sys_bind(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_sockaddr(1, &mut path)?;
+        let ret = file.bind(&path);
+        drop(file);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].bind(fd={}, path={}) = {:?}", self.excl.lock().pid, self.arg_i32(0), String::from_utf8_lossy(&path), ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Let a bound socket accept connections.
+    fn sys_listen(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        let backlog = self.arg_i32(1);
+        let ret = file.listen(max(backlog, 0) as usize);
+        drop(file);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].listen(fd={}, backlog={}) = {:?}", self.excl.lock().pid, self.arg_i32(0), backlog, ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Accept a connection on a listening socket, return the fd of the new connected socket.
+    /// The peer is always unnamed, so only the family is filled in the address if it is not null.
+    fn sys_accept(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        let addr = self.arg_addr(1);
+        let len_addr = self.arg_addr(2);
+
+        // fill in the address before dequeuing the connection,
+        // so that it is not lost by a bad address
+        let pdata = self.data.get_mut();
+        pdata.check_free_fd()?;
+        if addr != 0 {
+            let family = AF_UNIX as u16;
+            pdata.copy_out(&family as *const u16 as *const u8, addr, mem::size_of::<u16>())?;
+        }
+        if len_addr != 0 {
+            let len = mem::size_of::<u16>() as i32;
+            pdata.copy_out(&len as *const i32 as *const u8, len_addr, mem::size_of::<i32>())?;
+        }
+        let ret = file.accept();
+        drop(file);
+        let new_fd = pdata.install_fd(ret?)?;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].accept(fd={}) = {}(fd)", self.excl.lock().pid, self.arg_i32(0), new_fd);
+
+        Ok(new_fd)
+    }
+
+    /// Connect a socket to the listening one bound at the path in the address.
+    fn sys_connect(&mut self) -> SysResult {
+        let file = self.arg_file(0)?;
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        self.arg_sockaddr(1, &mut path)?;
+        let ret = file.connect(&path);
+        drop(file);
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].connect(fd={}, path={}) = {:?}", self.excl.lock().pid, self.arg_i32(0), String::from_utf8_lossy(&path), ret);
+
+        ret.map(|()| 0)
+    }
+
+    /// Create a pair of connected sockets, like [`Syscall::sys_pipe`] but in both directions.
+    fn sys_socketpair(&mut self) -> SysResult {
+        let domain = self.arg_i32(0);
+        let stype = self.arg_i32(1);
+        let protocol = self.arg_i32(2);
+        let sv_addr = self.arg_addr(3);
+        if domain != AF_UNIX {
+            return Err(Errno::EAFNOSUPPORT)
+        }
+        if stype != SOCK_STREAM || protocol != 0 {
+            return Err(Errno::EINVAL)
+        }
+
+        let pdata = self.data.get_mut();
+        let (file0, file1) = Socket::pair()?;
+        let (fd0, fd1) = pdata.install_fd2(file0, file1)?;
+        let fd0_u32: u32 = fd0.try_into().unwrap();
+        let fd1_u32: u32 = fd1.try_into().unwrap();
+        let ret = pdata.copy_out(&fd0_u32 as *const u32 as *const u8, sv_addr, mem::size_of::<u32>())
+            .and_then(|()| pdata.copy_out(&fd1_u32 as *const u32 as *const u8, sv_addr+mem::size_of::<u32>(), mem::size_of::<u32>()));
+        if let Err(e) = ret {
+            drop(pdata.close_fd(fd0));
+            drop(pdata.close_fd(fd1));
+            return Err(e)
+        }
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].socketpair(sv={:#x}) = ok, fd=[{},{}]", self.excl.lock().pid, sv_addr, fd0, fd1);
+
+        Ok(0)
+    }
+
+    /// Set the group id, leaving the user id unchanged.
+    /// Root can join any group, while others can only set their own group id.
+    fn sys_setgid(&mut self) -> SysResult {
+        let gid = self.arg_i32(0);
+        let gid: u16 = gid.try_into().map_err(|_| Errno::EINVAL)?;
+
+        let cred = &mut self.data.get_mut().cred;
+        let ret = if cred.is_root() || cred.gid == gid {
+            cred.gid = gid;
+            Ok(0)
+        } else {
+            Err(Errno::EPERM)
+        };
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].setgid({}) = {:?}", self.excl.lock().pid, gid, ret);
+
+        ret
+    }
+
+    /// Get the group id.
+    fn sys_getgid(&mut self) -> SysResult {
+        let gid = self.data.get_mut().cred.gid as usize;
+
+        #[cfg(feature = "trace_syscall")]
+        println!("[{}].getgid() = {}", self.excl.lock().pid, gid);
+
+        Ok(gid)
+    }
 }
 
 // LTODO - switch to macro that can include line numbers
@@ -0,0 +1,149 @@
+//! Runtime syscall tracing, enabled per process by the trace syscall
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::consts::MAXPATH;
+
+use super::Proc;
+use super::syscall::SysResult;
+
+/// How a syscall argument is decoded when traced.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// signed integer
+    Int,
+    /// address, flags or masks
+    Hex,
+    /// nul-terminated string in the user space
+    Str,
+}
+
+use Arg::*;
+
+/// Name and arguments of each syscall, indexed by the syscall number.
+/// It should be in accordance with the numbers in include/syscall.h.
+const SYSCALLS: [(&str, &[Arg]); 61] = [
+    ("", &[]),
+    ("fork", &[]),
+    ("exit", &[Int]),
+    ("wait", &[Hex]),
+    ("pipe", &[Hex]),
+    ("read", &[Int, Hex, Int]),
+    ("kill", &[Int, Int]),
+    ("exec", &[Str, Hex]),
+    ("fstat", &[Int, Hex]),
+    ("chdir", &[Str]),
+    ("dup", &[Int]),
+    ("getpid", &[]),
+    ("sbrk", &[Int]),
+    ("sleep", &[Int]),
+    ("uptime", &[]),
+    ("open", &[Str, Hex]),
+    ("write", &[Int, Hex, Int]),
+    ("mknod", &[Str, Int, Int]),
+    ("unlink", &[Str]),
+    ("link", &[Str, Str]),
+    ("mkdir", &[Str]),
+    ("close", &[Int]),
+    ("mmap", &[Hex, Int, Hex, Hex, Int, Int]),
+    ("munmap", &[Hex, Int]),
+    ("clone", &[Hex, Hex, Hex, Hex]),
+    ("join", &[Int, Hex]),
+    ("sigaction", &[Int, Hex, Hex]),
+    ("sigprocmask", &[Int, Hex, Hex]),
+    ("sigreturn", &[]),
+    ("setpriority", &[Int, Int]),
+    ("sched_setaffinity", &[Int, Hex]),
+    ("trace", &[Hex]),
+    ("waitpid", &[Int, Hex, Hex]),
+    ("setpgid", &[Int, Int]),
+    ("getpgid", &[Int]),
+    ("setsid", &[]),
+    ("tcsetpgrp", &[Int, Int]),
+    ("tcgetpgrp", &[Int]),
+    ("execve", &[Str, Hex, Hex]),
+    ("getrlimit", &[Int, Hex]),
+    ("setrlimit", &[Int, Hex]),
+    ("chmod", &[Str, Hex]),
+    ("chown", &[Str, Int, Int]),
+    ("setuid", &[Int]),
+    ("getuid", &[]),
+    ("sigalarm", &[Int, Hex]),
+    ("clock_gettime", &[Int, Hex]),
+    ("nanosleep", &[Hex, Hex]),
+    ("gettimeofday", &[Hex, Hex]),
+    ("futex", &[Hex, Int, Int, Int]),
+    ("fcntl", &[Int, Int, Hex]),
+    ("poll", &[Hex, Int, Int]),
+    ("mkfifo", &[Str, Hex]),
+    ("socket", &[Int, Int, Int]),
+    ("bind", &[Int, Hex, Int]),
+    ("listen", &[Int, Int]),
+    ("accept", &[Int, Hex, Hex]),
+    ("connect", &[Int, Hex, Int]),
+    ("socketpair", &[Int, Int, Int, Hex]),
+    ("setgid", &[Int]),
+    ("getgid", &[]),
+];
+
+/// Syscall that does not return.
+const SYS_EXIT: usize = 2;
+
+impl Proc {
+    /// Check if the syscall `num` is selected by the trace mask.
+    pub(super) fn traced(&self, num: usize) -> bool {
+        let mask = unsafe { self.data.get().as_ref().unwrap().trace_mask };
+        num < SYSCALLS.len() && mask & (1 << num) != 0
+    }
+
+    /// Decode the name and arguments of the syscall `num` before it runs,
+    /// since the arguments may be gone after it, e.g., by exec.
+    /// Exit is logged here, because it never returns.
+    pub(super) fn trace_enter(&self, num: usize) -> String {
+        let (name, args) = SYSCALLS[num];
+        let mut call = String::new();
+        write!(call, "{}(", name).unwrap();
+        for (i, &arg) in args.iter().enumerate() {
+            if i > 0 {
+                call.push_str(", ");
+            }
+            let raw = self.arg_raw(i);
+            match arg {
+                Int => write!(call, "{}", raw as isize),
+                Hex => write!(call, "{:#x}", raw),
+                Str => {
+                    let mut buf = [0u8; MAXPATH];
+                    match self.arg_str(i, &mut buf) {
+                        Ok(()) => {
+                            let len = buf.iter().position(|&c| c == 0).unwrap_or(MAXPATH);
+                            write!(call, "{:?}", String::from_utf8_lossy(&buf[..len]))
+                        },
+                        Err(_) => write!(call, "{:#x}", raw),
+                    }
+                },
+            }.unwrap();
+        }
+        call.push(')');
+
+        if num == SYS_EXIT {
+            println!("[{}] {} = ?", self.excl.lock().pid, call);
+        }
+        call
+    }
+
+    /// Log the syscall decoded by [`trace_enter`] with its result.
+    ///
+    /// [`trace_enter`]: Proc::trace_enter
+    pub(super) fn trace_exit(&self, call: &str, result: &SysResult) {
+        let pid = self.excl.lock().pid;
+        match result {
+            Ok(ret) => {
+                println!("[{}] {} = {}", pid, call, *ret as isize);
+            },
+            Err(errno) => {
+                println!("[{}] {} = -1 {}", pid, call, errno);
+            },
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Memory mapped areas of a process
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::consts::{PGSIZE, MMAPTOP, NVMA};
+use crate::consts::{PROT_READ, PROT_WRITE, PROT_EXEC, MAP_SHARED, MAP_PRIVATE};
+use crate::errno::Errno;
+use crate::fs::File;
+use crate::mm::{Address, Addr, PhysAddr, PteFlag, RawPage, RawSinglePage, VirtAddr};
+use crate::mm::{pg_round_up, pg_round_down, put_user_page};
+
+use super::space::UserSpace;
+
+/// A memory mapped area in the user space,
+/// backed by a file or anonymous memory.
+#[derive(Clone)]
+pub struct Vma {
+    /// page-aligned start address
+    pub(super) start: usize,
+    /// page-aligned end address, exclusive
+    pub(super) end: usize,
+    prot: i32,
+    flags: i32,
+    /// the mapped file, none for anonymous memory
+    file: Option<Arc<File>>,
+    /// file offset that the start address maps to
+    offset: usize,
+}
+
+impl Vma {
+    #[inline]
+    fn contains(&self, va: usize) -> bool {
+        self.start <= va && va < self.end
+    }
+
+    #[inline]
+    pub(super) fn is_shared(&self) -> bool {
+        self.flags & MAP_SHARED > 0
+    }
+
+    /// Page permission of this area.
+    fn perm(&self) -> PteFlag {
+        let mut perm = PteFlag::U;
+        if self.prot & PROT_READ > 0 {
+            perm |= PteFlag::R;
+        }
+        if self.prot & PROT_WRITE > 0 {
+            // writable pages must also be readable in risc-v
+            perm |= PteFlag::R | PteFlag::W;
+        }
+        if self.prot & PROT_EXEC > 0 {
+            perm |= PteFlag::X;
+        }
+        perm
+    }
+}
+
+/// A faulting page that should be read from the mapped file.
+pub struct PageFill {
+    va: usize,
+    file: Arc<File>,
+    offset: u32,
+}
+
+impl PageFill {
+    /// Allocate a page and read the file content into it.
+    /// Return the page, which should be mapped by [`UserSpace::map_filled`].
+    pub fn load(&self) -> Result<*mut u8, &'static str> {
+        let mem = unsafe { RawSinglePage::try_new_zeroed() }
+            .map_err(|_| "not enough memory for mapped page")?;
+        if self.file.read_at(Address::KernelMut(mem), self.offset, PGSIZE as u32).is_err() {
+            unsafe { RawSinglePage::from_raw_and_drop(mem); }
+            return Err("cannot read the mapped file")
+        }
+        Ok(mem)
+    }
+}
+
+/// What is left to do after unmapping a range of an area,
+/// which may sleep and thus must be done without the lock of [`UserSpace`] held.
+/// Dirty pages of a shared file mapping are pinned until written back,
+/// and the mapped file is released afterwards.
+pub struct Unmapped {
+    file: Option<Arc<File>>,
+    pages: Vec<(PhysAddr, u32)>,
+}
+
+impl Unmapped {
+    /// Write back the dirty pages and release them.
+    pub fn finish(self) {
+        if let Some(ref file) = self.file {
+            for &(pa, offset) in self.pages.iter() {
+                if file.write_at(Address::Kernel(pa.as_ptr()), offset, PGSIZE as u32).is_err() {
+                    #[cfg(feature = "kernel_warning")]
+                    println!("kernel warning: cannot write back mapped page at offset {}", offset);
+                }
+                put_user_page(pa);
+            }
+        }
+        drop(self.file);
+    }
+}
+
+impl UserSpace {
+    /// Map `len` bytes of `file` from `offset` into the user space,
+    /// or anonymous memory if `file` is none.
+    /// The pages are populated lazily when first accessed.
+    /// Return the start address of the mapped area.
+    pub fn mmap(&mut self, len: usize, prot: i32, flags: i32, file: Option<Arc<File>>, offset: usize)
+        -> Result<usize, Errno>
+    {
+        if len == 0 || len > MMAPTOP.into() || offset % PGSIZE != 0 {
+            return Err(Errno::EINVAL)
+        }
+        let shared = flags & MAP_SHARED > 0;
+        if shared == (flags & MAP_PRIVATE > 0) {
+            return Err(Errno::EINVAL)
+        }
+        if let Some(ref f) = file {
+            if u32::try_from(offset).is_err() {
+                return Err(Errno::EINVAL)
+            }
+            if !f.mmapable(shared && prot & PROT_WRITE > 0) {
+                return Err(Errno::EACCES)
+            }
+        }
+
+        let slot = self.vmas.iter().position(|v| v.is_none()).ok_or(Errno::ENOMEM)?;
+        let len = pg_round_up(len);
+        let end = self.mmap_bottom();
+        if end < pg_round_up(self.size()) + len {
+            return Err(Errno::ENOMEM)
+        }
+        let start = end - len;
+        self.vmas[slot] = Some(Vma {
+            start,
+            end,
+            prot,
+            flags,
+            file,
+            offset,
+        });
+        Ok(start)
+    }
+
+    /// Unmap [addr, addr+len) from the user space.
+    /// The range should be at the start or the end of an area, or covering the whole area.
+    pub fn munmap(&mut self, addr: usize, len: usize) -> Result<Unmapped, Errno> {
+        if addr % PGSIZE != 0 || len == 0 {
+            return Err(Errno::EINVAL)
+        }
+        let i = self.vma_find(addr).ok_or(Errno::EINVAL)?;
+        let vma = self.vmas[i].as_ref().unwrap();
+        let end = pg_round_up(addr.checked_add(len).ok_or(Errno::EINVAL)?);
+        if end > vma.end || (addr != vma.start && end != vma.end) {
+            return Err(Errno::EINVAL)
+        }
+        Ok(self.vma_unmap(i, addr, end))
+    }
+
+    /// Unmap all the memory mapped areas.
+    pub fn munmap_all(&mut self) -> Vec<Unmapped> {
+        let mut unmapped = Vec::new();
+        for i in 0..NVMA {
+            if let Some(ref vma) = self.vmas[i] {
+                let (start, end) = (vma.start, vma.end);
+                unmapped.push(self.vma_unmap(i, start, end));
+            }
+        }
+        unmapped
+    }
+
+    /// Unmap the memory mapped areas without writing back.
+    /// The areas should already be unmapped by [`munmap_all`] normally,
+    /// except that a failed fork leaves some in the child.
+    /// In that case the parent still refers to the mapped files,
+    /// so dropping them here does not close the files.
+    pub fn vma_clear(&mut self) {
+        for i in 0..NVMA {
+            if let Some(vma) = self.vmas[i].take() {
+                self.pagetable().uvm_unmap(vma.start, (vma.end - vma.start)/PGSIZE, true);
+            }
+        }
+    }
+
+    /// The ranges of the shared memory mapped areas.
+    pub fn shared_areas(&self) -> [Option<(usize, usize)>; NVMA] {
+        let mut areas = [None; NVMA];
+        for (area, vma) in areas.iter_mut().zip(self.vmas.iter()) {
+            if let Some(ref vma) = vma {
+                if vma.is_shared() {
+                    *area = Some((vma.start, vma.end));
+                }
+            }
+        }
+        areas
+    }
+
+    /// Handle a page fault at the user virtual address `va` within a memory mapped area.
+    pub(super) fn vma_fault(&mut self, va: usize, store: bool) -> Result<Option<PageFill>, &'static str> {
+        let i = self.vma_find(va).ok_or("va not in any memory mapped area")?;
+        let va = VirtAddr::try_from(pg_round_down(va))?;
+        if self.pagetable().uvm_fault(va, store)? {
+            return Ok(None)
+        }
+
+        let vma = self.vmas[i].as_ref().unwrap();
+        if vma.prot & (PROT_READ | PROT_WRITE | PROT_EXEC) == 0 {
+            return Err("mapped area not accessible")
+        }
+        if store && vma.prot & PROT_WRITE == 0 {
+            return Err("mapped area not writable")
+        }
+        let offset = vma.offset + (va.as_usize() - vma.start);
+        match (vma.file.as_ref(), u32::try_from(offset)) {
+            (Some(file), Ok(offset)) => {
+                Ok(Some(PageFill { va: va.as_usize(), file: Arc::clone(file), offset }))
+            },
+            _ => {
+                let perm = vma.perm();
+                let mem = unsafe { RawSinglePage::try_new_zeroed() }
+                    .map_err(|_| "not enough memory for mapped page")?;
+                self.pagetable().uvm_map_new(va, mem, perm)?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Map the page loaded by [`PageFill::load`].
+    /// The area may have been changed by another thread during loading.
+    pub fn map_filled(&mut self, fill: PageFill, mem: *mut u8) -> Result<(), &'static str> {
+        let perm = match self.vma_find(fill.va) {
+            Some(i) => self.vmas[i].as_ref().unwrap().perm(),
+            None => {
+                unsafe { RawSinglePage::from_raw_and_drop(mem); }
+                return Err("mapped area unmapped during page fault")
+            },
+        };
+        self.pagetable().uvm_map_new(VirtAddr::try_from(fill.va)?, mem, perm)
+    }
+
+    /// Check if the user virtual address is within any memory mapped area.
+    #[inline]
+    pub fn in_vma(&self, va: usize) -> bool {
+        self.vma_find(va).is_some()
+    }
+
+    /// Total bytes of the memory mapped areas.
+    pub fn vma_size(&self) -> usize {
+        self.vmas.iter()
+            .filter_map(|v| v.as_ref())
+            .map(|v| v.end - v.start)
+            .sum()
+    }
+
+    /// The lowest address of the memory mapped areas,
+    /// which is also the upper limit of the program break.
+    pub fn mmap_bottom(&self) -> usize {
+        self.vmas.iter()
+            .filter_map(|v| v.as_ref())
+            .map(|v| v.start)
+            .fold(MMAPTOP.into(), |bottom, start| if start < bottom { start } else { bottom })
+    }
+
+    fn vma_find(&self, va: usize) -> Option<usize> {
+        self.vmas.iter()
+            .position(|v| v.as_ref().map_or(false, |v| v.contains(va)))
+    }
+
+    /// Unmap [start, end) of the i-th area,
+    /// then shrink the area or remove it if nothing left.
+    fn vma_unmap(&mut self, i: usize, start: usize, end: usize) -> Unmapped {
+        let mut vma = self.vmas[i].take().unwrap();
+        let mut pages = Vec::new();
+        if vma.is_shared() && vma.file.is_some() {
+            for va in (start..end).step_by(PGSIZE) {
+                let offset = match u32::try_from(vma.offset + (va - vma.start)) {
+                    Ok(offset) => offset,
+                    Err(_) => break,
+                };
+                if let Some(pa) = self.pagetable().pin_dirty_page(VirtAddr::try_from(va).unwrap()) {
+                    pages.push((pa, offset));
+                }
+            }
+        }
+        self.pagetable().uvm_unmap(start, (end - start)/PGSIZE, true);
+
+        if start == vma.start {
+            vma.offset += end - start;
+            vma.start = end;
+        } else {
+            vma.end = start;
+        }
+        let file = vma.file.clone();
+        if vma.start != vma.end {
+            self.vmas[i] = Some(vma);
+        }
+        Unmapped { file, pages }
+    }
+}
@@ -0,0 +1,87 @@
+//! Per-cpu run queue of the multilevel feedback queue scheduler
+
+use array_macro::array;
+
+use crate::consts::NPROC;
+
+/// A runnable proc waiting in a run queue.
+#[derive(Clone, Copy)]
+struct Entry {
+    /// index into the process table
+    index: usize,
+    /// priority level, lower is higher
+    level: usize,
+    /// level to move back when boosted
+    base: usize,
+    /// cpus allowed to run the proc, copied when enqueued
+    affinity: usize,
+    /// order of enqueueing in the same level
+    seq: usize,
+}
+
+/// Runnable procs of a cpu.
+/// Procs at a higher level are picked first, in the FIFO order at the same level.
+/// A proc is in at most one run queue, so it holds at most [`NPROC`] entries.
+pub struct RunQueue {
+    entries: [Option<Entry>; NPROC],
+    len: usize,
+    seq: usize,
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            entries: array![_ => None; NPROC],
+            len: 0,
+            seq: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Append a proc at its level.
+    pub fn push(&mut self, index: usize, level: usize, base: usize, affinity: usize) {
+        let slot = self.entries.iter_mut()
+            .find(|e| e.is_none())
+            .expect("run queue full");
+        *slot = Some(Entry {
+            index,
+            level,
+            base,
+            affinity,
+            seq: self.seq,
+        });
+        self.seq += 1;
+        self.len += 1;
+    }
+
+    /// Is there any proc allowed to run on the cpus in `allowed`?
+    pub fn has(&self, allowed: usize) -> bool {
+        self.entries.iter().flatten().any(|e| e.affinity & allowed != 0)
+    }
+
+    /// Take the proc at the highest level that is allowed to run on the cpus in `allowed`.
+    pub fn pop(&mut self, allowed: usize) -> Option<usize> {
+        let (i, _) = self.entries.iter()
+            .enumerate()
+            .filter_map(|(i, e)| match e {
+                Some(e) if e.affinity & allowed != 0 => Some((i, (e.level, e.seq))),
+                _ => None,
+            })
+            .min_by_key(|&(_, key)| key)?;
+
+        let entry = self.entries[i].take().unwrap();
+        self.len -= 1;
+        Some(entry.index)
+    }
+
+    /// Move every proc back to its base level.
+    pub fn boost(&mut self) {
+        for e in self.entries.iter_mut().flatten() {
+            e.level = e.base;
+        }
+    }
+}
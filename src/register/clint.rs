@@ -6,15 +6,15 @@
 use core::ptr;
 use core::convert::Into;
 
-use crate::consts::{CLINT_MTIME, CLINT_MTIMECMP};
+use crate::consts::{CLINT_MSIP, CLINT_MTIME, CLINT_MTIMECMP};
 
 #[inline]
-unsafe fn read_mtime() -> u64 {
+pub unsafe fn read_mtime() -> u64 {
     ptr::read_volatile(Into::<usize>::into(CLINT_MTIME) as *const u64)
 }
 
 #[inline]
-unsafe fn write_mtimecmp(mhartid: usize, value: u64) {
+pub unsafe fn write_mtimecmp(mhartid: usize, value: u64) {
     let offset = Into::<usize>::into(CLINT_MTIMECMP) + 8 * mhartid;
     ptr::write_volatile(offset as *mut u64, value);
 }
@@ -28,3 +28,11 @@ pub unsafe fn read_mtimecmp(mhartid: usize) -> u64 {
     let offset = Into::<usize>::into(CLINT_MTIMECMP) + 8 * mhartid;
     ptr::read_volatile(offset as *const u64)
 }
+
+/// Raise a machine-mode software interrupt on the hart, i.e., an IPI,
+/// which timervec in kernelvec.S clears and forwards to the supervisor.
+#[inline]
+pub unsafe fn send_ipi(mhartid: usize) {
+    let offset = Into::<usize>::into(CLINT_MSIP) + 4 * mhartid;
+    ptr::write_volatile(offset as *mut u32, 1);
+}
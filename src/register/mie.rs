@@ -20,3 +20,10 @@ pub unsafe fn set_mtie() {
     mie.set_bit(7, true);
     write(mie);
 }
+
+/// set MSIE field
+pub unsafe fn set_msie() {
+    let mut mie = read();
+    mie.set_bit(3, true);
+    write(mie);
+}
@@ -98,3 +98,10 @@ pub mod stval {
         ret
     }
 }
+
+/// wfi
+/// wait for interrupt, the hart may idle until one is pending.
+#[inline]
+pub fn wfi() {
+    unsafe {llvm_asm!("wfi"::::"volatile");}
+}
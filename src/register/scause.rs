@@ -5,12 +5,18 @@ const INTERRUPT_SUPERVISOR_SOFTWARE: usize = INTERRUPT + 1;
 const INTERRUPT_SUPERVISOR_EXTERNAL: usize = INTERRUPT + 9;
 const EXCEPTION: usize = 0;
 const EXCEPTION_ECALL_USER: usize = EXCEPTION + 8;
+const EXCEPTION_INSTRUCTION_PAGE_FAULT: usize = EXCEPTION + 12;
+const EXCEPTION_LOAD_PAGE_FAULT: usize = EXCEPTION + 13;
+const EXCEPTION_STORE_PAGE_FAULT: usize = EXCEPTION + 15;
 
 pub enum ScauseType {
     Unknown,
     IntSSoft,
     IntSExt,
     ExcUEcall,
+    ExcInstPageFault,
+    ExcLoadPageFault,
+    ExcStorePageFault,
 }
 
 #[inline]
@@ -26,6 +32,9 @@ pub fn get_scause() -> ScauseType {
         INTERRUPT_SUPERVISOR_SOFTWARE => ScauseType::IntSSoft,
         INTERRUPT_SUPERVISOR_EXTERNAL => ScauseType::IntSExt,
         EXCEPTION_ECALL_USER => ScauseType::ExcUEcall,
+        EXCEPTION_INSTRUCTION_PAGE_FAULT => ScauseType::ExcInstPageFault,
+        EXCEPTION_LOAD_PAGE_FAULT => ScauseType::ExcLoadPageFault,
+        EXCEPTION_STORE_PAGE_FAULT => ScauseType::ExcStorePageFault,
         _ => ScauseType::Unknown,
     }
 }
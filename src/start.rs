@@ -1,12 +1,12 @@
 use core::convert::Into;
 
-use crate::{consts::{CLINT_MTIMECMP, NCPU}, register::sie};
+use crate::{consts::{CLINT_MSIP, CLINT_MTIMECMP, NCPU, TIMER_INTERVAL}, register::sie};
 use crate::register::{
     clint, medeleg, mepc, mhartid, mideleg, mie, mscratch, mstatus, mtvec, satp, tp,
 };
 use crate::rmain::rust_main;
 
-/// for each cpu, only 6 of 32 usize are used, others are reserved.
+/// for each cpu, only 7 of 32 usize are used, others are reserved.
 static mut MSCRATCH0: [usize; NCPU * 32] = [0; NCPU * 32];
 
 #[no_mangle]
@@ -48,16 +48,18 @@ unsafe fn timerinit() {
     let id = mhartid::read();
 
     // ask the CLINT for a timer interrupt.
-    let interval: u64 = 1000000; // cycles; about 1/10th second in qemu.
+    let interval: u64 = TIMER_INTERVAL;
     clint::add_mtimecmp(id, interval);
 
     // prepare information in scratch[] for timervec.
     // scratch[0..3] : space for timervec to save registers.
     // scratch[4] : address of CLINT MTIMECMP register.
     // scratch[5] : desired interval (in cycles) between timer interrupts.
+    // scratch[6] : address of CLINT MSIP register, for the IPIs.
     let offset = 32 * id;
     MSCRATCH0[offset + 4] = 8 * id + Into::<usize>::into(CLINT_MTIMECMP);
     MSCRATCH0[offset + 5] = interval as usize;
+    MSCRATCH0[offset + 6] = 4 * id + Into::<usize>::into(CLINT_MSIP);
     mscratch::write((MSCRATCH0.as_ptr() as usize) + offset * core::mem::size_of::<usize>());
 
     // set the machine-mode trap handler.
@@ -71,4 +73,7 @@ unsafe fn timerinit() {
 
     // enable machine-mode timer interrupts.
     mie::set_mtie();
+
+    // enable machine-mode software interrupts, the IPIs from other harts.
+    mie::set_msie();
 }
@@ -0,0 +1,155 @@
+//! High-resolution time from the CLINT mtime, and precise timed sleeps
+//!
+//! Every hart gets a timer interrupt each [`TIMER_INTERVAL`] cycles as the tick.
+//! A sleeping process arms an earlier one for its deadline,
+//! so that it is woken up right then instead of checking on every tick.
+
+use core::cmp::min;
+
+use crate::consts::{NCPU, NPROC, MTIME_FREQ, TIMER_INTERVAL};
+use crate::process::{PROC_MANAGER, CpuManager, Proc};
+use crate::register::clint;
+use crate::spinlock::SpinLock;
+
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+const NSEC_PER_CYCLE: u64 = NSEC_PER_SEC / MTIME_FREQ;
+
+/// Deadlines in mtime of the sleeping processes, indexed by the process index.
+/// The address of each entry is the channel the process sleeps on.
+static TIMERS: SpinLock<[Option<u64>; NPROC]> = SpinLock::new([None; NPROC], "timers");
+
+/// When the next tick is due on each hart.
+/// Only accessed by the hart itself with interrupts disabled.
+static mut NEXT_TICK: [u64; NCPU] = [0; NCPU];
+
+/// Read the current mtime.
+#[inline]
+pub fn now() -> u64 {
+    unsafe { clint::read_mtime() }
+}
+
+/// Nanoseconds since boot.
+pub fn monotonic_ns() -> u64 {
+    now().saturating_mul(NSEC_PER_CYCLE)
+}
+
+/// Convert nanoseconds to cycles of mtime, rounded up.
+fn ns_to_cycles(ns: u64) -> u64 {
+    ns / NSEC_PER_CYCLE + (ns % NSEC_PER_CYCLE != 0) as u64
+}
+
+/// Handle a timer interrupt on this hart, return if it is a tick.
+/// Wake up the sleepers whose deadlines have passed,
+/// and arm the timer for the nearer one of the next tick and the remaining deadlines.
+/// Must be called with interrupts disabled.
+pub unsafe fn timer_intr() -> bool {
+    let id = CpuManager::cpu_id();
+    let now = now();
+    let next = &mut NEXT_TICK[id];
+    let tick = now >= *next;
+    if tick {
+        *next += TIMER_INTERVAL;
+        if *next <= now {
+            *next = now + TIMER_INTERVAL;
+        }
+    }
+
+    let mut timers = TIMERS.lock();
+    let mut nearest = *next;
+    for timer in timers.iter_mut() {
+        match *timer {
+            Some(deadline) if deadline <= now => {
+                *timer = None;
+                PROC_MANAGER.wakeup(timer as *const Option<u64> as usize);
+            },
+            Some(deadline) => nearest = min(nearest, deadline),
+            None => {},
+        }
+    }
+    // timervec in kernelvec.S has moved mtimecmp after the interrupt, override it
+    clint::write_mtimecmp(id, nearest);
+    drop(timers);
+
+    tick
+}
+
+/// The deadline in mtime `ns` nanoseconds from now, for [`nanosleep`].
+pub fn deadline_after(ns: u64) -> u64 {
+    now().saturating_add(ns_to_cycles(ns))
+}
+
+/// Sleep until mtime reaches the `deadline`.
+/// If interrupted before that, return the remaining nanoseconds as the error.
+pub fn nanosleep(p: &Proc, deadline: u64) -> Result<(), u64> {
+    let mut timers = TIMERS.lock();
+    let channel = &timers[p.index()] as *const Option<u64> as usize;
+    timers[p.index()] = Some(deadline);
+
+    // arm this hart if the deadline is nearer than its next interrupt,
+    // interrupts are disabled by the lock
+    unsafe {
+        let id = CpuManager::cpu_id();
+        if deadline < clint::read_mtimecmp(id) {
+            clint::write_mtimecmp(id, deadline);
+        }
+    }
+
+    loop {
+        let now = now();
+        if now >= deadline {
+            break
+        }
+        if p.interrupted() {
+            timers[p.index()] = None;
+            return Err((deadline - now).saturating_mul(NSEC_PER_CYCLE))
+        }
+        p.sleep_interruptible(channel, timers);
+        timers = TIMERS.lock();
+    }
+    timers[p.index()] = None;
+    Ok(())
+}
+
+/// Time in seconds and nanoseconds.
+/// Its layout should be the same as `struct timespec` in the user space.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TimeSpec {
+    pub sec: i64,
+    pub nsec: i64,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            sec: (ns / NSEC_PER_SEC) as i64,
+            nsec: (ns % NSEC_PER_SEC) as i64,
+        }
+    }
+
+    /// Convert to nanoseconds, None if it is negative or the nanoseconds are out of range.
+    pub fn to_ns(&self) -> Option<u64> {
+        if self.sec < 0 || self.nsec < 0 || self.nsec as u64 >= NSEC_PER_SEC {
+            return None
+        }
+        Some((self.sec as u64).saturating_mul(NSEC_PER_SEC).saturating_add(self.nsec as u64))
+    }
+}
+
+/// Time in seconds and microseconds, used by gettimeofday.
+/// Its layout should be the same as `struct timeval` in the user space.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TimeVal {
+    pub sec: i64,
+    pub usec: i64,
+}
+
+impl TimeVal {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            sec: (ns / NSEC_PER_SEC) as i64,
+            usec: (ns % NSEC_PER_SEC / 1000) as i64,
+        }
+    }
+}
@@ -1,14 +1,15 @@
 //! Trap handler between user/kernel space and kernel space
 
 use core::num::Wrapping;
-use core::sync::atomic::Ordering;
 
-use crate::{consts::{TRAMPOLINE, TRAPFRAME, UART0_IRQ, VIRTIO0_IRQ}, process::{PROC_MANAGER, Proc}};
+use crate::{consts::{TRAMPOLINE, UART0_IRQ, VIRTIO0_IRQ, BOOST_INTERVAL, signal::SIGSEGV}, process::{PROC_MANAGER, Proc, futex_tick}, fs::poll_tick};
 use crate::register::{stvec, sstatus, sepc, stval, sip,
     scause::{self, ScauseType}};
 use crate::process::{CPU_MANAGER, CpuManager};
+use crate::errno::Errno;
 use crate::spinlock::SpinLock;
 use crate::plic;
+use crate::timer;
 use crate::driver::virtio_disk::DISK;
 use crate::driver::uart::UART;
 
@@ -55,23 +56,53 @@ pub unsafe extern fn user_trap() {
             // software interrupt from a machine-mode timer interrupt,
             // forwarded by timervec in kernelvec.S.
 
-            // only cpu 0 inc ticks
-            if CpuManager::cpu_id() == 0 {
-                clock_intr();
-            }
-
-            // acknowledge the software interrupt
+            // acknowledge the software interrupt,
+            // before the timer is armed again
             sip::clear_ssip();
 
-            // give up the cpu
+            let tick = timer::timer_intr();
+            if tick {
+                // only cpu 0 inc ticks
+                if CpuManager::cpu_id() == 0 {
+                    clock_intr();
+                }
+
+                // the tick is taken in the user space, count it for the alarm
+                p.alarm_tick();
+            }
+
+            // give up the cpu on ticks
             p.check_abondon(-1);
-            p.yielding();
+            if tick {
+                p.yielding();
+            }
         }
         ScauseType::ExcUEcall => {
             p.check_abondon(-1);
             p.syscall();
             p.check_abondon(-1);
         }
+        ScauseType::ExcInstPageFault => {
+            let pd = p.data.get_mut();
+            if let Err(s) = pd.exec_fault(stval::read()) {
+                segv(p, s);
+            }
+            p.check_abondon(-1);
+        }
+        ScauseType::ExcLoadPageFault => {
+            let pd = p.data.get_mut();
+            if let Err(s) = pd.page_fault(stval::read(), false) {
+                segv(p, s);
+            }
+            p.check_abondon(-1);
+        }
+        ScauseType::ExcStorePageFault => {
+            let pd = p.data.get_mut();
+            if let Err(s) = pd.page_fault(stval::read(), true) {
+                segv(p, s);
+            }
+            p.check_abondon(-1);
+        }
         ScauseType::Unknown => {
             println!("scause {:#x}", scause::read());
             println!("sepc={:#x} stval={:#x}", sepc::read(), stval::read());
@@ -82,8 +113,20 @@ pub unsafe extern fn user_trap() {
     user_trap_ret();
 }
 
+/// Report a user page fault that cannot be handled to the process by SIGSEGV,
+/// which terminates it unless caught.
+#[allow(unused_variables)]
+fn segv(p: &Proc, reason: &str) {
+    #[cfg(feature = "kernel_warning")]
+    println!("kernel warning: segfault at {:#x}, sepc={:#x}: {}", stval::read(), sepc::read(), reason);
+    p.force_signal(SIGSEGV);
+}
+
 /// Return to user space
 pub unsafe fn user_trap_ret() -> ! {
+    // deliver pending signals, the process may exit or stop here
+    CPU_MANAGER.my_proc().handle_signals();
+
     // disable interrupts and prepare sret to user mode
     sstatus::intr_off();
     sstatus::user_ret_prepare();
@@ -92,7 +135,7 @@ pub unsafe fn user_trap_ret() -> ! {
     stvec::write(TRAMPOLINE.into());
 
     // let the current process prepare for the sret
-    let satp = {
+    let (tf_va, satp) = {
         let pd = CPU_MANAGER.my_proc().data.get_mut();
         pd.user_ret_prepare()
     };
@@ -105,7 +148,7 @@ pub unsafe fn user_trap_ret() -> ! {
     let distance = userret as usize - trampoline as usize;
     let userret_virt: extern "C" fn(usize, usize) -> ! =
         core::mem::transmute(Into::<usize>::into(TRAMPOLINE) + distance);
-    userret_virt(TRAPFRAME.into(), satp);
+    userret_virt(tf_va, satp);
 }
 
 /// Used to handle kernel space's trap
@@ -142,21 +185,25 @@ pub unsafe fn kerneltrap() {
             // software interrupt from a machine-mode timer interrupt,
             // forwarded by timervec in kernelvec.S.
 
-            // only cpu 0 inc ticks
-            if CpuManager::cpu_id() == 0 {
-                clock_intr();
-            }
-
-            // acknowledge the software interrupt
+            // acknowledge the software interrupt,
+            // before the timer is armed again
             sip::clear_ssip();
 
-            // give up the cpu
-            CPU_MANAGER.my_cpu_mut().try_yield_proc();
+            if timer::timer_intr() {
+                // only cpu 0 inc ticks
+                if CpuManager::cpu_id() == 0 {
+                    clock_intr();
+                }
+
+                // give up the cpu
+                CPU_MANAGER.my_cpu_mut().try_yield_proc();
+            }
         }
         ScauseType::ExcUEcall => {
             panic!("ecall from supervisor mode");
         }
-        ScauseType::Unknown => {
+        ScauseType::ExcInstPageFault | ScauseType::ExcLoadPageFault |
+        ScauseType::ExcStorePageFault | ScauseType::Unknown => {
             println!("scause {:#x}", scause::read());
             println!("sepc={:#x} stval={:#x}", sepc::read(), stval::read());
             panic!("unknown trap type");
@@ -174,19 +221,26 @@ static TICKS: SpinLock<Wrapping<usize>> = SpinLock::new(Wrapping(0), "time");
 fn clock_intr() {
     let mut guard = TICKS.lock();
     *guard += Wrapping(1);
+    let boost = guard.0 % BOOST_INTERVAL == 0;
     unsafe { PROC_MANAGER.wakeup(&TICKS as *const _ as usize); }
+    let now = guard.0;
     drop(guard);
+    futex_tick(now);
+    poll_tick(now);
+    if boost {
+        unsafe { PROC_MANAGER.boost(); }
+    }
 }
 
 /// Sleep for a specified number of ticks.
-pub fn clock_sleep(p: &Proc, count: usize) -> Result<(), ()> {
+pub fn clock_sleep(p: &Proc, count: usize) -> Result<(), Errno> {
     let mut guard = TICKS.lock();
     let old_ticks = *guard;
     while (*guard - old_ticks) < Wrapping(count) {
-        if p.killed.load(Ordering::Relaxed) {
-            return Err(())
+        if p.interrupted() {
+            return Err(Errno::EINTR)
         }
-        p.sleep(&TICKS as *const _ as usize, guard);
+        p.sleep_interruptible(&TICKS as *const _ as usize, guard);
         guard = TICKS.lock();
     }
     Ok(())
@@ -0,0 +1,54 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/errno.h"
+#include "user/user.h"
+
+#define MAXENV 32
+
+// Length of the name part of a name=value string,
+// or -1 if it is not an assignment.
+static int
+namelen(char *s)
+{
+  char *eq;
+
+  eq = strchr(s, '=');
+  if(eq == 0 || eq == s)
+    return -1;
+  return eq - s;
+}
+
+// Print the environment, or run a command in it,
+// with the leading name=value arguments added or replaced.
+int
+main(int argc, char *argv[], char *envp[])
+{
+  static char *env[MAXENV+1];
+  int i, j, k, n, nenv;
+
+  nenv = 0;
+  for(j = 0; envp[j] && nenv < MAXENV; j++)
+    env[nenv++] = envp[j];
+  for(i = 1; i < argc && (n = namelen(argv[i])) > 0; i++){
+    for(k = 0; k < nenv; k++)
+      if(namelen(env[k]) == n && memcmp(env[k], argv[i], n) == 0)
+        break;
+    if(k == MAXENV){
+      fprintf(2, "env: too many variables\n");
+      exit(1);
+    }
+    env[k] = argv[i];
+    if(k == nenv)
+      nenv++;
+  }
+  env[nenv] = 0;
+
+  if(i == argc){
+    for(k = 0; k < nenv; k++)
+      printf("%s\n", env[k]);
+    exit(0);
+  }
+  execve(argv[i], argv + i, env);
+  fprintf(2, "env: exec %s failed: %s\n", argv[i], strerror(errno));
+  exit(1);
+}
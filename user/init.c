@@ -15,6 +15,7 @@ int
 main(void)
 {
   int pid, wpid;
+  struct stat st;
 
   if(open("console", O_RDWR) < 0){
     mknod("console", CONSOLE, 0);
@@ -23,6 +24,9 @@ main(void)
   dup(0);  // stdout
   dup(0);  // stderr
 
+  if(stat("rtc", &st) < 0)
+    mknod("rtc", RTC, 0);
+
   for(;;){
     printf("init: starting sh\n");
     pid = fork();
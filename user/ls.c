@@ -22,6 +22,46 @@ fmtname(char *path)
   return buf;
 }
 
+// Format seconds since the epoch as "YYYY-MM-DD hh:mm" in UTC.
+char*
+fmtdate(uint t)
+{
+  static char buf[17];
+  int days, y, m, d, doy, yoe, era, mp;
+
+  // civil date from days since 1970-01-01, with years starting in March
+  days = t / 86400 + 719468;
+  era = days / 146097;
+  doy = days - era * 146097;
+  yoe = (doy - doy/1460 + doy/36524 - doy/146096) / 365;
+  y = yoe + era * 400;
+  doy = doy - (365*yoe + yoe/4 - yoe/100);
+  mp = (5*doy + 2) / 153;
+  d = doy - (153*mp + 2)/5 + 1;
+  m = mp < 10 ? mp + 3 : mp - 9;
+  if(m <= 2)
+    y++;
+
+  buf[0] = '0' + y / 1000;
+  buf[1] = '0' + y / 100 % 10;
+  buf[2] = '0' + y / 10 % 10;
+  buf[3] = '0' + y % 10;
+  buf[4] = '-';
+  buf[5] = '0' + m / 10;
+  buf[6] = '0' + m % 10;
+  buf[7] = '-';
+  buf[8] = '0' + d / 10;
+  buf[9] = '0' + d % 10;
+  buf[10] = ' ';
+  buf[11] = '0' + t / 3600 % 24 / 10;
+  buf[12] = '0' + t / 3600 % 24 % 10;
+  buf[13] = ':';
+  buf[14] = '0' + t / 60 % 60 / 10;
+  buf[15] = '0' + t / 60 % 60 % 10;
+  buf[16] = 0;
+  return buf;
+}
+
 void
 ls(char *path)
 {
@@ -43,7 +83,7 @@ ls(char *path)
 
   switch(st.type){
   case T_FILE:
-    printf("%s %d %d %l\n", fmtname(path), st.type, st.ino, st.size);
+    printf("%s %d %d %l %s\n", fmtname(path), st.type, st.ino, st.size, fmtdate(st.mtime));
     break;
 
   case T_DIR:
@@ -63,7 +103,7 @@ ls(char *path)
         printf("ls: cannot stat %s\n", buf);
         continue;
       }
-      printf("%s %d %d %d\n", fmtname(buf), st.type, st.ino, st.size);
+      printf("%s %d %d %d %s\n", fmtname(buf), st.type, st.ino, st.size, fmtdate(st.mtime));
     }
     break;
   }
@@ -0,0 +1,25 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "user/user.h"
+
+int
+main(int argc, char **argv)
+{
+  int n;
+
+  if(argc < 3){
+    fprintf(2, "usage: nice n command [args...]\n");
+    exit(1);
+  }
+  if(argv[1][0] == '-')
+    n = -atoi(argv[1] + 1);
+  else
+    n = atoi(argv[1]);
+  if(setpriority(0, n) < 0){
+    fprintf(2, "nice: cannot set priority %d\n", n);
+    exit(1);
+  }
+  exec(argv[2], argv + 2);
+  fprintf(2, "nice: exec %s failed\n", argv[2]);
+  exit(1);
+}
@@ -3,6 +3,9 @@
 #include "include/types.h"
 #include "user/user.h"
 #include "include/fcntl.h"
+#include "include/errno.h"
+#include "include/wait.h"
+#include "include/signal.h"
 
 // Parsed command representation
 #define EXEC  1
@@ -49,6 +52,8 @@ struct backcmd {
   struct cmd *cmd;
 };
 
+int interactive = 1;  // Read commands from the console, not a script.
+
 int fork1(void);  // Fork but panics on failure.
 void panic(char*);
 struct cmd *parsecmd(char*);
@@ -76,14 +81,14 @@ runcmd(struct cmd *cmd)
     if(ecmd->argv[0] == 0)
       exit(1);
     exec(ecmd->argv[0], ecmd->argv);
-    fprintf(2, "exec %s failed\n", ecmd->argv[0]);
+    fprintf(2, "exec %s failed: %s\n", ecmd->argv[0], strerror(errno));
     break;
 
   case REDIR:
     rcmd = (struct redircmd*)cmd;
     close(rcmd->fd);
     if(open(rcmd->file, rcmd->mode) < 0){
-      fprintf(2, "open %s failed\n", rcmd->file);
+      fprintf(2, "open %s failed: %s\n", rcmd->file, strerror(errno));
       exit(1);
     }
     runcmd(rcmd->cmd);
@@ -133,7 +138,8 @@ runcmd(struct cmd *cmd)
 int
 getcmd(char *buf, int nbuf)
 {
-  fprintf(2, "$ ");
+  if(interactive)
+    fprintf(2, "$ ");
   memset(buf, 0, nbuf);
   gets(buf, nbuf);
   if(buf[0] == 0) // EOF
@@ -141,11 +147,51 @@ getcmd(char *buf, int nbuf)
   return 0;
 }
 
+// Set the action of the job control signals.
+void
+jobsignals(void (*handler)(int))
+{
+  struct sigaction sa;
+
+  memset(&sa, 0, sizeof(sa));
+  sa.sa_handler = handler;
+  sigaction(SIGINT, &sa, 0);
+  sigaction(SIGTSTP, &sa, 0);
+}
+
+// Check if the command line ends with '&'.
 int
-main(void)
+background(char *buf)
+{
+  int i;
+
+  for(i = strlen(buf) - 1; i >= 0 && strchr(" \t\r\n", buf[i]); i--)
+    ;
+  return i >= 0 && buf[i] == '&';
+}
+
+// Wait for the job in the foreground, until it finishes or stops,
+// and take back the console.
+void
+waitjob(int pid)
+{
+  int status;
+
+  tcsetpgrp(0, pid);
+  if(waitpid(pid, &status, WUNTRACED) < 0)
+    status = 0;
+  tcsetpgrp(0, getpid());
+  if(WIFSIGNALED(status) && WTERMSIG(status) != SIGINT)
+    fprintf(2, "[%d] killed by signal %d\n", pid, WTERMSIG(status));
+  else if(WIFSTOPPED(status))
+    fprintf(2, "[%d] stopped\n", pid);
+}
+
+int
+main(int argc, char *argv[])
 {
   static char buf[100];
-  int fd;
+  int fd, pid;
 
   // Ensure that three file descriptors are open.
   while((fd = open("console", O_RDWR)) >= 0){
@@ -155,18 +201,60 @@ main(void)
     }
   }
 
+  if(argc > 1){
+    // Run a script, e.g., started by exec with "#!/sh".
+    close(0);
+    if(open(argv[1], O_RDONLY) != 0){
+      fprintf(2, "sh: cannot open %s: %s\n", argv[1], strerror(errno));
+      exit(1);
+    }
+    interactive = 0;
+  } else {
+    // Own the console, and leave Ctrl-C/Ctrl-Z to the foreground job.
+    setsid();
+    tcsetpgrp(0, getpid());
+    jobsignals(SIG_IGN);
+  }
+
   // Read and run input commands.
   while(getcmd(buf, sizeof(buf)) >= 0){
+    if(buf[0] == '#')  // comment, including the "#!" line
+      continue;
     if(buf[0] == 'c' && buf[1] == 'd' && buf[2] == ' '){
       // Chdir must be called by the parent, not the child.
       buf[strlen(buf)-1] = 0;  // chop \n
       if(chdir(buf+3) < 0)
-        fprintf(2, "cannot cd %s\n", buf+3);
+        fprintf(2, "cannot cd %s: %s\n", buf+3, strerror(errno));
       continue;
     }
-    if(fork1() == 0)
+    if((buf[0] == 'f' || buf[0] == 'b') && buf[1] == 'g' && buf[2] == ' '){
+      // Continue a stopped job, in the foreground or background.
+      pid = atoi(buf+3);
+      if(kill(-pid, SIGCONT) < 0){
+        fprintf(2, "no job %d: %s\n", pid, strerror(errno));
+        continue;
+      }
+      if(buf[0] == 'f')
+        waitjob(pid);
+      continue;
+    }
+    if(!interactive){
+      if(fork1() == 0)
+        runcmd(parsecmd(buf));
+      wait(0);
+      continue;
+    }
+    // Each job runs in its own process group.
+    if((pid = fork1()) == 0){
+      setpgid(0, 0);
+      jobsignals(SIG_DFL);
       runcmd(parsecmd(buf));
-    wait(0);
+    }
+    setpgid(pid, pid);
+    if(background(buf))
+      waitpid(pid, 0, 0);
+    else
+      waitjob(pid);
   }
   exit(0);
 }
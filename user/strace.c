@@ -0,0 +1,32 @@
+#include "include/types.h"
+#include "include/stat.h"
+#include "include/errno.h"
+#include "user/user.h"
+
+// Run a command with its syscalls logged by the kernel,
+// all of them by default, or those selected by the mask,
+// where bit n selects the syscall numbered n.
+int
+main(int argc, char **argv)
+{
+  unsigned long mask;
+  int i;
+
+  mask = ~0UL;
+  i = 1;
+  if(argc > 2 && strcmp(argv[1], "-m") == 0){
+    mask = atoi(argv[2]);
+    i = 3;
+  }
+  if(i >= argc){
+    fprintf(2, "usage: strace [-m mask] command [args...]\n");
+    exit(1);
+  }
+  if(trace(mask) < 0){
+    fprintf(2, "strace: trace failed: %s\n", strerror(errno));
+    exit(1);
+  }
+  exec(argv[i], argv + i);
+  fprintf(2, "strace: exec %s failed: %s\n", argv[i], strerror(errno));
+  exit(1);
+}
@@ -1,6 +1,8 @@
 #include "include/types.h"
 #include "include/stat.h"
 #include "include/fcntl.h"
+#include "include/signal.h"
+#include "include/errno.h"
 #include "user/user.h"
 
 char*
@@ -134,3 +136,78 @@ memcpy(void *dst, const void *src, uint n)
 {
   return memmove(dst, src, n);
 }
+
+int __sigaction(int, const struct sigaction*, struct sigaction*);
+
+// handlers return to sigreturn, which restores the interrupted context
+int
+sigaction(int sig, const struct sigaction *act, struct sigaction *oldact)
+{
+  struct sigaction kact;
+
+  if(act == 0)
+    return __sigaction(sig, 0, oldact);
+  kact = *act;
+  kact.sa_restorer = (void (*)(void))sigreturn;
+  return __sigaction(sig, &kact, oldact);
+}
+
+int errno;
+
+// jumped to by the syscall stubs in usys.S when the kernel returns -errno
+long
+__syscall_error(long ret)
+{
+  errno = -ret;
+  return -1;
+}
+
+static const char *errstr[] = {
+[0]             "success",
+[EPERM]         "operation not permitted",
+[ENOENT]        "no such file or directory",
+[ESRCH]         "no such process",
+[EINTR]         "interrupted system call",
+[EIO]           "input/output error",
+[ENXIO]         "no such device or address",
+[E2BIG]         "argument list too long",
+[ENOEXEC]       "exec format error",
+[EBADF]         "bad file descriptor",
+[ECHILD]        "no child processes",
+[EAGAIN]        "resource temporarily unavailable",
+[ENOMEM]        "out of memory",
+[EACCES]        "permission denied",
+[EFAULT]        "bad address",
+[EBUSY]         "device or resource busy",
+[EEXIST]        "file exists",
+[EXDEV]         "cross-device link",
+[ENODEV]        "no such device",
+[ENOTDIR]       "not a directory",
+[EISDIR]        "is a directory",
+[EINVAL]        "invalid argument",
+[ENFILE]        "too many open files in system",
+[EMFILE]        "too many open files",
+[ENOTTY]        "inappropriate ioctl for device",
+[EFBIG]         "file too large",
+[ENOSPC]        "no space left on device",
+[EPIPE]         "broken pipe",
+[ENAMETOOLONG]  "file name too long",
+[ENOSYS]        "function not implemented",
+[ENOTEMPTY]     "directory not empty",
+[ELOOP]         "too many levels of symbolic links",
+[ENOTSOCK]      "socket operation on non-socket",
+[EAFNOSUPPORT]  "address family not supported by protocol",
+[EADDRINUSE]    "address already in use",
+[EISCONN]       "transport endpoint is already connected",
+[ENOTCONN]      "transport endpoint is not connected",
+[ETIMEDOUT]     "connection timed out",
+[ECONNREFUSED]  "connection refused",
+};
+
+const char*
+strerror(int err)
+{
+  if(err < 0 || err >= (int)(sizeof(errstr)/sizeof(errstr[0])) || errstr[err] == 0)
+    return "unknown error";
+  return errstr[err];
+}
@@ -1,5 +1,11 @@
 struct stat;
 struct rtcdate;
+struct sigaction;
+struct rlimit;
+struct timespec;
+struct timeval;
+struct pollfd;
+struct sockaddr_un;
 
 // system calls
 int fork(void);
@@ -9,7 +15,7 @@ int pipe(int*);
 int write(int, const void*, int);
 int read(int, void*, int);
 int close(int);
-int kill(int);
+int kill(int, int);
 int exec(char*, char**);
 int open(const char*, int);
 int mknod(const char*, short, short);
@@ -23,6 +29,44 @@ int getpid(void);
 char* sbrk(int);
 int sleep(int);
 int uptime(void);
+void* mmap(void*, int, int, int, int, int);
+int munmap(void*, int);
+int clone(void(*)(void*), void*, void*, void*);
+int join(int, int*);
+int sigprocmask(int, const uint*, uint*);
+int sigreturn(void);
+int setpriority(int, int);
+int sched_setaffinity(int, unsigned long);
+int trace(unsigned long);
+int waitpid(int, int*, int);
+int setpgid(int, int);
+int getpgid(int);
+int setsid(void);
+int tcsetpgrp(int, int);
+int tcgetpgrp(int);
+int execve(char*, char**, char**);
+int getrlimit(int, struct rlimit*);
+int setrlimit(int, const struct rlimit*);
+int chmod(const char*, int);
+int chown(const char*, int, int);
+int setuid(int);
+int getuid(void);
+int sigalarm(int, void (*)());
+int clock_gettime(int, struct timespec*);
+int nanosleep(const struct timespec*, struct timespec*);
+int gettimeofday(struct timeval*, void*);
+int futex(int*, int, int, int);
+int fcntl(int, int, ...);
+int poll(struct pollfd*, int, int);
+int mkfifo(const char*, int);
+int socket(int, int, int);
+int bind(int, const struct sockaddr_un*, int);
+int listen(int, int);
+int accept(int, struct sockaddr_un*, int*);
+int connect(int, const struct sockaddr_un*, int);
+int socketpair(int, int, int, int*);
+int setgid(int);
+int getgid(void);
 
 // ulib.c
 int stat(const char*, struct stat*);
@@ -40,3 +84,5 @@ void free(void*);
 int atoi(const char*);
 int memcmp(const void *, const void *, uint);
 void *memcpy(void *, const void *, uint);
+int sigaction(int, const struct sigaction*, struct sigaction*);
+const char* strerror(int);
@@ -2,8 +2,19 @@
 #include "include/types.h"
 #include "include/stat.h"
 #include "user/user.h"
+#include "include/spinlock.h"
+#include "include/sleeplock.h"
 #include "include/fs.h"
+#include "include/file.h"
 #include "include/fcntl.h"
+#include "include/signal.h"
+#include "include/errno.h"
+#include "include/wait.h"
+#include "include/resource.h"
+#include "include/time.h"
+#include "include/futex.h"
+#include "include/poll.h"
+#include "include/socket.h"
 #include "include/syscall.h"
 #include "include/memlayout.h"
 #include "include/riscv.h"
@@ -799,7 +810,7 @@ killstatus(char *s)
       exit(0);
     }
     sleep(1);
-    kill(pid1);
+    kill(pid1, SIGKILL);
     wait(&xst);
     if(xst != -1) {
        printf("%s: status should be -1\n", s);
@@ -856,9 +867,9 @@ preempt(char *s)
   }
   close(pfds[0]);
   printf("kill... ");
-  kill(pid1);
-  kill(pid2);
-  kill(pid3);
+  kill(pid1, SIGKILL);
+  kill(pid2, SIGKILL);
+  kill(pid3, SIGKILL);
   printf("wait... ");
   wait(0);
   wait(0);
@@ -914,7 +925,7 @@ reparent(char *s)
     } else {
       int pid2 = fork();
       if(pid2 < 0){
-        kill(master_pid);
+        kill(master_pid, SIGKILL);
         exit(1);
       }
       exit(0);
@@ -2080,6 +2091,1868 @@ forktest(char *s)
   }
 }
 
+// test that pages shared by copy-on-write fork
+// are copied when either the parent or the child writes,
+// including writes made by the kernel (e.g. read()).
+char cowbuf[3*4096];
+
+void
+cowfork(char *s)
+{
+  int fds[2], pid, xstatus;
+
+  memset(cowbuf, 'a', sizeof(cowbuf));
+  if(pipe(fds) < 0){
+    printf("%s: pipe() failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(fds[1]);
+    memset(cowbuf, 'b', 4096);
+    if(read(fds[0], cowbuf + 4096, 1) != 1){
+      printf("%s: read failed\n", s);
+      exit(1);
+    }
+    if(cowbuf[0] != 'b' || cowbuf[4096] != 'c' || cowbuf[2*4096] != 'a'){
+      printf("%s: child sees wrong content\n", s);
+      exit(1);
+    }
+    exit(0);
+  }
+  close(fds[0]);
+  cowbuf[2*4096] = 'd';
+  if(write(fds[1], "c", 1) != 1){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  close(fds[1]);
+  wait(&xstatus);
+  if(xstatus != 0)
+    exit(xstatus);
+  if(cowbuf[0] != 'a' || cowbuf[4096] != 'a' || cowbuf[2*4096] != 'd'){
+    printf("%s: parent sees child's writes\n", s);
+    exit(1);
+  }
+}
+
+// test that sbrk() only reserves memory,
+// and pages are allocated when first touched,
+// by the user or by the kernel on behalf of the user.
+void
+lazyalloc(char *s)
+{
+  enum { BIG=512*1024*1024 };
+  int fds[2];
+  char *a, *oldbrk;
+
+  oldbrk = sbrk(0);
+  a = sbrk(BIG);
+  if(a == (char*)0xffffffffffffffffL){
+    printf("%s: sbrk(BIG) failed\n", s);
+    exit(1);
+  }
+  if(a[BIG/2] != 0 || a[BIG-1] != 0){
+    printf("%s: lazily allocated page not zeroed\n", s);
+    exit(1);
+  }
+  a[BIG-1] = 'x';
+  if(pipe(fds) < 0){
+    printf("%s: pipe() failed\n", s);
+    exit(1);
+  }
+  if(write(fds[1], a + BIG - 1, 1) != 1 || read(fds[0], a + 4096, 1) != 1){
+    printf("%s: pipe on lazily allocated memory failed\n", s);
+    exit(1);
+  }
+  if(a[4096] != 'x'){
+    printf("%s: read wrong content\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+  if(sbrk(-BIG) == (char*)0xffffffffffffffffL || sbrk(0) != oldbrk){
+    printf("%s: sbrk(-BIG) failed\n", s);
+    exit(1);
+  }
+}
+
+// test mmap() of a file with MAP_SHARED and MAP_PRIVATE,
+// and of anonymous memory.
+void
+mmaptest(char *s)
+{
+  enum { FSIZE=6000 };
+  int fd, i, pid, xstatus, fds[2];
+  char *p, *q;
+
+  unlink("mmapfile");
+  fd = open("mmapfile", O_CREATE|O_RDWR);
+  if(fd < 0){
+    printf("%s: create mmapfile failed\n", s);
+    exit(1);
+  }
+  memset(buf, 'A', FSIZE);
+  if(write(fd, buf, FSIZE) != FSIZE){
+    printf("%s: write mmapfile failed\n", s);
+    exit(1);
+  }
+
+  p = mmap(0, FSIZE, PROT_READ|PROT_WRITE, MAP_SHARED, fd, 0);
+  if(p == MAP_FAILED){
+    printf("%s: mmap shared failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < 2*PGSIZE; i++){
+    if(p[i] != (i < FSIZE ? 'A' : 0)){
+      printf("%s: mmap wrong content at %d\n", s, i);
+      exit(1);
+    }
+  }
+  p[0] = 'B';
+  p[PGSIZE] = 'C';
+
+  // the child shares the same pages
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    p[1] = 'D';
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus != 0)
+    exit(xstatus);
+  if(p[1] != 'D'){
+    printf("%s: shared mapping not shared with child\n", s);
+    exit(1);
+  }
+
+  q = mmap(0, PGSIZE, PROT_READ|PROT_WRITE, MAP_PRIVATE, fd, 0);
+  if(q == MAP_FAILED){
+    printf("%s: mmap private failed\n", s);
+    exit(1);
+  }
+  if(q[0] != 'B'){
+    printf("%s: private mapping wrong content\n", s);
+    exit(1);
+  }
+  q[2] = 'E';
+  if(munmap(q, PGSIZE) < 0 || munmap(p, FSIZE) < 0){
+    printf("%s: munmap failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // dirty pages of the shared mapping are written back
+  fd = open("mmapfile", O_RDONLY);
+  if(fd < 0 || read(fd, buf, sizeof(buf)) != FSIZE){
+    printf("%s: mmapfile size changed\n", s);
+    exit(1);
+  }
+  if(buf[0] != 'B' || buf[1] != 'D' || buf[2] != 'A' || buf[PGSIZE] != 'C'){
+    printf("%s: mmapfile wrong content after munmap\n", s);
+    exit(1);
+  }
+  // not writable for a read-only file
+  if(mmap(0, PGSIZE, PROT_READ|PROT_WRITE, MAP_SHARED, fd, 0) != MAP_FAILED){
+    printf("%s: mmap read-only file as writable\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // pages written only by the kernel, e.g. by read(), are written back too
+  fd = open("mmapfile", O_RDWR);
+  if(fd < 0 || pipe(fds) < 0){
+    printf("%s: open mmapfile or pipe failed\n", s);
+    exit(1);
+  }
+  p = mmap(0, PGSIZE, PROT_READ|PROT_WRITE, MAP_SHARED, fd, 0);
+  if(p == MAP_FAILED || p[8] != 'A'){
+    printf("%s: mmap shared again failed\n", s);
+    exit(1);
+  }
+  if(write(fds[1], "pipe", 4) != 4 || read(fds[0], p + 8, 4) != 4){
+    printf("%s: read into mapping failed\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+  if(munmap(p, PGSIZE) < 0){
+    printf("%s: munmap failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  fd = open("mmapfile", O_RDONLY);
+  if(fd < 0 || read(fd, buf, sizeof(buf)) != FSIZE || memcmp(buf + 8, "pipe", 4) != 0){
+    printf("%s: read into mapping not written back\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("mmapfile");
+
+  p = mmap(0, 2*PGSIZE, PROT_READ|PROT_WRITE, MAP_PRIVATE|MAP_ANONYMOUS, -1, 0);
+  if(p == MAP_FAILED){
+    printf("%s: mmap anonymous failed\n", s);
+    exit(1);
+  }
+  if(p[0] != 0 || p[2*PGSIZE-1] != 0){
+    printf("%s: anonymous mapping not zeroed\n", s);
+    exit(1);
+  }
+  p[0] = 'F';
+  if(munmap(p, PGSIZE) < 0){
+    printf("%s: munmap anonymous failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    p[PGSIZE] = 'G';
+    // should be killed here
+    p[0] = 'G';
+    exit(1);
+  }
+  wait(&xstatus);
+  if(xstatus != -1){
+    printf("%s: access to unmapped page not killed\n", s);
+    exit(1);
+  }
+}
+
+// shared by threadtest and its threads
+volatile int threadout[4];
+char *volatile threadbrk;
+
+void
+threadfunc(void *arg)
+{
+  int i = (int)(uint64)arg;
+  uint64 tp;
+
+  asm volatile("mv %0, tp" : "=r" (tp));
+  threadout[i] = (tp == 100 + i) ? i * 2 : -1;
+  if(i == 0)
+    threadbrk = sbrk(PGSIZE);
+  exit(i + 1);
+}
+
+void
+threadspin(void *arg)
+{
+  for(;;)
+    ;
+}
+
+// test that clone() creates threads sharing the address space,
+// and join() collects their exit status.
+void
+threadtest(char *s)
+{
+  enum { NTHREAD=4, STKSIZE=PGSIZE };
+  char *stacks;
+  int tids[NTHREAD];
+  int i, pid, xstatus;
+
+  stacks = sbrk(NTHREAD*STKSIZE + 16);
+  if(stacks == (char*)0xffffffffffffffffL){
+    printf("%s: sbrk failed\n", s);
+    exit(1);
+  }
+  stacks = (char*)(((uint64)stacks + 15) & ~15L);
+
+  for(i = 0; i < NTHREAD; i++){
+    threadout[i] = 0;
+    tids[i] = clone(threadfunc, (void*)(uint64)i, stacks + (i+1)*STKSIZE, (void*)(uint64)(100+i));
+    if(tids[i] < 0){
+      printf("%s: clone failed\n", s);
+      exit(1);
+    }
+  }
+  for(i = 0; i < NTHREAD; i++){
+    if(join(tids[i], &xstatus) != tids[i] || xstatus != i + 1){
+      printf("%s: join thread %d failed\n", s, i);
+      exit(1);
+    }
+    if(threadout[i] != i * 2){
+      printf("%s: thread %d wrote wrong value\n", s, i);
+      exit(1);
+    }
+  }
+  // memory grown by a thread is visible to the others
+  if(threadbrk == (char*)0xffffffffffffffffL || threadbrk[0] != 0){
+    printf("%s: sbrk in thread failed\n", s);
+    exit(1);
+  }
+  if(join(tids[0], 0) >= 0){
+    printf("%s: joined a thread twice\n", s);
+    exit(1);
+  }
+  if(wait(0) >= 0){
+    printf("%s: wait returned a thread\n", s);
+    exit(1);
+  }
+
+  // threads are killed when the process exits
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    if(clone(threadspin, 0, stacks + STKSIZE, 0) < 0)
+      exit(1);
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: clone in child failed\n", s);
+    exit(1);
+  }
+}
+
+// shared by threadfilestest and its thread
+volatile int threadfd;
+volatile int threadclosefd;
+
+void
+threadfiles(void *arg)
+{
+  threadfd = open("thrfile", O_CREATE|O_RDWR);
+  close(threadclosefd);
+  chdir("thrdir");
+  exit(0);
+}
+
+// do threads share the file descriptors and cwd,
+// so that an fd opened or closed, or a chdir, in one is seen by the others?
+void
+threadfilestest(char *s)
+{
+  char *stack;
+  int tid, xstatus, fd;
+  struct stat st;
+
+  unlink("thrfile");
+  unlink("thrdir");
+  if(mkdir("thrdir") < 0){
+    printf("%s: mkdir failed\n", s);
+    exit(1);
+  }
+  threadclosefd = open(".", O_RDONLY);
+  stack = sbrk(PGSIZE + 16);
+  if(threadclosefd < 0 || stack == (char*)0xffffffffffffffffL){
+    printf("%s: open or sbrk failed\n", s);
+    exit(1);
+  }
+  stack = (char*)(((uint64)stack + 15) & ~15L);
+
+  tid = clone(threadfiles, 0, stack + PGSIZE, 0);
+  if(tid < 0 || join(tid, &xstatus) != tid || xstatus != 0){
+    printf("%s: clone or join failed\n", s);
+    exit(1);
+  }
+  if(threadfd < 0 || fstat(threadfd, &st) < 0 || write(threadfd, "x", 1) != 1){
+    printf("%s: fd opened by the thread not shared\n", s);
+    exit(1);
+  }
+  if(fstat(threadclosefd, &st) != -1 || errno != EBADF){
+    printf("%s: fd closed by the thread still open\n", s);
+    exit(1);
+  }
+  if(open("thrfile", O_RDONLY) >= 0 || (fd = open("../thrfile", O_RDONLY)) < 0){
+    printf("%s: chdir by the thread not shared\n", s);
+    exit(1);
+  }
+  close(fd);
+  close(threadfd);
+  if(chdir("..") < 0 || unlink("thrfile") < 0 || unlink("thrdir") < 0){
+    printf("%s: cleanup failed\n", s);
+    exit(1);
+  }
+}
+
+volatile int threadexecerr;
+
+void
+threadexec(void *arg)
+{
+  char *echoargv[] = { "echo", "thread", 0 };
+
+  // not the main thread, so exec is refused with EBUSY
+  threadexecerr = 0;
+  if(exec("echo", echoargv) != -1)
+    exit(1);
+  threadexecerr = errno;
+  exit(0);
+}
+
+// exec is allowed only in the main thread, and fails with EBUSY in the others.
+// when the main thread execs, the other threads are killed,
+// rather than left in the old image.
+void
+threadexectest(char *s)
+{
+  char *stacks;
+  char *echoargv[] = { "echo", "OK", 0 };
+  int tid, pid, xstatus;
+
+  stacks = sbrk(2*PGSIZE + 16);
+  if(stacks == (char*)0xffffffffffffffffL){
+    printf("%s: sbrk failed\n", s);
+    exit(1);
+  }
+  stacks = (char*)(((uint64)stacks + 15) & ~15L);
+
+  unlink("threxecout");
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    tid = clone(threadexec, 0, stacks + PGSIZE, 0);
+    if(tid < 0 || join(tid, &xstatus) != tid || xstatus != 0)
+      exit(2);
+    if(threadexecerr != EBUSY)
+      exit(3);
+    if(clone(threadspin, 0, stacks + 2*PGSIZE, 0) < 0)
+      exit(4);
+    close(1);
+    if(open("threxecout", O_CREATE|O_WRONLY) != 1)
+      exit(5);
+    exec("echo", echoargv);
+    exit(6);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: exec with threads failed, status %d\n", s, xstatus);
+    exit(1);
+  }
+  if(unlink("threxecout") < 0){
+    printf("%s: echo did not run\n", s);
+    exit(1);
+  }
+}
+
+volatile int sigcount;
+volatile int siglast;
+
+void
+sighandler(int sig)
+{
+  sigcount++;
+  siglast = sig;
+}
+
+// test signal handlers, blocked and ignored signals,
+// and the default actions to stop, continue and terminate.
+void
+sigtest(char *s)
+{
+  struct sigaction sa, old;
+  uint set;
+  int pid, xstatus, c1, c2;
+  volatile int *counter;
+
+  sigcount = 0;
+  memset(&sa, 0, sizeof(sa));
+  sa.sa_handler = sighandler;
+  if(sigaction(SIGUSR1, &sa, &old) < 0 || old.sa_handler != SIG_DFL){
+    printf("%s: sigaction failed\n", s);
+    exit(1);
+  }
+  // delivered when returning from kill
+  kill(getpid(), SIGUSR1);
+  if(sigcount != 1 || siglast != SIGUSR1){
+    printf("%s: handler not called\n", s);
+    exit(1);
+  }
+
+  // a blocked signal stays pending until unblocked
+  set = sigmask(SIGUSR1);
+  sigprocmask(SIG_BLOCK, &set, 0);
+  kill(getpid(), SIGUSR1);
+  if(sigcount != 1){
+    printf("%s: blocked signal delivered\n", s);
+    exit(1);
+  }
+  sigprocmask(SIG_UNBLOCK, &set, &set);
+  if(sigcount != 2 || set != sigmask(SIGUSR1)){
+    printf("%s: unblocked signal not delivered\n", s);
+    exit(1);
+  }
+
+  sa.sa_handler = SIG_IGN;
+  if(sigaction(SIGUSR2, &sa, 0) < 0 || kill(getpid(), SIGUSR2) < 0){
+    printf("%s: ignore signal failed\n", s);
+    exit(1);
+  }
+  if(sigaction(SIGKILL, &sa, 0) >= 0){
+    printf("%s: SIGKILL ignored\n", s);
+    exit(1);
+  }
+
+  counter = mmap(0, PGSIZE, PROT_READ|PROT_WRITE, MAP_SHARED|MAP_ANONYMOUS, -1, 0);
+  if(counter == MAP_FAILED){
+    printf("%s: mmap failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    for(;;)
+      (*counter)++;
+  }
+  sleep(1);
+  kill(pid, SIGSTOP);
+  sleep(2);
+  c1 = *counter;
+  sleep(2);
+  c2 = *counter;
+  if(c1 != c2){
+    printf("%s: stopped child still running\n", s);
+    exit(1);
+  }
+  kill(pid, SIGCONT);
+  sleep(2);
+  if(*counter == c2){
+    printf("%s: continued child not running\n", s);
+    exit(1);
+  }
+  kill(pid, SIGTERM);
+  wait(&xstatus);
+  if(xstatus != -1){
+    printf("%s: child not terminated\n", s);
+    exit(1);
+  }
+  munmap((void*)counter, PGSIZE);
+}
+
+// does a caught signal interrupt a read blocked on an empty pipe?
+void
+sigintrtest(char *s)
+{
+  struct sigaction sa;
+  int pid, xstatus, fds[2], i;
+  char c;
+
+  // installed before fork, so the child never dies of SIGUSR1
+  sigcount = 0;
+  memset(&sa, 0, sizeof(sa));
+  sa.sa_handler = sighandler;
+  if(sigaction(SIGUSR1, &sa, 0) < 0 || pipe(fds) < 0){
+    printf("%s: sigaction or pipe failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    // the write end stays open, so the pipe is empty but not closed
+    if(read(fds[0], &c, 1) != -1 || errno != EINTR || sigcount == 0)
+      exit(1);
+    exit(0);
+  }
+
+  // signal again in case the first one came before the read blocked
+  for(i = 0; i < 50; i++){
+    kill(pid, SIGUSR1);
+    sleep(1);
+    if(waitpid(pid, &xstatus, WNOHANG) == pid)
+      break;
+  }
+  if(i == 50){
+    printf("%s: read not interrupted\n", s);
+    kill(pid, SIGKILL);
+    wait(0);
+    exit(1);
+  }
+  if(!WIFEXITED(xstatus) || WEXITSTATUS(xstatus) != 0){
+    printf("%s: read did not fail with EINTR\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+volatile int alarmcount;
+volatile int inalarm;
+volatile int alarmnested;
+
+void
+alarmhandler(void)
+{
+  int start;
+
+  if(inalarm)
+    alarmnested = 1;
+  inalarm = 1;
+  alarmcount++;
+  // stay for a few ticks, the handler should not be re-entered
+  if(alarmcount == 1){
+    start = uptime();
+    while(uptime() - start < 3)
+      ;
+  }
+  inalarm = 0;
+  sigreturn();
+}
+
+// is the alarm handler called periodically by ticks of cpu time,
+// and does the interrupted code resume untouched?
+void
+alarmtest(char *s)
+{
+  int i, start, count;
+  uint64 a, b;
+
+  alarmcount = alarmnested = 0;
+  a = b = 0;
+  start = uptime();
+  sigalarm(2, alarmhandler);
+  for(i = 0; alarmcount < 3; i++){
+    a += i;
+    b += 2*i;
+    if(uptime() - start > 500){
+      printf("%s: alarm handler not called\n", s);
+      exit(1);
+    }
+  }
+  sigalarm(0, 0);
+  if(alarmnested){
+    printf("%s: alarm handler re-entered\n", s);
+    exit(1);
+  }
+  if(b != 2*a){
+    printf("%s: registers not restored\n", s);
+    exit(1);
+  }
+
+  count = alarmcount;
+  start = uptime();
+  while(uptime() - start < 5)
+    ;
+  if(alarmcount != count){
+    printf("%s: alarm not stopped\n", s);
+    exit(1);
+  }
+}
+
+long
+elapsedns(struct timespec *a, struct timespec *b)
+{
+  return (b->tv_sec - a->tv_sec) * NSEC_PER_SEC + (b->tv_nsec - a->tv_nsec);
+}
+
+// is the monotonic clock finer than ticks,
+// and does nanosleep wake up between two ticks?
+void
+clocktest(char *s)
+{
+  struct timespec t0, t1, req;
+  long ns;
+
+  if(clock_gettime(CLOCK_MONOTONIC, &t0) < 0 || clock_gettime(CLOCK_MONOTONIC, &t1) < 0){
+    printf("%s: clock_gettime failed\n", s);
+    exit(1);
+  }
+  if(elapsedns(&t0, &t1) < 0 || t1.tv_nsec >= NSEC_PER_SEC){
+    printf("%s: clock went backwards\n", s);
+    exit(1);
+  }
+  if(clock_gettime(-1, &t0) != -1 || errno != EINVAL){
+    printf("%s: bad clock accepted\n", s);
+    exit(1);
+  }
+
+  req.tv_sec = 0;
+  req.tv_nsec = NSEC_PER_SEC;
+  if(nanosleep(&req, 0) != -1 || errno != EINVAL){
+    printf("%s: bad nanoseconds accepted\n", s);
+    exit(1);
+  }
+
+  // a quarter of a tick
+  req.tv_nsec = 25000000;
+  clock_gettime(CLOCK_MONOTONIC, &t0);
+  if(nanosleep(&req, 0) < 0){
+    printf("%s: nanosleep failed\n", s);
+    exit(1);
+  }
+  clock_gettime(CLOCK_MONOTONIC, &t1);
+  ns = elapsedns(&t0, &t1);
+  if(ns < req.tv_nsec || ns > NSEC_PER_SEC){
+    printf("%s: slept %d ns for %d ns\n", s, (int)ns, (int)req.tv_nsec);
+    exit(1);
+  }
+}
+
+// does the wall-clock time from the rtc agree among
+// gettimeofday, CLOCK_REALTIME, the rtc device and the file times?
+void
+rtctest(char *s)
+{
+  struct timeval tv;
+  struct timespec ts;
+  struct stat st;
+  uint64 ns;
+  int fd;
+
+  if(gettimeofday(&tv, 0) < 0 || clock_gettime(CLOCK_REALTIME, &ts) < 0){
+    printf("%s: get time failed\n", s);
+    exit(1);
+  }
+  // later than 2020-01-01
+  if(tv.tv_sec < 1577836800 || ts.tv_sec < tv.tv_sec || ts.tv_sec > tv.tv_sec + 1){
+    printf("%s: wrong wall-clock time\n", s);
+    exit(1);
+  }
+  if(gettimeofday(&tv, &tv) != -1 || errno != EINVAL){
+    printf("%s: timezone accepted\n", s);
+    exit(1);
+  }
+
+  fd = open("rtc", O_RDONLY);
+  if(fd < 0 || read(fd, &ns, sizeof(ns)) != sizeof(ns) || ns / NSEC_PER_SEC < ts.tv_sec){
+    printf("%s: read rtc device failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  fd = open("rtc.out", O_CREATE|O_WRONLY);
+  if(fd < 0 || write(fd, "x", 1) != 1 || fstat(fd, &st) < 0){
+    printf("%s: write file failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("rtc.out");
+  if(st.mtime < ts.tv_sec || st.mtime > ts.tv_sec + 10){
+    printf("%s: wrong modification time\n", s);
+    exit(1);
+  }
+}
+
+// does a futex wake up a waiter in another process sharing the memory,
+// and does the waiting time out?
+void
+futextest(char *s)
+{
+  int *word, pid, xstatus, n;
+
+  word = mmap(0, PGSIZE, PROT_READ|PROT_WRITE, MAP_SHARED|MAP_ANONYMOUS, -1, 0);
+  if(word == MAP_FAILED){
+    printf("%s: mmap failed\n", s);
+    exit(1);
+  }
+  *word = 0;
+  if(futex(word, FUTEX_WAIT, 1, 0) != -1 || errno != EAGAIN){
+    printf("%s: waited on a changed word\n", s);
+    exit(1);
+  }
+  if(futex(word, FUTEX_WAIT, 0, 2) != -1 || errno != ETIMEDOUT){
+    printf("%s: wait not timed out\n", s);
+    exit(1);
+  }
+  if(futex((int*)((char*)word + 1), FUTEX_WAKE, 1, 0) != -1 || errno != EINVAL){
+    printf("%s: unaligned word accepted\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    while(*word == 0)
+      futex(word, FUTEX_WAIT, 0, 0);
+    exit(*word == 1 ? 0 : 1);
+  }
+  sleep(2);
+  *word = 1;
+  n = futex(word, FUTEX_WAKE, 1, 0);
+  wait(&xstatus);
+  if(n < 0 || n > 1 || xstatus != 0){
+    printf("%s: waiter not woken up\n", s);
+    exit(1);
+  }
+  munmap(word, PGSIZE);
+}
+
+// do O_NONBLOCK pipes return EAGAIN instead of blocking,
+// and are F_DUPFD and FD_CLOEXEC honored?
+void
+fcntltest(char *s)
+{
+  int fds[2], fd, pid, xstatus, n, total;
+  char buf[128];
+  char *echoargv[] = { "echo", "cloexec", 0 };
+
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  if(fcntl(fds[0], F_GETFL) != O_RDONLY || fcntl(fds[1], F_GETFL) != O_WRONLY){
+    printf("%s: wrong flags of pipe\n", s);
+    exit(1);
+  }
+  if(fcntl(fds[0], F_SETFL, O_NONBLOCK) != 0 || fcntl(fds[1], F_SETFL, O_NONBLOCK) != 0){
+    printf("%s: F_SETFL failed\n", s);
+    exit(1);
+  }
+  if(read(fds[0], buf, 1) != -1 || errno != EAGAIN){
+    printf("%s: read of empty pipe not EAGAIN\n", s);
+    exit(1);
+  }
+
+  // fill up the pipe until it would block
+  total = 0;
+  for(;;){
+    n = write(fds[1], buf, sizeof(buf));
+    if(n < 0)
+      break;
+    total += n;
+    if(total > 64*1024){
+      printf("%s: write to a full pipe not EAGAIN\n", s);
+      exit(1);
+    }
+  }
+  if(errno != EAGAIN || total == 0){
+    printf("%s: wrote %d bytes, errno %d\n", s, total, errno);
+    exit(1);
+  }
+
+  // the duplicated fd shares the flags
+  fd = fcntl(fds[0], F_DUPFD, 10);
+  if(fd < 10){
+    printf("%s: F_DUPFD returned %d\n", s, fd);
+    exit(1);
+  }
+  if(fcntl(fd, F_GETFL) != (O_RDONLY|O_NONBLOCK)){
+    printf("%s: flags not shared by F_DUPFD\n", s);
+    exit(1);
+  }
+  if(read(fd, buf, sizeof(buf)) != sizeof(buf)){
+    printf("%s: read from duplicated fd failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  close(fds[0]);
+  close(fds[1]);
+
+  if(fcntl(NOFILE, F_GETFD) != -1 || errno != EBADF){
+    printf("%s: fcntl on a bad fd\n", s);
+    exit(1);
+  }
+
+  // echo writes nothing to a stdout closed by exec
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(fds[0]);
+    close(1);
+    if(dup(fds[1]) != 1 || fcntl(1, F_GETFD) != 0){
+      printf("%s: dup failed\n", s);
+      exit(1);
+    }
+    close(fds[1]);
+    fcntl(1, F_SETFD, FD_CLOEXEC);
+    if(fcntl(1, F_GETFD) != FD_CLOEXEC){
+      printf("%s: F_SETFD failed\n", s);
+      exit(1);
+    }
+    exec("echo", echoargv);
+    printf("%s: exec echo failed\n", s);
+    exit(1);
+  }
+  close(fds[1]);
+  n = read(fds[0], buf, sizeof(buf));
+  close(fds[0]);
+  wait(&xstatus);
+  if(xstatus != 0 || n != 0){
+    printf("%s: FD_CLOEXEC fd not closed by exec\n", s);
+    exit(1);
+  }
+}
+
+// does poll wait on several pipes at once, wake up when one of them
+// becomes ready, and time out?
+void
+polltest(char *s)
+{
+  int a[2], b[2], fd, pid, xstatus, n, start;
+  struct pollfd pfds[4];
+  char c;
+
+  if(pipe(a) != 0 || pipe(b) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  pfds[0].fd = a[0];
+  pfds[0].events = POLLIN;
+  pfds[1].fd = b[0];
+  pfds[1].events = POLLIN;
+  if(poll(pfds, 2, 0) != 0){
+    printf("%s: empty pipes ready\n", s);
+    exit(1);
+  }
+  start = uptime();
+  if(poll(pfds, 2, 2) != 0 || uptime() - start < 1){
+    printf("%s: poll not timed out\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    sleep(2);
+    write(b[1], "x", 1);
+    exit(0);
+  }
+  n = poll(pfds, 2, -1);
+  if(n != 1 || pfds[0].revents != 0 || pfds[1].revents != POLLIN){
+    printf("%s: poll returned %d, revents %x %x\n", s, n, pfds[0].revents, pfds[1].revents);
+    exit(1);
+  }
+  if(read(b[0], &c, 1) != 1 || c != 'x'){
+    printf("%s: read failed\n", s);
+    exit(1);
+  }
+  wait(&xstatus);
+
+  // a closed writer hangs up the reader, and a bad fd is reported
+  close(a[1]);
+  fd = open("README.md", O_RDONLY);
+  pfds[1].fd = b[1];
+  pfds[1].events = POLLOUT;
+  pfds[2].fd = fd;
+  pfds[2].events = POLLIN|POLLOUT;
+  pfds[3].fd = NOFILE - 1;
+  pfds[3].events = POLLIN;
+  if(fd < 0 || fd == NOFILE - 1){
+    printf("%s: open README.md failed\n", s);
+    exit(1);
+  }
+  n = poll(pfds, 4, -1);
+  if(n != 4 || pfds[0].revents != POLLHUP || pfds[1].revents != POLLOUT ||
+     pfds[2].revents != POLLIN || pfds[3].revents != POLLNVAL){
+    printf("%s: poll returned %d, revents %x %x %x %x\n", s, n,
+           pfds[0].revents, pfds[1].revents, pfds[2].revents, pfds[3].revents);
+    exit(1);
+  }
+  pfds[3].fd = -1;
+  if(poll(pfds + 3, 1, 0) != 0){
+    printf("%s: negative fd not ignored\n", s);
+    exit(1);
+  }
+  close(fd);
+  close(a[0]);
+  close(b[0]);
+  close(b[1]);
+}
+
+// can two processes talk through a FIFO opened by path,
+// with each open waiting for the other end?
+void
+fifotest(char *s)
+{
+  int fd, pid, xstatus, n, m;
+  struct stat st;
+  char buf[16];
+
+  unlink("fifo0");
+  if(mkfifo("fifo0", 0666) != 0){
+    printf("%s: mkfifo failed\n", s);
+    exit(1);
+  }
+  if(mkfifo("fifo0", 0666) != -1 || errno != EEXIST){
+    printf("%s: mkfifo on an existing path\n", s);
+    exit(1);
+  }
+  if(stat("fifo0", &st) != 0 || st.type != T_FIFO || st.mode != 0666){
+    printf("%s: wrong stat of fifo\n", s);
+    exit(1);
+  }
+  if(open("fifo0", O_WRONLY|O_NONBLOCK) != -1 || errno != ENXIO){
+    printf("%s: non-blocking open for writing without a reader\n", s);
+    exit(1);
+  }
+  fd = open("fifo0", O_RDONLY|O_NONBLOCK);
+  if(fd < 0){
+    printf("%s: non-blocking open for reading failed\n", s);
+    exit(1);
+  }
+  if(read(fd, buf, sizeof(buf)) != 0){
+    printf("%s: read without a writer not at end of file\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    // waits for the reader
+    fd = open("fifo0", O_WRONLY);
+    if(fd < 0 || write(fd, "hello", 5) != 5)
+      exit(1);
+    exit(0);
+  }
+  sleep(1);
+  fd = open("fifo0", O_RDONLY);
+  if(fd < 0){
+    printf("%s: open for reading failed\n", s);
+    exit(1);
+  }
+  n = 0;
+  while(n < 5){
+    m = read(fd, buf + n, sizeof(buf) - n);
+    if(m <= 0)
+      break;
+    n += m;
+  }
+  if(n != 5 || memcmp(buf, "hello", 5) != 0){
+    printf("%s: read %d bytes through fifo\n", s, n);
+    exit(1);
+  }
+  wait(&xstatus);
+  if(xstatus != 0 || read(fd, buf, sizeof(buf)) != 0){
+    printf("%s: writer failed or no end of file\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("fifo0");
+
+  // mknod with no device makes a FIFO as well
+  if(mknod("fifo1", FIFO, 0) != 0){
+    printf("%s: mknod of a fifo failed\n", s);
+    exit(1);
+  }
+  if(mknod("fifo1", FIFO, 0) != -1 || errno != EEXIST){
+    printf("%s: mknod of a fifo on an existing path\n", s);
+    exit(1);
+  }
+  if(stat("fifo1", &st) != 0 || st.type != T_FIFO){
+    printf("%s: wrong stat of fifo from mknod\n", s);
+    exit(1);
+  }
+  if(open("fifo1", O_WRONLY|O_NONBLOCK) != -1 || errno != ENXIO){
+    printf("%s: fifo from mknod opened for writing without a reader\n", s);
+    exit(1);
+  }
+  unlink("fifo1");
+}
+
+// do sockets carry bytes both ways, between a socketpair
+// and between a client and a server bound to a path?
+void
+sockettest(char *s)
+{
+  int sv[2], lfd, fd, pid, xstatus;
+  struct sockaddr_un addr;
+  char buf[8];
+
+  if(socket(AF_UNIX + 1, SOCK_STREAM, 0) != -1 || errno != EAFNOSUPPORT){
+    printf("%s: unsupported domain accepted\n", s);
+    exit(1);
+  }
+
+  if(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) != 0){
+    printf("%s: socketpair failed\n", s);
+    exit(1);
+  }
+  if(write(sv[0], "ab", 2) != 2 || write(sv[1], "cd", 2) != 2 ||
+     read(sv[1], buf, sizeof(buf)) != 2 || memcmp(buf, "ab", 2) != 0 ||
+     read(sv[0], buf, sizeof(buf)) != 2 || memcmp(buf, "cd", 2) != 0){
+    printf("%s: socketpair not both ways\n", s);
+    exit(1);
+  }
+  close(sv[0]);
+  if(read(sv[1], buf, sizeof(buf)) != 0){
+    printf("%s: no end of file after the peer closed\n", s);
+    exit(1);
+  }
+  close(sv[1]);
+
+  unlink("sock0");
+  addr.sun_family = AF_UNIX;
+  strcpy(addr.sun_path, "sock0");
+  lfd = socket(AF_UNIX, SOCK_STREAM, 0);
+  fd = socket(AF_UNIX, SOCK_STREAM, 0);
+  if(lfd < 0 || fd < 0 || bind(lfd, &addr, sizeof(addr)) != 0){
+    printf("%s: socket or bind failed\n", s);
+    exit(1);
+  }
+  if(bind(fd, &addr, sizeof(addr)) != -1 || errno != EADDRINUSE){
+    printf("%s: bound twice to a path\n", s);
+    exit(1);
+  }
+  if(open("sock0", O_RDWR) != -1 || errno != ENXIO){
+    printf("%s: opened a socket\n", s);
+    exit(1);
+  }
+  if(connect(fd, &addr, sizeof(addr)) != -1 || errno != ECONNREFUSED){
+    printf("%s: connected before listen\n", s);
+    exit(1);
+  }
+  if(read(fd, buf, 1) != -1 || errno != ENOTCONN){
+    printf("%s: read from an unconnected socket\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(listen(lfd, SOMAXCONN) != 0){
+    printf("%s: listen failed\n", s);
+    exit(1);
+  }
+  fcntl(lfd, F_SETFL, O_NONBLOCK);
+  if(accept(lfd, 0, 0) != -1 || errno != EAGAIN){
+    printf("%s: accepted without a client\n", s);
+    exit(1);
+  }
+  fcntl(lfd, F_SETFL, 0);
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(lfd);
+    fd = socket(AF_UNIX, SOCK_STREAM, 0);
+    if(fd < 0 || connect(fd, &addr, sizeof(addr)) != 0)
+      exit(1);
+    if(write(fd, "ping", 4) != 4 || read(fd, buf, sizeof(buf)) != 4 || memcmp(buf, "pong", 4) != 0)
+      exit(2);
+    exit(0);
+  }
+  // a bad address does not take away the connection
+  sleep(1);
+  if(accept(lfd, (struct sockaddr_un*)0xeaeb0b5b00002f5eL, 0) != -1 || errno != EFAULT){
+    printf("%s: accept to a bad address\n", s);
+    exit(1);
+  }
+  fd = accept(lfd, 0, 0);
+  if(fd < 0){
+    printf("%s: accept failed\n", s);
+    exit(1);
+  }
+  if(read(fd, buf, sizeof(buf)) != 4 || memcmp(buf, "ping", 4) != 0 || write(fd, "pong", 4) != 4){
+    printf("%s: server read or write failed\n", s);
+    exit(1);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: client failed with %d\n", s, xstatus);
+    exit(1);
+  }
+  close(fd);
+  close(lfd);
+  unlink("sock0");
+}
+
+// test the range of nice values accepted by setpriority().
+void
+nicetest(char *s)
+{
+  int pid, xstatus;
+
+  if(setpriority(0, 20) >= 0 || setpriority(0, -21) >= 0){
+    printf("%s: out of range nice value accepted\n", s);
+    exit(1);
+  }
+  if(setpriority(0, 19) < 0 || setpriority(getpid(), -20) < 0){
+    printf("%s: setpriority failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    for(;;)
+      ;
+  }
+  // a niced cpu hog does not starve the parent
+  if(setpriority(pid, 19) < 0){
+    printf("%s: setpriority of child failed\n", s);
+    exit(1);
+  }
+  sleep(1);
+  kill(pid, SIGKILL);
+  wait(&xstatus);
+  if(setpriority(pid, 0) >= 0){
+    printf("%s: setpriority of a reaped child\n", s);
+    exit(1);
+  }
+  setpriority(0, 0);
+}
+
+// failed system calls should tell why they failed.
+void
+errnotest(char *s)
+{
+  int fd, fds[2];
+  char buf[1];
+
+#define EXPECT(call, err) \
+  do { \
+    errno = 0; \
+    if((call) != -1 || errno != (err)){ \
+      printf("%s: %s got errno %d (%s), not %d\n", s, #call, errno, strerror(errno), err); \
+      exit(1); \
+    } \
+  } while(0)
+
+  EXPECT(open("errno.nosuchfile", O_RDONLY), ENOENT);
+  EXPECT(open("README.md/x", O_RDONLY), ENOTDIR);
+  EXPECT(open(".", O_WRONLY), EISDIR);
+  EXPECT(close(NOFILE), EBADF);
+  EXPECT(read(-1, buf, 1), EBADF);
+  EXPECT(mkdir("."), EEXIST);
+  EXPECT(unlink("."), EINVAL);
+  EXPECT(chdir("README.md"), ENOTDIR);
+  EXPECT(kill(-1, SIGKILL), EINVAL);
+  EXPECT(kill(1000000, SIGKILL), ESRCH);
+  EXPECT(wait(0), ECHILD);
+  EXPECT(sbrk(-0x7fffffff) == (char*)-1 ? -1 : 0, EINVAL);
+
+  fd = open("README.md", O_RDONLY);
+  if(fd < 0){
+    printf("%s: open README.md failed\n", s);
+    exit(1);
+  }
+  EXPECT(write(fd, "x", 1), EBADF);
+  close(fd);
+
+  if(pipe(fds) < 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  EXPECT(write(fds[1], "x", 1), EPIPE);
+  close(fds[1]);
+#undef EXPECT
+}
+
+// pin children to each hart in turn, they should all get to run.
+void
+affinitytest(char *s)
+{
+  int i, pid, xstatus;
+
+  if(sched_setaffinity(0, 0) >= 0){
+    printf("%s: empty cpu mask accepted\n", s);
+    exit(1);
+  }
+  if(sched_setaffinity(0, 1UL << 62) >= 0){
+    printf("%s: mask of nonexistent hart accepted\n", s);
+    exit(1);
+  }
+  for(i = 0; i < 8; i++){
+    pid = fork();
+    if(pid < 0){
+      printf("%s: fork failed\n", s);
+      exit(1);
+    }
+    if(pid == 0){
+      // harts beyond the actual ones are ignored
+      if(sched_setaffinity(0, 1UL << i) < 0 && sched_setaffinity(0, 1) < 0)
+        exit(1);
+      for(int j = 0; j < 100000; j++)
+        ;
+      exit(0);
+    }
+    wait(&xstatus);
+    if(xstatus != 0){
+      printf("%s: pinned child failed\n", s);
+      exit(1);
+    }
+  }
+  if(sched_setaffinity(0, ~0UL) < 0){
+    printf("%s: cannot restore the affinity\n", s);
+    exit(1);
+  }
+}
+
+// waitpid should wait for the given child,
+// and tell whether it exited, was killed or stopped.
+void
+waitpidtest(char *s)
+{
+  int pid1, pid2, status;
+
+  pid1 = fork();
+  if(pid1 < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid1 == 0){
+    sleep(5);
+    exit(3);
+  }
+  pid2 = fork();
+  if(pid2 < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid2 == 0){
+    for(;;)
+      sleep(1);
+  }
+
+  if(waitpid(pid2, &status, WNOHANG) != 0){
+    printf("%s: WNOHANG did not return 0\n", s);
+    exit(1);
+  }
+  if(waitpid(pid1, &status, 0) != pid1 || !WIFEXITED(status) || WEXITSTATUS(status) != 3){
+    printf("%s: wrong exit status %x\n", s, status);
+    exit(1);
+  }
+
+  kill(pid2, SIGSTOP);
+  if(waitpid(0, &status, WUNTRACED) != pid2 || !WIFSTOPPED(status) || WSTOPSIG(status) != SIGSTOP){
+    printf("%s: wrong stop status %x\n", s, status);
+    exit(1);
+  }
+  if(waitpid(pid2, &status, WUNTRACED | WNOHANG) != 0){
+    printf("%s: stop reported twice\n", s);
+    exit(1);
+  }
+  kill(pid2, SIGCONT);
+  kill(pid2, SIGTERM);
+  if(waitpid(-1, &status, 0) != pid2 || !WIFSIGNALED(status) || WTERMSIG(status) != SIGTERM){
+    printf("%s: wrong kill status %x\n", s, status);
+    exit(1);
+  }
+
+  if(waitpid(-1, &status, 0) != -1 || errno != ECHILD){
+    printf("%s: waitpid without children succeeded\n", s);
+    exit(1);
+  }
+  if(waitpid(-1, &status, 0x100) != -1 || errno != EINVAL){
+    printf("%s: unknown option accepted\n", s);
+    exit(1);
+  }
+}
+
+// process groups and sessions, and signals to a whole group.
+void
+pgrptest(char *s)
+{
+  int pid, status, fd, fds[2];
+
+  if(getpgid(0) != getpgid(getpid()) || kill(0, 0) < 0){
+    printf("%s: cannot find own process group\n", s);
+    exit(1);
+  }
+  if(setpgid(0, 1000000) != -1 || errno != EPERM){
+    printf("%s: joined a nonexistent group\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    setpgid(0, 0);
+    for(;;)
+      sleep(1);
+  }
+  if(setpgid(pid, pid) < 0 || getpgid(pid) != pid){
+    printf("%s: setpgid failed\n", s);
+    exit(1);
+  }
+  if(kill(-pid, SIGKILL) < 0){
+    printf("%s: cannot kill the group\n", s);
+    exit(1);
+  }
+  if(waitpid(pid, &status, 0) != pid || !WIFSIGNALED(status) || WTERMSIG(status) != SIGKILL){
+    printf("%s: group member not killed\n", s);
+    exit(1);
+  }
+  if(kill(-pid, SIGKILL) != -1 || errno != ESRCH){
+    printf("%s: killed an empty group\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    if(setsid() != getpid() || getpgid(0) != getpid())
+      exit(1);
+    // a group leader cannot start another session
+    if(setsid() != -1 || errno != EPERM)
+      exit(1);
+    // the console is still controlled by the session of the shell
+    fd = open("/console", O_RDWR);
+    if(fd < 0)
+      exit(2);
+    if(tcsetpgrp(fd, getpid()) != -1 || errno != ENOTTY)
+      exit(3);
+    exit(0);
+  }
+  if(waitpid(pid, &status, 0) != pid || !WIFEXITED(status) || WEXITSTATUS(status) != 0){
+    printf("%s: setsid failed, status %d\n", s, status);
+    exit(1);
+  }
+
+  if(pipe(fds) < 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  if(tcsetpgrp(fds[0], getpgid(0)) != -1 || errno != ENOTTY){
+    printf("%s: pipe taken as a terminal\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+// can a job blocked in read be stopped like by ^Z, and continued
+// without its read failing?
+void
+jobstoptest(char *s)
+{
+  int pid, status, fds[2];
+  char c;
+
+  if(pipe(fds) < 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    setpgid(0, 0);
+    if(read(fds[0], &c, 1) != 1 || c != 'x')
+      exit(1);
+    exit(0);
+  }
+  setpgid(pid, pid);
+  sleep(2);
+
+  // as the console does for the foreground job
+  if(kill(-pid, SIGTSTP) < 0){
+    printf("%s: cannot signal the job\n", s);
+    exit(1);
+  }
+  if(waitpid(pid, &status, WUNTRACED) != pid || !WIFSTOPPED(status) || WSTOPSIG(status) != SIGTSTP){
+    printf("%s: job not stopped, status %x\n", s, status);
+    exit(1);
+  }
+  kill(-pid, SIGCONT);
+  if(write(fds[1], "x", 1) != 1){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  if(waitpid(pid, &status, 0) != pid || !WIFEXITED(status) || WEXITSTATUS(status) != 0){
+    printf("%s: read failed after continued, status %x\n", s, status);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+// is a nanosleep stopped and continued over and over restarted
+// with the time left, so that it still ends at its deadline?
+void
+sleepstoptest(char *s)
+{
+  struct timespec req;
+  int i, pid, status;
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    // five ticks
+    req.tv_sec = 0;
+    req.tv_nsec = 500000000;
+    if(nanosleep(&req, 0) < 0)
+      exit(1);
+    exit(0);
+  }
+
+  // each round takes more than a tick, so twenty are beyond the deadline,
+  // while each restart with the full time would outlast a round
+  for(i = 0; i < 20; i++){
+    sleep(1);
+    kill(pid, SIGSTOP);
+    if(waitpid(pid, &status, WUNTRACED) != pid){
+      printf("%s: waitpid failed\n", s);
+      exit(1);
+    }
+    if(WIFEXITED(status))
+      break;
+    kill(pid, SIGCONT);
+  }
+  if(i == 20){
+    printf("%s: nanosleep restarted with the full time\n", s);
+    kill(pid, SIGKILL);
+    wait(0);
+    exit(1);
+  }
+  if(WEXITSTATUS(status) != 0){
+    printf("%s: nanosleep failed after continued\n", s);
+    exit(1);
+  }
+}
+
+// exec a script by its "#!" interpreter line.
+void
+shebangtest(char *s)
+{
+  int fd, pid, xstatus, n;
+  char buf[32];
+  char *argv[] = { "shebang.sh", "x", 0 };
+  char *expect = "hi shebang.sh x\n";
+
+  fd = open("shebang.sh", O_CREATE|O_WRONLY);
+  if(fd < 0){
+    printf("%s: create failed\n", s);
+    exit(1);
+  }
+  write(fd, "#! echo  hi \n", 13);
+  close(fd);
+  chmod("shebang.sh", 0755);
+  fd = open("shebang.loop", O_CREATE|O_WRONLY);
+  if(fd < 0){
+    printf("%s: create failed\n", s);
+    exit(1);
+  }
+  // the line ends at the end of file without a newline
+  write(fd, "#!shebang.loop", 14);
+  close(fd);
+  chmod("shebang.loop", 0755);
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(1);
+    if(open("shebang.out", O_CREATE|O_WRONLY) != 1)
+      exit(1);
+    exec("shebang.sh", argv);
+    exit(2);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: exec script failed\n", s);
+    exit(1);
+  }
+  fd = open("shebang.out", O_RDONLY);
+  n = read(fd, buf, sizeof(buf) - 1);
+  close(fd);
+  if(n < 0 || (buf[n] = 0, strcmp(buf, expect) != 0)){
+    printf("%s: wrong script output\n", s);
+    exit(1);
+  }
+
+  if(exec("shebang.loop", argv) != -1 || errno != ELOOP){
+    printf("%s: endless script not rejected\n", s);
+    exit(1);
+  }
+  unlink("shebang.sh");
+  unlink("shebang.loop");
+  unlink("shebang.out");
+}
+
+// does execve pass the environment after argv,
+// and reject arguments over the total budget?
+void
+execvetest(char *s)
+{
+  int fd, pid, xstatus, n;
+  char buf[32];
+  char *argv[] = { "env", 0 };
+  char *envp[] = { "A=1", "B=two", 0 };
+  char *expect = "A=1\nB=two\n";
+  static char big[4096+1];
+  char *bigv[] = { "env", big, 0 };
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(1);
+    if(open("execve.out", O_CREATE|O_WRONLY) != 1)
+      exit(1);
+    execve("env", argv, envp);
+    exit(2);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: execve failed\n", s);
+    exit(1);
+  }
+  fd = open("execve.out", O_RDONLY);
+  n = read(fd, buf, sizeof(buf) - 1);
+  close(fd);
+  if(n < 0 || (buf[n] = 0, strcmp(buf, expect) != 0)){
+    printf("%s: wrong environment\n", s);
+    exit(1);
+  }
+  unlink("execve.out");
+
+  memset(big, 'x', sizeof(big) - 1);
+  if(execve("env", argv, bigv) != -1 || errno != E2BIG){
+    printf("%s: oversized environment not rejected\n", s);
+    exit(1);
+  }
+}
+
+// set both limits of a resource, or exit.
+void
+limit(char *s, int resource, unsigned long cur, unsigned long max)
+{
+  struct rlimit rl;
+
+  rl.rlim_cur = cur;
+  rl.rlim_max = max;
+  if(setrlimit(resource, &rl) < 0){
+    printf("%s: setrlimit %d failed\n", s, resource);
+    exit(1);
+  }
+}
+
+// are open files, children, memory, file size and cpu time limited,
+// and are the limits inherited?
+void
+rlimittest(char *s)
+{
+  struct rlimit rl;
+  struct sigaction sa;
+  char buf[150];
+  int fd, pid, xstatus;
+  char *a;
+
+  if(getrlimit(RLIMIT_NOFILE, &rl) < 0 || rl.rlim_cur != NOFILE){
+    printf("%s: wrong default RLIMIT_NOFILE\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid == 0){
+    // fd 0, 1 and 2 are open, so only one more
+    limit(s, RLIMIT_NOFILE, 4, 4);
+    if(open("README.md", O_RDONLY) != 3 || open("README.md", O_RDONLY) != -1 || errno != EMFILE){
+      printf("%s: RLIMIT_NOFILE not enforced\n", s);
+      exit(1);
+    }
+    rl.rlim_cur = rl.rlim_max = NOFILE;
+    if(setrlimit(RLIMIT_NOFILE, &rl) != -1 || errno != EPERM){
+      printf("%s: raised the hard limit\n", s);
+      exit(1);
+    }
+
+    limit(s, RLIMIT_NPROC, 1, 1);
+    pid = fork();
+    if(pid == 0){
+      // inherited by the child
+      if(getrlimit(RLIMIT_NPROC, &rl) < 0 || rl.rlim_cur != 1)
+        exit(1);
+      exit(0);
+    }
+    if(pid < 0 || fork() != -1 || errno != EAGAIN){
+      printf("%s: RLIMIT_NPROC not enforced\n", s);
+      exit(1);
+    }
+    wait(&xstatus);
+    if(xstatus != 0){
+      printf("%s: RLIMIT_NPROC not inherited\n", s);
+      exit(1);
+    }
+
+    a = sbrk(0);
+    limit(s, RLIMIT_AS, (uint64)a + 2*PGSIZE, RLIM_INFINITY);
+    if(sbrk(PGSIZE) != a || sbrk(2*PGSIZE) != (char*)-1 || errno != ENOMEM){
+      printf("%s: RLIMIT_AS not enforced\n", s);
+      exit(1);
+    }
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus != 0)
+    exit(1);
+
+  pid = fork();
+  if(pid == 0){
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_handler = SIG_IGN;
+    sigaction(SIGXFSZ, &sa, 0);
+    limit(s, RLIMIT_FSIZE, 100, RLIM_INFINITY);
+    fd = open("rlimit.out", O_CREATE|O_WRONLY);
+    if(fd < 0 || write(fd, buf, sizeof(buf)) != 100 || write(fd, buf, 1) != -1 || errno != EFBIG){
+      printf("%s: RLIMIT_FSIZE not enforced\n", s);
+      exit(1);
+    }
+    close(fd);
+    unlink("rlimit.out");
+
+    // spin until killed by SIGXCPU
+    limit(s, RLIMIT_CPU, 2, RLIM_INFINITY);
+    for(;;)
+      ;
+  }
+  if(waitpid(pid, &xstatus, 0) != pid || !WIFSIGNALED(xstatus) || WTERMSIG(xstatus) != SIGXCPU){
+    printf("%s: RLIMIT_CPU not enforced\n", s);
+    exit(1);
+  }
+}
+
+// are the owner and mode of files checked for a normal user,
+// while root passes?
+void
+permtest(char *s)
+{
+  struct stat st;
+  char *argv[] = { "own", 0 };
+  int fd, pid, xstatus;
+
+  if(getuid() != 0){
+    printf("%s: not run by root\n", s);
+    exit(1);
+  }
+  if(mkdir("permd") < 0 || chown("permd", 1, 1) < 0){
+    printf("%s: mkdir/chown failed\n", s);
+    exit(1);
+  }
+  fd = open("permd/secret", O_CREATE|O_WRONLY);
+  if(fd < 0 || chmod("permd/secret", 0) < 0){
+    printf("%s: create secret failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(stat("permd", &st) < 0 || st.uid != 1 || st.gid != 1 || st.mode != 0755){
+    printf("%s: wrong owner or mode\n", s);
+    exit(1);
+  }
+  // readable only by group 3, owned by another user
+  fd = open("permd/grp", O_CREATE|O_WRONLY);
+  if(fd < 0 || chown("permd/grp", 2, 3) < 0 || chmod("permd/grp", 0040) < 0){
+    printf("%s: create group file failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  pid = fork();
+  if(pid == 0){
+    if(setgid(3) < 0 || getgid() != 3){
+      printf("%s: setgid failed\n", s);
+      exit(1);
+    }
+    if(setuid(1) < 0 || getuid() != 1 || getgid() != 3){
+      printf("%s: setuid failed or changed the group\n", s);
+      exit(1);
+    }
+    if(setgid(1) != -1 || errno != EPERM){
+      printf("%s: changed group as a normal user\n", s);
+      exit(1);
+    }
+    fd = open("permd/grp", O_RDONLY);
+    if(fd < 0){
+      printf("%s: group bits not checked\n", s);
+      exit(1);
+    }
+    close(fd);
+    if(open("permd/grp", O_WRONLY) != -1 || errno != EACCES){
+      printf("%s: wrote with only group read\n", s);
+      exit(1);
+    }
+    if(setuid(0) != -1 || errno != EPERM){
+      printf("%s: became root again\n", s);
+      exit(1);
+    }
+    if(open("permd/secret", O_RDONLY) != -1 || errno != EACCES){
+      printf("%s: opened a mode 0 file\n", s);
+      exit(1);
+    }
+    if(chmod("permd/secret", 0777) != -1 || errno != EPERM){
+      printf("%s: chmod by non-owner\n", s);
+      exit(1);
+    }
+    if(open("permx", O_CREATE|O_WRONLY) != -1 || errno != EACCES){
+      printf("%s: created in a read-only directory\n", s);
+      exit(1);
+    }
+    fd = open("permd/own", O_CREATE|O_WRONLY);
+    if(fd < 0){
+      printf("%s: create in own directory failed\n", s);
+      exit(1);
+    }
+    write(fd, "#!echo\n", 7);
+    close(fd);
+    if(exec("permd/own", argv) != -1 || errno != EACCES){
+      printf("%s: exec of a non-executable file\n", s);
+      exit(1);
+    }
+    if(chmod("permd/own", 0) < 0 || open("permd/own", O_RDONLY) != -1 || errno != EACCES){
+      printf("%s: owner bits not checked\n", s);
+      exit(1);
+    }
+    if(unlink("permd/own") < 0){
+      printf("%s: unlink in own directory failed\n", s);
+      exit(1);
+    }
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus != 0)
+    exit(1);
+
+  fd = open("permd/secret", O_RDWR);
+  if(fd < 0){
+    printf("%s: root cannot open a mode 0 file\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("permd/secret");
+  unlink("permd/grp");
+  unlink("permd");
+}
+
 void
 sbrkbasic(char *s)
 {
@@ -2263,7 +4136,7 @@ sbrkfail(char *s)
   for(i = 0; i < sizeof(pids)/sizeof(pids[0]); i++){
     if(pids[i] == -1)
       continue;
-    kill(pids[i]);
+    kill(pids[i], SIGKILL);
     wait(0);
   }
   if(c == (char*)0xffffffffffffffffL){
@@ -2466,30 +4339,150 @@ rand()
   return randstate;
 }
 
-// check that there's an invalid page beneath
-// the user stack, to catch stack overflow.
+// recurse n times with a half-page frame,
+// which is small enough not to jump over the guard gap.
+int
+stackdeep(int n)
+{
+  volatile char frame[PGSIZE/2];
+
+  frame[0] = n;
+  if(n == 0)
+    return frame[0];
+  return stackdeep(n - 1) + frame[0];
+}
+
+// check that the user stack grows on demand,
+// and that there's a guard gap beneath it,
+// to catch stack overflow by SIGSEGV.
 void
 stacktest(char *s)
 {
   int pid;
   int xstatus;
-  
+
+  pid = fork();
+  if(pid == 0) {
+    stackdeep(MAXSTACK/PGSIZE);
+    exit(0);
+  } else if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: stacktest: stack did not grow\n", s);
+    exit(1);
+  }
+
   pid = fork();
   if(pid == 0) {
-    char *sp = (char *) r_sp();
-    sp -= PGSIZE;
-    // the *sp should cause a trap.
-    printf("%s: stacktest: read below stack %p\n", s, *sp);
+    // the overflow should cause a trap.
+    stackdeep(MAXSTACK/PGSIZE*4);
+    printf("%s: stacktest: overflowed the stack\n", s);
+    exit(1);
+  } else if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(waitpid(pid, &xstatus, 0) != pid || !WIFSIGNALED(xstatus) || WTERMSIG(xstatus) != SIGSEGV){
+    printf("%s: stacktest: overflow not killed by SIGSEGV\n", s);
     exit(1);
+  }
+
+  // a lowered RLIMIT_STACK still lets the stack grow below it,
+  // but not beyond, and is taken by exec for the new stack.
+  pid = fork();
+  if(pid == 0) {
+    limit(s, RLIMIT_STACK, 16*PGSIZE, MAXSTACK);
+    stackdeep(8);
+    exit(0);
   } else if(pid < 0){
     printf("%s: fork failed\n", s);
     exit(1);
   }
   wait(&xstatus);
-  if(xstatus == -1)  // kernel killed child?
+  if(xstatus != 0){
+    printf("%s: stacktest: stack did not grow below RLIMIT_STACK\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid == 0) {
+    limit(s, RLIMIT_STACK, 16*PGSIZE, MAXSTACK);
+    stackdeep(MAXSTACK/PGSIZE);
+    printf("%s: stacktest: grew beyond RLIMIT_STACK\n", s);
+    exit(1);
+  } else if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(waitpid(pid, &xstatus, 0) != pid || !WIFSIGNALED(xstatus) || WTERMSIG(xstatus) != SIGSEGV){
+    printf("%s: stacktest: RLIMIT_STACK not enforced\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid == 0) {
+    char *argv[] = { "echo", 0 };
+    limit(s, RLIMIT_STACK, 0, MAXSTACK);
+    if(exec("echo", argv) != -1 || errno != E2BIG)
+      exit(1);
     exit(0);
-  else
-    exit(xstatus);
+  } else if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  wait(&xstatus);
+  if(xstatus != 0){
+    printf("%s: stacktest: exec ignored RLIMIT_STACK\n", s);
+    exit(1);
+  }
+}
+
+// a page of the bss, to try executing data
+char wxdata[2*PGSIZE];
+
+// is the program text not writable, and are the data and heap
+// not executable, so that each of them is killed by SIGSEGV?
+void
+wxtest(char *s)
+{
+  char *heap;
+  uint *code;
+  int i, pid, xstatus;
+
+  heap = sbrk(PGSIZE);
+  if(heap == (char*)0xffffffffffffffffL){
+    printf("%s: sbrk failed\n", s);
+    exit(1);
+  }
+
+  for(i = 0; i < 3; i++){
+    pid = fork();
+    if(pid < 0){
+      printf("%s: fork failed\n", s);
+      exit(1);
+    }
+    if(pid == 0){
+      if(i == 0){
+        // store into the text of this function
+        *(volatile uint*)wxtest = 0;
+        printf("%s: wrote to program text\n", s);
+      } else {
+        // a "ret" in a data or heap page
+        code = (uint*)(i == 1 ? PGROUNDUP((uint64)wxdata) : (uint64)heap);
+        code[0] = 0x00008067;
+        ((void (*)(void))code)();
+        printf("%s: executed %s\n", s, i == 1 ? "data" : "heap");
+      }
+      exit(1);
+    }
+    if(waitpid(pid, &xstatus, 0) != pid || !WIFSIGNALED(xstatus) || WTERMSIG(xstatus) != SIGSEGV){
+      printf("%s: case %d not killed by SIGSEGV, status %d\n", s, i, xstatus);
+      exit(1);
+    }
+  }
 }
 
 // regression test. copyin(), copyout(), and copyinstr() used to cast
@@ -2801,12 +4794,39 @@ main(int argc, char *argv[])
     {bigwrite, "bigwrite"},
     {bsstest, "bsstest"},
     {sbrkbasic, "sbrkbasic"},
+    {lazyalloc, "lazyalloc"},
+    {mmaptest, "mmaptest"},
+    {threadtest, "threadtest"},
+    {threadfilestest, "threadfilestest"},
+    {threadexectest, "threadexectest"},
+    {sigtest, "sigtest"},
+    {sigintrtest, "sigintrtest"},
+    {alarmtest, "alarmtest"},
+    {clocktest, "clocktest"},
+    {rtctest, "rtctest"},
+    {futextest, "futextest"},
+    {fcntltest, "fcntltest"},
+    {polltest, "polltest"},
+    {fifotest, "fifotest"},
+    {sockettest, "sockettest"},
+    {nicetest, "nicetest"},
+    {affinitytest, "affinitytest"},
+    {errnotest, "errnotest"},
+    {waitpidtest, "waitpidtest"},
+    {pgrptest, "pgrptest"},
+    {jobstoptest, "jobstoptest"},
+    {sleepstoptest, "sleepstoptest"},
+    {shebangtest, "shebangtest"},
+    {execvetest, "execvetest"},
+    {rlimittest, "rlimittest"},
+    {permtest, "permtest"},
     {sbrkmuch, "sbrkmuch"},
     {kernmem, "kernmem"},
     {sbrkfail, "sbrkfail"},
     {sbrkarg, "sbrkarg"},
     {validatetest, "validatetest"},
     {stacktest, "stacktest"},
+    {wxtest, "wxtest"},
     {opentest, "opentest"},
     {writetest, "writetest"},
     {writebig, "writebig"},
@@ -2825,6 +4845,7 @@ main(int argc, char *argv[])
     {dirfile, "dirfile"},
     {iref, "iref"},
     {forktest, "forktest"},
+    {cowfork, "cowfork"},
     {bigdir, "bigdir"}, // slow
     { 0, 0},
   };
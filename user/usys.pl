@@ -5,8 +5,26 @@
 print "# generated by usys.pl - do not edit\n";
 
 print "#include \"include/syscall.h\"\n";
+print "#include \"include/errno.h\"\n";
 
+# the stub is named after the second argument if given,
+# so that ulib.c can wrap the syscall.
+# a negative errno returned by the kernel is stored in errno,
+# and the stub returns -1 instead, see __syscall_error in ulib.c
 sub entry {
+    my $name = shift;
+    my $label = shift || $name;
+    print ".global $label\n";
+    print "${label}:\n";
+    print " li a7, SYS_${name}\n";
+    print " ecall\n";
+    print " li t0, -MAXERRNO-1\n";
+    print " bgtu a0, t0, __syscall_error\n";
+    print " ret\n";
+}
+
+# the stub returns whatever the kernel returns, without touching errno
+sub raw_entry {
     my $name = shift;
     print ".global $name\n";
     print "${name}:\n";
@@ -36,3 +54,43 @@ entry("getpid");
 entry("sbrk");
 entry("sleep");
 entry("uptime");
+entry("mmap");
+entry("munmap");
+entry("clone");
+entry("join");
+entry("sigaction", "__sigaction");
+entry("sigprocmask");
+# it returns the restored a0 of the interrupted context
+raw_entry("sigreturn");
+entry("setpriority");
+entry("sched_setaffinity");
+entry("trace");
+entry("waitpid");
+entry("setpgid");
+entry("getpgid");
+entry("setsid");
+entry("tcsetpgrp");
+entry("tcgetpgrp");
+entry("execve");
+entry("getrlimit");
+entry("setrlimit");
+entry("chmod");
+entry("chown");
+entry("setuid");
+entry("getuid");
+entry("sigalarm");
+entry("clock_gettime");
+entry("nanosleep");
+entry("gettimeofday");
+entry("futex");
+entry("fcntl");
+entry("poll");
+entry("mkfifo");
+entry("socket");
+entry("bind");
+entry("listen");
+entry("accept");
+entry("connect");
+entry("socketpair");
+entry("setgid");
+entry("getgid");